@@ -1,23 +1,74 @@
-use crate::lexer::lexer::Token;
+use crate::lexer::lexer::{Span, Token};
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Statement {
     Let(Let),
+    LetPattern(LetPattern),
     Return(Return),
     Expression(Expression),
+    While(While),
+    For(For),
+    Break(Span),
+    Continue(Span),
 }
+
+impl Statement {
+    pub fn span(&self) -> Span {
+        match self {
+            Statement::Let(l) => l.span,
+            Statement::LetPattern(l) => l.span,
+            Statement::Return(r) => r.span,
+            Statement::Expression(e) => e.span(),
+            Statement::While(w) => w.span,
+            Statement::For(f) => f.span,
+            Statement::Break(span) | Statement::Continue(span) => *span,
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum Expression {
-    Identifier(Token),
-    Integer(Token),
-    String(String),
-    Boolean(Token),
+    Identifier(Token, Span),
+    Integer(Token, Span),
+    Float(Token, Span),
+    String(String, Span),
+    /// The parts of an interpolated string in order, text parts are `Expression::String`.
+    Interpolated(Vec<Expression>, Span),
+    Boolean(Token, Span),
     Prefix(Box<PrefixExpression>),
     Infix(Box<InfixExpression>),
     If(Box<IfExpression>),
     Fn(Box<FnExpression>),
     Call(Box<CallExpression>),
-    Array(Vec<Expression>),
+    Array(Vec<Expression>, Span),
+    Hash(Vec<(Expression, Expression)>, Span),
+    Index(Box<IndexExpression>),
+    Assign(Box<AssignExpression>),
+    Match(Box<MatchExpression>),
+}
+
+impl Expression {
+    /// Where the expression starts in the source, or for operators, where the operator is.
+    pub fn span(&self) -> Span {
+        match self {
+            Expression::Identifier(_, span)
+            | Expression::Integer(_, span)
+            | Expression::Float(_, span)
+            | Expression::String(_, span)
+            | Expression::Interpolated(_, span)
+            | Expression::Boolean(_, span)
+            | Expression::Array(_, span)
+            | Expression::Hash(_, span) => *span,
+            Expression::Prefix(p) => p.span,
+            Expression::Infix(i) => i.span,
+            Expression::If(i) => i.span,
+            Expression::Fn(f) => f.span,
+            Expression::Call(c) => c.span,
+            Expression::Index(i) => i.span,
+            Expression::Assign(a) => a.span,
+            Expression::Match(m) => m.span,
+        }
+    }
 }
 
 #[derive(Debug, PartialEq)]
@@ -25,6 +76,7 @@ pub struct Program {
     pub statements: Vec<Statement>,
 }
 
+#[allow(clippy::needless_return)]
 impl Program {
     pub fn new(statements: Vec<Statement>) -> Program {
         return Program { statements };
@@ -36,11 +88,54 @@ pub struct Let {
     pub token: Token,
     pub name: Identifier,
     pub value: Expression,
+    /// Declared with `const`, so the name cannot be assigned to or declared again.
+    pub constant: bool,
+    pub span: Span,
 }
 
 impl Let {
-    pub fn new(token: Token, name: Identifier, value: Expression) -> Let {
-        Let { token, name, value }
+    pub fn new(
+        token: Token,
+        name: Identifier,
+        value: Expression,
+        constant: bool,
+        span: Span,
+    ) -> Let {
+        Let {
+            token,
+            name,
+            value,
+            constant,
+            span,
+        }
+    }
+}
+
+/// `let [a, ...rest] = value;` or `let {name} = value;`, binding the parts of the value.
+#[derive(Debug, PartialEq, Clone)]
+pub struct LetPattern {
+    pub token: Token,
+    pub pattern: Pattern,
+    pub value: Expression,
+    pub constant: bool,
+    pub span: Span,
+}
+
+impl LetPattern {
+    pub fn new(
+        token: Token,
+        pattern: Pattern,
+        value: Expression,
+        constant: bool,
+        span: Span,
+    ) -> LetPattern {
+        LetPattern {
+            token,
+            pattern,
+            value,
+            constant,
+            span,
+        }
     }
 }
 
@@ -48,12 +143,61 @@ impl Let {
 pub struct Return {
     pub token: Token,
     pub return_value: Expression,
+    pub span: Span,
 }
 impl Return {
-    pub fn new(token: Token, return_value: Expression) -> Return {
+    pub fn new(token: Token, return_value: Expression, span: Span) -> Return {
         Return {
             token,
             return_value,
+            span,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct While {
+    pub token: Token,
+    pub condition: Expression,
+    pub body: BlockStatement,
+    pub span: Span,
+}
+
+impl While {
+    pub fn new(token: Token, condition: Expression, body: BlockStatement, span: Span) -> Self {
+        While {
+            token,
+            condition,
+            body,
+            span,
+        }
+    }
+}
+
+/// `for (variable in iterable) { body }`
+#[derive(Debug, PartialEq, Clone)]
+pub struct For {
+    pub token: Token,
+    pub variable: Identifier,
+    pub iterable: Expression,
+    pub body: BlockStatement,
+    pub span: Span,
+}
+
+impl For {
+    pub fn new(
+        token: Token,
+        variable: Identifier,
+        iterable: Expression,
+        body: BlockStatement,
+        span: Span,
+    ) -> Self {
+        For {
+            token,
+            variable,
+            iterable,
+            body,
+            span,
         }
     }
 }
@@ -61,10 +205,11 @@ impl Return {
 #[derive(Debug, PartialEq, Clone)]
 pub struct Identifier {
     pub token: Token,
+    pub span: Span,
 }
 impl Identifier {
-    pub fn new(token: Token) -> Identifier {
-        Identifier { token }
+    pub fn new(token: Token, span: Span) -> Identifier {
+        Identifier { token, span }
     }
 }
 
@@ -72,11 +217,12 @@ impl Identifier {
 pub struct PrefixExpression {
     pub token: Token,
     pub right: Expression,
+    pub span: Span,
 }
 
 impl PrefixExpression {
-    pub fn new(token: Token, right: Expression) -> Self {
-        PrefixExpression { token, right }
+    pub fn new(token: Token, right: Expression, span: Span) -> Self {
+        PrefixExpression { token, right, span }
     }
 }
 
@@ -85,11 +231,17 @@ pub struct InfixExpression {
     pub left: Expression,
     pub token: Token,
     pub right: Expression,
+    pub span: Span,
 }
 
 impl InfixExpression {
-    pub fn new(left: Expression, token: Token, right: Expression) -> Self {
-        InfixExpression { left, token, right }
+    pub fn new(left: Expression, token: Token, right: Expression, span: Span) -> Self {
+        InfixExpression {
+            left,
+            token,
+            right,
+            span,
+        }
     }
 }
 
@@ -99,6 +251,7 @@ pub struct IfExpression {
     pub condition: Expression,
     pub consequence: BlockStatement,
     pub alternative: Option<BlockStatement>,
+    pub span: Span,
 }
 
 impl IfExpression {
@@ -107,12 +260,14 @@ impl IfExpression {
         condition: Expression,
         consequence: BlockStatement,
         alternative: Option<BlockStatement>,
+        span: Span,
     ) -> Self {
         IfExpression {
             token,
             condition,
             consequence,
             alternative,
+            span,
         }
     }
 }
@@ -121,27 +276,61 @@ impl IfExpression {
 pub struct BlockStatement {
     pub token: Token,
     pub statements: Vec<Statement>,
+    pub span: Span,
 }
 
 impl BlockStatement {
-    pub fn new(token: Token, statements: Vec<Statement>) -> Self {
-        BlockStatement { token, statements }
+    pub fn new(token: Token, statements: Vec<Statement>, span: Span) -> Self {
+        BlockStatement {
+            token,
+            statements,
+            span,
+        }
     }
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct FnExpression {
     pub token: Token,
-    pub parameters: Option<Vec<Identifier>>,
+    /// The name of a function declared with `fn name(params) { body }`.
+    pub name: Option<Identifier>,
+    pub parameters: Option<Vec<Parameter>>,
     pub body: BlockStatement,
+    pub span: Span,
 }
 
 impl FnExpression {
-    pub fn new(token: Token, parameters: Option<Vec<Identifier>>, body: BlockStatement) -> Self {
+    pub fn new(
+        token: Token,
+        name: Option<Identifier>,
+        parameters: Option<Vec<Parameter>>,
+        body: BlockStatement,
+        span: Span,
+    ) -> Self {
         FnExpression {
             token,
+            name,
             parameters,
             body,
+            span,
+        }
+    }
+}
+
+/// A function parameter: `name`, `name = default` or the rest parameter `...name`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Parameter {
+    pub name: Identifier,
+    pub default: Option<Expression>,
+    pub rest: bool,
+}
+
+impl Parameter {
+    pub fn new(name: Identifier, default: Option<Expression>, rest: bool) -> Self {
+        Parameter {
+            name,
+            default,
+            rest,
         }
     }
 }
@@ -151,14 +340,167 @@ pub struct CallExpression {
     pub token: Token,
     pub function: Expression,
     pub arguments: Option<Vec<Expression>>,
+    /// `name: value` arguments, which follow the positional ones.
+    pub keyword_arguments: Vec<(Identifier, Expression)>,
+    pub span: Span,
 }
 
 impl CallExpression {
-    pub fn new(token: Token, function: Expression, arguments: Option<Vec<Expression>>) -> Self {
+    pub fn new(
+        token: Token,
+        function: Expression,
+        arguments: Option<Vec<Expression>>,
+        keyword_arguments: Vec<(Identifier, Expression)>,
+        span: Span,
+    ) -> Self {
         CallExpression {
             token,
             function,
             arguments,
+            keyword_arguments,
+            span,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct IndexExpression {
+    pub token: Token,
+    pub left: Expression,
+    pub index: Expression,
+    pub span: Span,
+}
+
+impl IndexExpression {
+    pub fn new(token: Token, left: Expression, index: Expression, span: Span) -> Self {
+        IndexExpression {
+            token,
+            left,
+            index,
+            span,
+        }
+    }
+}
+
+/// `name = value`, or a compound assignment like `name += value`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct AssignExpression {
+    pub token: Token,
+    pub name: Identifier,
+    pub value: Expression,
+    pub span: Span,
+}
+
+impl AssignExpression {
+    pub fn new(token: Token, name: Identifier, value: Expression, span: Span) -> Self {
+        AssignExpression {
+            token,
+            name,
+            value,
+            span,
+        }
+    }
+
+    /// The operator a compound assignment applies to the current value, `Plus` for `+=`.
+    pub fn operator(&self) -> Option<Token> {
+        match self.token {
+            Token::PlusAssign => Some(Token::Plus),
+            Token::MinusAssign => Some(Token::Minus),
+            Token::AsteriskAssign => Some(Token::Asterisk),
+            Token::SlashAssign => Some(Token::Slash),
+            Token::PercentAssign => Some(Token::Percent),
+            _ => None,
+        }
+    }
+}
+
+/// `match (value) { pattern => body, pattern if guard => body }`
+#[derive(Debug, PartialEq, Clone)]
+pub struct MatchExpression {
+    pub token: Token,
+    pub value: Expression,
+    pub arms: Vec<MatchArm>,
+    pub span: Span,
+}
+
+impl MatchExpression {
+    pub fn new(token: Token, value: Expression, arms: Vec<MatchArm>, span: Span) -> Self {
+        MatchExpression {
+            token,
+            value,
+            arms,
+            span,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct MatchArm {
+    pub pattern: Pattern,
+    pub guard: Option<Expression>,
+    /// A body written as a single expression is kept as a block holding just that expression.
+    pub body: BlockStatement,
+    pub span: Span,
+}
+
+impl MatchArm {
+    pub fn new(
+        pattern: Pattern,
+        guard: Option<Expression>,
+        body: BlockStatement,
+        span: Span,
+    ) -> Self {
+        MatchArm {
+            pattern,
+            guard,
+            body,
+            span,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Pattern {
+    /// `_`, matches anything.
+    Wildcard(Span),
+    /// Matches anything and binds it to the name.
+    Binding(Identifier),
+    /// A number, string or boolean literal, matches values equal to it.
+    Literal(Expression),
+    /// `[a, b, ...rest]`, matches arrays with exactly as many items as there are patterns, or
+    /// at least as many when there is a rest binding for the remaining items.
+    Array(Vec<Pattern>, Option<Identifier>, Span),
+    /// `{"key": pattern, name}`, matches hashes having every key. A bare name is shorthand
+    /// for binding the value of the key with that name.
+    Hash(Vec<(Expression, Pattern)>, Span),
+}
+
+impl Pattern {
+    pub fn span(&self) -> Span {
+        match self {
+            Pattern::Wildcard(span) | Pattern::Array(_, _, span) | Pattern::Hash(_, span) => *span,
+            Pattern::Binding(name) => name.span,
+            Pattern::Literal(literal) => literal.span(),
+        }
+    }
+
+    /// The names the pattern binds, in source order.
+    pub fn bindings(&self) -> Vec<&Identifier> {
+        match self {
+            Pattern::Wildcard(_) | Pattern::Literal(_) => vec![],
+            Pattern::Binding(name) => vec![name],
+            Pattern::Array(items, rest, _) => items
+                .iter()
+                .flat_map(Pattern::bindings)
+                .chain(
+                    rest.iter()
+                        .filter(|rest| rest.token != Token::Ident("_".into())),
+                )
+                .collect(),
+            Pattern::Hash(entries, _) => entries
+                .iter()
+                .flat_map(|(_, pattern)| pattern.bindings())
+                .collect(),
         }
     }
 }
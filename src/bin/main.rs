@@ -1,5 +1,50 @@
-use interp_rust::repl::repl::start;
+use interp_rust::{
+    object::object::EVAL_STACK_SIZE,
+    repl::repl::{run_file, start, Engine},
+};
+
+const USAGE: &str = "Usage: main [--engine eval|vm] [file]";
 
 fn main() {
-    start();
+    let mut engine = Engine::Eval;
+    let mut file: Option<String> = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--engine" => match args.next().map(|e| e.parse()) {
+                Some(Ok(e)) => engine = e,
+                Some(Err(e)) => exit_with(&e.to_string()),
+                None => exit_with(USAGE),
+            },
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            _ if file.is_none() => file = Some(arg),
+            _ => exit_with(USAGE),
+        }
+    }
+
+    // the tree walker uses the native stack for every nested call, give it enough to reach
+    // its recursion limit
+    let interpreter = std::thread::Builder::new()
+        .stack_size(EVAL_STACK_SIZE)
+        .spawn(move || match file {
+            Some(path) => {
+                if let Err(e) = run_file(&path, engine) {
+                    exit_with(&e.to_string());
+                }
+            }
+            None => start(engine),
+        })
+        .unwrap_or_else(|e| exit_with(&e.to_string()));
+    if interpreter.join().is_err() {
+        std::process::exit(1);
+    }
+}
+
+fn exit_with(message: &str) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(1);
 }
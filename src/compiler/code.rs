@@ -0,0 +1,357 @@
+use std::fmt::Display;
+
+use anyhow::{anyhow, Result};
+
+pub type Instructions = Vec<u8>;
+
+#[repr(u8)]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Opcode {
+    Constant,
+    Pop,
+
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Equal,
+    NotEqual,
+    GreaterThan,
+    LessThan,
+    GreaterEqual,
+    LessEqual,
+    Mod,
+    Pow,
+    BitAnd,
+    BitOr,
+    BitXor,
+    ShiftLeft,
+    ShiftRight,
+    Minus,
+    Bang,
+
+    True,
+    False,
+    Null,
+
+    Jump,
+    JumpNotTruthy,
+    Iterate,
+    IterNext,
+
+    GetGlobal,
+    SetGlobal,
+    AssignGlobal,
+    GetLocal,
+    SetLocal,
+    GetFree,
+    SetFree,
+
+    Array,
+    Hash,
+    Index,
+    Interpolate,
+
+    Call,
+    /// Calls with the operand's arguments, the last of them given by the names in the array
+    /// on top of the stack.
+    CallKeywords,
+    /// Pushes whether the operand's parameter was left without an argument, to give it its
+    /// default.
+    ArgumentMissing,
+    ReturnValue,
+    Return,
+    Closure,
+
+    /// Pushes whether a value matches a literal pattern, see `Object::matches_literal`.
+    MatchLiteral,
+    /// Pushes whether a value is an array with as many items as the pattern, or at least as
+    /// many when the second operand says the pattern has a rest.
+    MatchArray,
+    /// Pushes whether a value is a hash.
+    MatchHash,
+    /// Pushes whether a hash has a key.
+    MatchKey,
+    /// Replaces an array with its items from the operand on.
+    SliceFrom,
+    /// Fails with the value no match arm matched.
+    NoMatch,
+    /// Fails a destructuring let of the value below the top because the top is not an array
+    /// of the operand's items, or at least that many when the second operand is 1.
+    MismatchArray,
+    /// Fails a destructuring let of the value below the top because the top is not a hash.
+    MismatchHash,
+    /// Fails a destructuring let of the value below the top because it has no such key.
+    MismatchKey,
+    /// Fails a destructuring let of the value below the top two because the top is not
+    /// equal to the literal below it.
+    MismatchLiteral,
+}
+
+const OPCODES: [Opcode; 55] = [
+    Opcode::Constant,
+    Opcode::Pop,
+    Opcode::Add,
+    Opcode::Sub,
+    Opcode::Mul,
+    Opcode::Div,
+    Opcode::Equal,
+    Opcode::NotEqual,
+    Opcode::GreaterThan,
+    Opcode::LessThan,
+    Opcode::GreaterEqual,
+    Opcode::LessEqual,
+    Opcode::Mod,
+    Opcode::Pow,
+    Opcode::BitAnd,
+    Opcode::BitOr,
+    Opcode::BitXor,
+    Opcode::ShiftLeft,
+    Opcode::ShiftRight,
+    Opcode::Minus,
+    Opcode::Bang,
+    Opcode::True,
+    Opcode::False,
+    Opcode::Null,
+    Opcode::Jump,
+    Opcode::JumpNotTruthy,
+    Opcode::Iterate,
+    Opcode::IterNext,
+    Opcode::GetGlobal,
+    Opcode::SetGlobal,
+    Opcode::AssignGlobal,
+    Opcode::GetLocal,
+    Opcode::SetLocal,
+    Opcode::GetFree,
+    Opcode::SetFree,
+    Opcode::Array,
+    Opcode::Hash,
+    Opcode::Index,
+    Opcode::Interpolate,
+    Opcode::Call,
+    Opcode::CallKeywords,
+    Opcode::ArgumentMissing,
+    Opcode::ReturnValue,
+    Opcode::Return,
+    Opcode::Closure,
+    Opcode::MatchLiteral,
+    Opcode::MatchArray,
+    Opcode::MatchHash,
+    Opcode::MatchKey,
+    Opcode::SliceFrom,
+    Opcode::NoMatch,
+    Opcode::MismatchArray,
+    Opcode::MismatchHash,
+    Opcode::MismatchKey,
+    Opcode::MismatchLiteral,
+];
+
+impl TryFrom<u8> for Opcode {
+    type Error = anyhow::Error;
+
+    fn try_from(byte: u8) -> Result<Self> {
+        OPCODES
+            .get(byte as usize)
+            .copied()
+            .ok_or_else(|| anyhow!("Unknown opcode: {}", byte))
+    }
+}
+
+impl Display for Opcode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Op{:?}", self)
+    }
+}
+
+impl Opcode {
+    /// The width in bytes of each operand that follows the opcode.
+    pub fn operand_widths(&self) -> &'static [usize] {
+        match self {
+            Opcode::Constant
+            | Opcode::Jump
+            | Opcode::JumpNotTruthy
+            | Opcode::IterNext
+            | Opcode::GetGlobal
+            | Opcode::SetGlobal
+            | Opcode::AssignGlobal
+            | Opcode::Array
+            | Opcode::Hash
+            | Opcode::Interpolate
+            | Opcode::Closure
+            | Opcode::SliceFrom => &[2],
+            Opcode::MatchArray | Opcode::MismatchArray => &[2, 1],
+            Opcode::GetLocal
+            | Opcode::SetLocal
+            | Opcode::GetFree
+            | Opcode::SetFree
+            | Opcode::Call
+            | Opcode::CallKeywords
+            | Opcode::ArgumentMissing => &[1],
+            _ => &[],
+        }
+    }
+}
+
+/// Checks that each operand of `op` fits in its width, naming the limit that was hit.
+pub fn check_operands(op: Opcode, operands: &[usize]) -> Result<()> {
+    for (operand, width) in operands.iter().zip(op.operand_widths()) {
+        let max = (1 << (8 * width)) - 1;
+        if *operand <= max {
+            continue;
+        }
+        // operands are either counts or indexes into something that can hold one more item
+        let (what, limit) = match op {
+            Opcode::Constant | Opcode::Closure => ("constants", max + 1),
+            Opcode::Jump | Opcode::JumpNotTruthy | Opcode::IterNext => {
+                ("bytes of instructions in one function", max + 1)
+            }
+            Opcode::GetGlobal | Opcode::SetGlobal | Opcode::AssignGlobal => {
+                ("global bindings", max + 1)
+            }
+            Opcode::GetLocal | Opcode::SetLocal => ("local bindings in one function", max + 1),
+            Opcode::GetFree | Opcode::SetFree => ("captured variables in one function", max + 1),
+            Opcode::Call | Opcode::CallKeywords => ("arguments in a call", max),
+            Opcode::Array => ("items in an array literal", max),
+            Opcode::Hash => ("pairs in a hash literal", max),
+            Opcode::Interpolate => ("parts in an interpolated string", max),
+            Opcode::MatchArray | Opcode::MismatchArray | Opcode::SliceFrom => {
+                ("items in an array pattern", max)
+            }
+            _ => ("operands", max),
+        };
+        return Err(anyhow!("Too many {}, the limit is {}", what, limit));
+    }
+    Ok(())
+}
+
+/// Encodes an opcode and its operands as big-endian bytes. The operands must fit in their
+/// widths, see `check_operands`.
+pub fn make(op: Opcode, operands: &[usize]) -> Instructions {
+    let widths = op.operand_widths();
+    let mut instruction: Instructions = vec![op as u8];
+    for (operand, width) in operands.iter().zip(widths) {
+        match width {
+            2 => instruction.extend_from_slice(&(*operand as u16).to_be_bytes()),
+            1 => instruction.push(*operand as u8),
+            _ => unreachable!("operand width {} is not supported", width),
+        }
+    }
+    instruction
+}
+
+/// Decodes the operands of `op` from the start of `ins`, returning them along with the
+/// number of bytes read.
+pub fn read_operands(op: Opcode, ins: &[u8]) -> (Vec<usize>, usize) {
+    let mut operands: Vec<usize> = vec![];
+    let mut offset = 0;
+    for width in op.operand_widths() {
+        match width {
+            2 => operands.push(read_u16(&ins[offset..])),
+            1 => operands.push(ins[offset] as usize),
+            _ => unreachable!("operand width {} is not supported", width),
+        }
+        offset += width;
+    }
+    (operands, offset)
+}
+
+pub fn read_u16(ins: &[u8]) -> usize {
+    u16::from_be_bytes([ins[0], ins[1]]) as usize
+}
+
+/// Renders instructions one per line, prefixed with their offset.
+pub fn disassemble(ins: &[u8]) -> String {
+    let mut out = String::new();
+    let mut i = 0;
+    while i < ins.len() {
+        match Opcode::try_from(ins[i]) {
+            Ok(op) => {
+                let (operands, read) = read_operands(op, &ins[i + 1..]);
+                let operands: Vec<String> = operands.iter().map(|o| o.to_string()).collect();
+                out.push_str(&format!("{:04} {} {}\n", i, op, operands.join(" ")));
+                i += 1 + read;
+            }
+            Err(e) => {
+                out.push_str(&format!("ERROR: {}\n", e));
+                i += 1;
+            }
+        }
+    }
+    out.lines()
+        .map(|l| l.trim_end())
+        .collect::<Vec<&str>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{disassemble, make, read_operands, Opcode, OPCODES};
+
+    #[test]
+    fn test_make() {
+        struct Test {
+            op: Opcode,
+            operands: Vec<usize>,
+            expected: Vec<u8>,
+        }
+        let tests = vec![
+            Test {
+                op: Opcode::Constant,
+                operands: vec![65534],
+                expected: vec![Opcode::Constant as u8, 255, 254],
+            },
+            Test {
+                op: Opcode::Add,
+                operands: vec![],
+                expected: vec![Opcode::Add as u8],
+            },
+            Test {
+                op: Opcode::GetLocal,
+                operands: vec![255],
+                expected: vec![Opcode::GetLocal as u8, 255],
+            },
+        ];
+
+        for test in tests.into_iter() {
+            assert_eq!(make(test.op, &test.operands), test.expected);
+        }
+    }
+
+    #[test]
+    fn test_read_operands() {
+        let tests = vec![
+            (Opcode::Constant, vec![65535], 2),
+            (Opcode::GetLocal, vec![255], 1),
+            (Opcode::Pop, vec![], 0),
+        ];
+
+        for (op, operands, bytes_read) in tests.into_iter() {
+            let instruction = make(op, &operands);
+            let (read, n) = read_operands(op, &instruction[1..]);
+            assert_eq!(n, bytes_read);
+            assert_eq!(read, operands);
+        }
+    }
+
+    #[test]
+    fn test_opcode_round_trip() {
+        for (i, op) in OPCODES.iter().enumerate() {
+            assert_eq!(*op as usize, i);
+            assert_eq!(Opcode::try_from(i as u8).unwrap(), *op);
+        }
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let instructions: Vec<u8> = [
+            make(Opcode::Add, &[]),
+            make(Opcode::GetLocal, &[1]),
+            make(Opcode::Constant, &[2]),
+            make(Opcode::Constant, &[65535]),
+        ]
+        .concat();
+
+        let expected = "0000 OpAdd\n0001 OpGetLocal 1\n0003 OpConstant 2\n0006 OpConstant 65535";
+        assert_eq!(disassemble(&instructions), expected);
+    }
+}
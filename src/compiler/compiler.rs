@@ -0,0 +1,1435 @@
+use std::{collections::HashMap, rc::Rc};
+
+use anyhow::{anyhow, Result};
+
+use crate::{
+    ast::ast::{
+        AssignExpression, BlockStatement, Expression, FnExpression, InfixExpression,
+        MatchExpression, Parameter, Pattern, Program, Statement,
+    },
+    compiler::{
+        code::{check_operands, make, Instructions, Opcode},
+        symbol_table::{Symbol, SymbolScope, SymbolTable},
+    },
+    lexer::lexer::{Span, Token},
+    object::object::{at, Capture, CompiledFunction, HashKey, Object},
+    parser::builtin_functions::BuiltinFunctions,
+};
+
+/// The output of the compiler: the instructions of the top level program and the constant
+/// pool they index into.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Bytecode {
+    pub instructions: Instructions,
+    pub constants: Vec<Object>,
+    /// Names of the global slots, used to report reads of globals that were never set.
+    pub global_names: Vec<String>,
+    /// Source positions of the top level instructions that can fail.
+    pub positions: Vec<(usize, Span)>,
+}
+
+/// Operand of a jump emitted before its target is known, replaced by `change_operand`.
+const UNKNOWN_TARGET: usize = 0xFFFF;
+
+#[derive(Debug, Clone, Copy)]
+struct EmittedInstruction {
+    opcode: Opcode,
+    position: usize,
+}
+
+/// A loop being compiled: where `continue` jumps to, and the `break` jumps to patch once the
+/// end of the loop is known.
+#[derive(Debug)]
+struct Loop {
+    start: usize,
+    breaks: Vec<usize>,
+    /// The operands on the stack when the body starts, see `CompilationScope::operands`.
+    operands: usize,
+}
+
+/// A test made by a pattern, kept with its jump so a failed destructuring let can say which
+/// part of the value did not match.
+#[derive(Debug)]
+enum PatternTest<'a> {
+    Array {
+        value: Symbol,
+        len: usize,
+        rest: bool,
+    },
+    Hash {
+        value: Symbol,
+    },
+    Key {
+        key: Symbol,
+    },
+    Literal {
+        value: Symbol,
+        literal: &'a Expression,
+    },
+}
+
+/// The code compiled for a pattern: the jumps taken when one of its tests fails, and the
+/// hidden bindings holding the values of the names it binds once every test has passed.
+#[derive(Debug, Default)]
+struct CompiledPattern<'a> {
+    fails: Vec<(usize, PatternTest<'a>)>,
+    bindings: Vec<(&'a str, Symbol)>,
+}
+
+#[derive(Debug, Default)]
+struct CompilationScope {
+    instructions: Instructions,
+    last_instruction: Option<EmittedInstruction>,
+    previous_instruction: Option<EmittedInstruction>,
+    positions: Vec<(usize, Span)>,
+    loops: Vec<Loop>,
+    /// Values the expressions being compiled have left on the stack for an instruction still
+    /// to come, which a `break` or `continue` in a later operand has to pop.
+    operands: usize,
+}
+
+/// Lowers a `Program` into bytecode for the `Vm`. Constants and global symbols are kept
+/// between calls to `compile` so a REPL can build on earlier input.
+pub struct Compiler {
+    constants: Vec<Object>,
+    /// Where integer and string constants already are in `constants`.
+    constant_indexes: HashMap<HashKey, usize>,
+    symbol_table: SymbolTable,
+    scopes: Vec<CompilationScope>,
+    builtin_functions: BuiltinFunctions,
+    /// How many hidden bindings were made, to give each one its own name.
+    hidden_symbols: usize,
+}
+
+impl Default for Compiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Compiler {
+    pub fn new() -> Self {
+        Compiler {
+            constants: vec![],
+            constant_indexes: HashMap::new(),
+            symbol_table: SymbolTable::new(),
+            scopes: vec![CompilationScope::default()],
+            builtin_functions: BuiltinFunctions::setup(),
+            hidden_symbols: 0,
+        }
+    }
+
+    pub fn compile(&mut self, program: &Program) -> Result<Bytecode> {
+        self.scopes = vec![CompilationScope::default()];
+        self.symbol_table.close_open_scopes();
+        for statement in program.statements.iter() {
+            self.compile_statement(statement)?;
+        }
+
+        Ok(Bytecode {
+            instructions: self.current_scope().instructions.clone(),
+            constants: self.constants.clone(),
+            global_names: self.symbol_table.global_names(),
+            positions: self.current_scope().positions.clone(),
+        })
+    }
+
+    fn compile_statement(&mut self, statement: &Statement) -> Result<()> {
+        match statement {
+            Statement::Expression(e) => {
+                self.compile_expression(e)?;
+                self.emit(Opcode::Pop, &[])?;
+            }
+            Statement::Let(l) => {
+                let name = match &l.token {
+                    Token::Ident(s) => s,
+                    _ => return Err(anyhow!("Wrong token type for let: {:?}", l.token)),
+                };
+                self.check_redeclaration(name, l.span)?;
+                // functions are bound before their body is compiled so they can refer to
+                // themselves, everything else sees the previous binding of the name
+                let symbol = match &l.value {
+                    Expression::Fn(func) => {
+                        let symbol = self.declare(name, l.constant);
+                        self.compile_function(func, Some(name.to_owned()))?;
+                        symbol
+                    }
+                    value => {
+                        self.compile_expression(value)?;
+                        self.declare(name, l.constant)
+                    }
+                };
+                self.store_symbol(&symbol).map_err(|e| at(l.span, e))?;
+                // a let evaluates to the bound value, like it does in the tree walker
+                self.load_symbol(&symbol)?;
+                self.emit(Opcode::Pop, &[])?;
+            }
+            Statement::LetPattern(l) => {
+                self.compile_expression(&l.value)?;
+                let value = self.hidden_symbol();
+                self.store_symbol(&value).map_err(|e| at(l.span, e))?;
+                let mut pattern = CompiledPattern::default();
+                self.compile_pattern(&l.pattern, &value, l.span, &mut pattern)?;
+                if !pattern.fails.is_empty() {
+                    let matched = self.emit(Opcode::Jump, &[UNKNOWN_TARGET])?;
+                    for (jump, test) in pattern.fails.into_iter() {
+                        let failed = self.current_scope().instructions.len();
+                        self.change_operand(jump, failed)?;
+                        self.load_symbol(&value)?;
+                        self.compile_mismatch(test, l.span)?;
+                    }
+                    let after = self.current_scope().instructions.len();
+                    self.change_operand(matched, after)?;
+                }
+                self.bind_pattern(&pattern.bindings, l.constant, l.span)?;
+                self.load_symbol(&value)?;
+                self.emit(Opcode::Pop, &[])?;
+            }
+            Statement::Return(r) => {
+                self.compile_expression(&r.return_value)?;
+                self.emit(Opcode::ReturnValue, &[])?;
+            }
+            Statement::While(w) => {
+                let start = self.current_scope().instructions.len();
+                self.compile_expression(&w.condition)?;
+                let exit = self.emit(Opcode::JumpNotTruthy, &[UNKNOWN_TARGET])?;
+
+                // the lets in the body are only bound inside the loop
+                self.symbol_table.enter_block();
+                let breaks = self.compile_loop_body(&w.body, start)?;
+                self.symbol_table.leave_block();
+                let end = self.current_scope().instructions.len();
+                self.change_operand(exit, end)?;
+                for jump in breaks.into_iter() {
+                    self.change_operand(jump, end)?;
+                }
+                // a loop evaluates to null, like it does in the tree walker
+                self.emit(Opcode::Null, &[])?;
+                self.emit(Opcode::Pop, &[])?;
+            }
+            Statement::For(f) => {
+                let name = match &f.variable.token {
+                    Token::Ident(s) => s,
+                    _ => return Err(anyhow!("Wrong token type for loop variable")),
+                };
+                // the items and the index of the next one stay on the stack during the loop
+                self.compile_expression(&f.iterable)?;
+                self.emit_at(Opcode::Iterate, &[], f.iterable.span())?;
+                let start = self.current_scope().instructions.len();
+                let exit = self.emit(Opcode::IterNext, &[UNKNOWN_TARGET])?;
+                // the variable and the lets in the body are only bound inside the loop
+                self.symbol_table.enter_block();
+                self.check_redeclaration(name, f.variable.span)?;
+                let symbol = self.symbol_table.define(name);
+                self.store_symbol(&symbol)
+                    .map_err(|e| at(f.variable.span, e))?;
+
+                let breaks = self.compile_loop_body(&f.body, start)?;
+                self.symbol_table.leave_block();
+                // IterNext drops the iterator when it is done, a break has to do it itself
+                let after_body = self.current_scope().instructions.len();
+                for jump in breaks.into_iter() {
+                    self.change_operand(jump, after_body)?;
+                }
+                self.emit(Opcode::Pop, &[])?;
+                self.emit(Opcode::Pop, &[])?;
+                let end = self.current_scope().instructions.len();
+                self.change_operand(exit, end)?;
+                self.emit(Opcode::Null, &[])?;
+                self.emit(Opcode::Pop, &[])?;
+            }
+            Statement::Break(_) => {
+                let operands = match self.current_scope().loops.last() {
+                    Some(current) => current.operands,
+                    None => return Err(anyhow!("Break outside of a loop")),
+                };
+                self.pop_operands(operands)?;
+                let jump = self.emit(Opcode::Jump, &[UNKNOWN_TARGET])?;
+                if let Some(current) = self.current_scope_mut().loops.last_mut() {
+                    current.breaks.push(jump);
+                }
+            }
+            Statement::Continue(_) => {
+                let (start, operands) = match self.current_scope().loops.last() {
+                    Some(current) => (current.start, current.operands),
+                    None => return Err(anyhow!("Continue outside of a loop")),
+                };
+                self.pop_operands(operands)?;
+                self.emit(Opcode::Jump, &[start])?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Compiles the body of a loop starting at `start`, returning the `break` jumps in it.
+    fn compile_loop_body(&mut self, body: &BlockStatement, start: usize) -> Result<Vec<usize>> {
+        let operands = self.current_scope().operands;
+        self.current_scope_mut().loops.push(Loop {
+            start,
+            breaks: vec![],
+            operands,
+        });
+        for statement in body.statements.iter() {
+            self.compile_statement(statement)?;
+        }
+        self.emit(Opcode::Jump, &[start])?;
+        let current = self.current_scope_mut().loops.pop();
+        Ok(current.map(|l| l.breaks).unwrap_or_default())
+    }
+
+    /// Pops the operands left on the stack above the `operands` a loop started with, before
+    /// a `break` or `continue` jumps out of the expression they belong to.
+    fn pop_operands(&mut self, operands: usize) -> Result<()> {
+        for _ in operands..self.current_scope().operands {
+            self.emit(Opcode::Pop, &[])?;
+        }
+        Ok(())
+    }
+
+    /// Compiles `expression` as an operand of an instruction whose first `before` operands
+    /// are already on the stack.
+    fn compile_operand(&mut self, expression: &Expression, before: usize) -> Result<()> {
+        self.current_scope_mut().operands += before;
+        self.compile_expression(expression)?;
+        self.current_scope_mut().operands -= before;
+        Ok(())
+    }
+
+    fn compile_expression(&mut self, expression: &Expression) -> Result<()> {
+        match expression {
+            Expression::Integer(i, _) => match i {
+                Token::Int(int) => {
+                    let constant = self.add_constant(Object::Integer(*int));
+                    self.emit(Opcode::Constant, &[constant])?;
+                }
+                _ => return Err(anyhow!("Wrong token type for integer: {:?}", i)),
+            },
+            Expression::Float(f, _) => match f {
+                Token::Float(float) => {
+                    let constant = self.add_constant(Object::Float(*float));
+                    self.emit(Opcode::Constant, &[constant])?;
+                }
+                _ => return Err(anyhow!("Wrong token type for float: {:?}", f)),
+            },
+            Expression::String(s, _) => {
+                let constant = self.add_constant(Object::String(s.to_owned()));
+                self.emit(Opcode::Constant, &[constant])?;
+            }
+            Expression::Interpolated(parts, _) => {
+                for (i, part) in parts.iter().enumerate() {
+                    self.compile_operand(part, i)?;
+                }
+                self.emit(Opcode::Interpolate, &[parts.len()])?;
+            }
+            Expression::Boolean(b, _) => match b {
+                Token::True => {
+                    self.emit(Opcode::True, &[])?;
+                }
+                Token::False => {
+                    self.emit(Opcode::False, &[])?;
+                }
+                _ => return Err(anyhow!("Wrong token type for boolean: {:?}", b)),
+            },
+            Expression::Identifier(i, span) => match i {
+                Token::Ident(name) => self.compile_identifier(name, *span)?,
+                _ => return Err(anyhow!("Wrong token type for identifier: {:?}", i)),
+            },
+            Expression::Prefix(p) => {
+                self.compile_expression(&p.right)?;
+                match p.token {
+                    Token::Bang => self.emit_at(Opcode::Bang, &[], p.span)?,
+                    Token::Minus => self.emit_at(Opcode::Minus, &[], p.span)?,
+                    _ => return Err(anyhow!("Unknown prefix operator: {}", p.token)),
+                };
+            }
+            Expression::Infix(inf) if matches!(inf.token, Token::And | Token::Or) => {
+                self.compile_logical(inf)?
+            }
+            Expression::Infix(inf) => {
+                self.compile_expression(&inf.left)?;
+                self.compile_operand(&inf.right, 1)?;
+                self.emit_at(infix_opcode(&inf.token)?, &[], inf.span)?;
+            }
+            Expression::If(i) => {
+                self.compile_expression(&i.condition)?;
+                let jump_not_truthy = self.emit(Opcode::JumpNotTruthy, &[UNKNOWN_TARGET])?;
+
+                self.compile_block(&i.consequence)?;
+                let jump = self.emit(Opcode::Jump, &[UNKNOWN_TARGET])?;
+                let after_consequence = self.current_scope().instructions.len();
+                self.change_operand(jump_not_truthy, after_consequence)?;
+
+                match &i.alternative {
+                    Some(alt) => self.compile_block(alt)?,
+                    None => {
+                        self.emit(Opcode::Null, &[])?;
+                    }
+                }
+                let after_alternative = self.current_scope().instructions.len();
+                self.change_operand(jump, after_alternative)?;
+            }
+            Expression::Fn(func) => self.compile_function(func, None)?,
+            Expression::Call(call) => {
+                self.compile_expression(&call.function)?;
+                let arguments = call.arguments.as_deref().unwrap_or_default();
+                for (i, arg) in arguments.iter().enumerate() {
+                    self.compile_operand(arg, 1 + i)?;
+                }
+                if call.keyword_arguments.is_empty() {
+                    self.emit_at(Opcode::Call, &[arguments.len()], call.span)?;
+                } else {
+                    // keyword arguments follow the positional ones, their names come last
+                    let mut names: Vec<Object> = vec![];
+                    for (i, (name, arg)) in call.keyword_arguments.iter().enumerate() {
+                        self.compile_operand(arg, 1 + arguments.len() + i)?;
+                        match &name.token {
+                            Token::Ident(s) => names.push(Object::String(s.to_owned())),
+                            _ => return Err(anyhow!("Wrong token type for keyword: {:?}", name)),
+                        }
+                    }
+                    let num_args = arguments.len() + names.len();
+                    let constant = self.add_constant(Object::Array(names));
+                    self.emit(Opcode::Constant, &[constant])?;
+                    self.emit_at(Opcode::CallKeywords, &[num_args], call.span)?;
+                }
+            }
+            Expression::Array(a, _) => {
+                for (i, item) in a.iter().enumerate() {
+                    self.compile_operand(item, i)?;
+                }
+                self.emit(Opcode::Array, &[a.len()])?;
+            }
+            Expression::Hash(h, span) => {
+                for (i, (key, value)) in h.iter().enumerate() {
+                    self.compile_operand(key, 2 * i)?;
+                    self.compile_operand(value, 2 * i + 1)?;
+                }
+                self.emit_at(Opcode::Hash, &[h.len()], *span)?;
+            }
+            Expression::Index(i) => {
+                self.compile_expression(&i.left)?;
+                self.compile_operand(&i.index, 1)?;
+                self.emit_at(Opcode::Index, &[], i.span)?;
+            }
+            Expression::Assign(a) => self.compile_assign(a)?,
+            Expression::Match(m) => self.compile_match(m)?,
+        }
+        Ok(())
+    }
+
+    /// Compiles an assignment, leaving the assigned value on the stack.
+    fn compile_assign(&mut self, a: &AssignExpression) -> Result<()> {
+        let name = match &a.name.token {
+            Token::Ident(s) => s,
+            _ => return Err(anyhow!("Wrong token type for assignment: {:?}", a.name)),
+        };
+        match a.operator() {
+            Some(operator) => {
+                self.compile_identifier(name, a.name.span)?;
+                self.compile_operand(&a.value, 1)?;
+                self.emit_at(infix_opcode(&operator)?, &[], a.span)?;
+            }
+            None => self.compile_expression(&a.value)?,
+        }
+
+        // as in compile_identifier, a name that is not bound yet may be bound by a later top
+        // level let, so the vm checks globals when the assignment runs
+        let symbol = match self.symbol_table.resolve(name) {
+            Some(symbol) => symbol,
+            None => self.symbol_table.define_global(name),
+        };
+        if symbol.constant {
+            return Err(at(a.span, anyhow!("Cannot assign to constant: {}", name)));
+        }
+        match symbol.scope {
+            SymbolScope::Global => self.emit_at(Opcode::AssignGlobal, &[symbol.index], a.span)?,
+            SymbolScope::Local => self.emit(Opcode::SetLocal, &[symbol.index])?,
+            SymbolScope::Free => self.emit(Opcode::SetFree, &[symbol.index])?,
+        };
+        self.load_symbol(&symbol)?;
+        Ok(())
+    }
+
+    /// Compiles `&&` and `||` so the right side only runs when the left side does not decide
+    /// the result.
+    fn compile_logical(&mut self, inf: &InfixExpression) -> Result<()> {
+        self.compile_expression(&inf.left)?;
+        let jump_not_truthy = self.emit(Opcode::JumpNotTruthy, &[UNKNOWN_TARGET])?;
+
+        // left side is truthy
+        match inf.token {
+            Token::Or => {
+                self.emit(Opcode::True, &[])?;
+            }
+            _ => self.compile_truthiness(&inf.right)?,
+        }
+        let jump = self.emit(Opcode::Jump, &[UNKNOWN_TARGET])?;
+        let after_truthy = self.current_scope().instructions.len();
+        self.change_operand(jump_not_truthy, after_truthy)?;
+
+        // left side is falsy
+        match inf.token {
+            Token::Or => self.compile_truthiness(&inf.right)?,
+            _ => {
+                self.emit(Opcode::False, &[])?;
+            }
+        }
+        let after_falsy = self.current_scope().instructions.len();
+        self.change_operand(jump, after_falsy)?;
+        Ok(())
+    }
+
+    /// Compiles a match as a test of each arm in turn, falling through to a `NoMatch`. The
+    /// value and the parts of it that patterns look into are kept in hidden bindings, so a
+    /// failed test jumps to the next arm with nothing left on the stack.
+    fn compile_match(&mut self, m: &MatchExpression) -> Result<()> {
+        self.symbol_table.enter_block();
+        self.compile_expression(&m.value)?;
+        let subject = self.hidden_symbol();
+        self.store_symbol(&subject)?;
+
+        let mut ends: Vec<usize> = vec![];
+        for arm in m.arms.iter() {
+            // names bound by the pattern are only visible in its arm
+            self.symbol_table.enter_block();
+            let mut pattern = CompiledPattern::default();
+            self.compile_pattern(&arm.pattern, &subject, arm.span, &mut pattern)?;
+            self.bind_pattern(&pattern.bindings, false, arm.span)?;
+            let mut fails: Vec<usize> = pattern.fails.into_iter().map(|(jump, _)| jump).collect();
+            if let Some(guard) = &arm.guard {
+                self.compile_expression(guard)?;
+                fails.push(self.emit(Opcode::JumpNotTruthy, &[UNKNOWN_TARGET])?);
+            }
+            self.compile_block(&arm.body)?;
+            ends.push(self.emit(Opcode::Jump, &[UNKNOWN_TARGET])?);
+            self.symbol_table.leave_block();
+
+            let next_arm = self.current_scope().instructions.len();
+            for jump in fails.into_iter() {
+                self.change_operand(jump, next_arm)?;
+            }
+        }
+        self.load_symbol(&subject)?;
+        self.emit_at(Opcode::NoMatch, &[], m.span)?;
+
+        let end = self.current_scope().instructions.len();
+        for jump in ends.into_iter() {
+            self.change_operand(jump, end)?;
+        }
+        self.symbol_table.leave_block();
+        Ok(())
+    }
+
+    /// Compiles a test of the value in `value` against `pattern`, adding the jumps taken when
+    /// it does not match and the names it binds to `compiled`. Errors are reported at `span`.
+    fn compile_pattern<'a>(
+        &mut self,
+        pattern: &'a Pattern,
+        value: &Symbol,
+        span: Span,
+        compiled: &mut CompiledPattern<'a>,
+    ) -> Result<()> {
+        match pattern {
+            Pattern::Wildcard(_) => {}
+            Pattern::Binding(name) => {
+                let name = match &name.token {
+                    Token::Ident(s) => s,
+                    _ => return Err(anyhow!("Wrong token type for binding: {:?}", name.token)),
+                };
+                compiled.bindings.push((name, value.clone()));
+            }
+            Pattern::Literal(literal) => {
+                self.load_symbol(value)?;
+                self.compile_expression(literal)?;
+                self.emit(Opcode::MatchLiteral, &[])?;
+                let jump = self.emit(Opcode::JumpNotTruthy, &[UNKNOWN_TARGET])?;
+                let test = PatternTest::Literal {
+                    value: value.clone(),
+                    literal,
+                };
+                compiled.fails.push((jump, test));
+            }
+            Pattern::Array(items, rest, _) => {
+                self.load_symbol(value)?;
+                let (len, rest_name) = (items.len(), rest.as_ref().map(|r| &r.token));
+                self.emit_at(Opcode::MatchArray, &[len, rest.is_some() as usize], span)?;
+                let jump = self.emit(Opcode::JumpNotTruthy, &[UNKNOWN_TARGET])?;
+                let test = PatternTest::Array {
+                    value: value.clone(),
+                    len,
+                    rest: rest.is_some(),
+                };
+                compiled.fails.push((jump, test));
+                for (i, item) in items.iter().enumerate() {
+                    self.load_symbol(value)?;
+                    let index = self.add_constant(Object::Integer(i as isize));
+                    self.emit(Opcode::Constant, &[index])?;
+                    self.emit(Opcode::Index, &[])?;
+                    let element = self.hidden_symbol();
+                    self.store_symbol(&element).map_err(|e| at(span, e))?;
+                    self.compile_pattern(item, &element, span, compiled)?;
+                }
+                match rest_name {
+                    Some(Token::Ident(name)) if name != "_" => {
+                        self.load_symbol(value)?;
+                        self.emit(Opcode::SliceFrom, &[len])?;
+                        let rest = self.hidden_symbol();
+                        self.store_symbol(&rest).map_err(|e| at(span, e))?;
+                        compiled.bindings.push((name, rest));
+                    }
+                    _ => {}
+                }
+            }
+            Pattern::Hash(entries, _) => {
+                self.load_symbol(value)?;
+                self.emit(Opcode::MatchHash, &[])?;
+                let jump = self.emit(Opcode::JumpNotTruthy, &[UNKNOWN_TARGET])?;
+                let test = PatternTest::Hash {
+                    value: value.clone(),
+                };
+                compiled.fails.push((jump, test));
+                for (key, pattern) in entries.iter() {
+                    self.compile_expression(key)?;
+                    let key = self.hidden_symbol();
+                    self.store_symbol(&key).map_err(|e| at(span, e))?;
+
+                    self.load_symbol(value)?;
+                    self.load_symbol(&key)?;
+                    self.emit_at(Opcode::MatchKey, &[], span)?;
+                    let jump = self.emit(Opcode::JumpNotTruthy, &[UNKNOWN_TARGET])?;
+                    compiled
+                        .fails
+                        .push((jump, PatternTest::Key { key: key.clone() }));
+
+                    self.load_symbol(value)?;
+                    self.load_symbol(&key)?;
+                    self.emit(Opcode::Index, &[])?;
+                    let entry = self.hidden_symbol();
+                    self.store_symbol(&entry).map_err(|e| at(span, e))?;
+                    self.compile_pattern(pattern, &entry, span, compiled)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Binds the names of a pattern whose tests have all passed.
+    fn bind_pattern(
+        &mut self,
+        bindings: &[(&str, Symbol)],
+        constant: bool,
+        span: Span,
+    ) -> Result<()> {
+        for (name, value) in bindings.iter() {
+            self.check_redeclaration(name, span)?;
+            self.load_symbol(value)?;
+            let symbol = self.declare(name, constant);
+            self.store_symbol(&symbol).map_err(|e| at(span, e))?;
+        }
+        Ok(())
+    }
+
+    /// Compiles the error of a destructuring let whose pattern failed `test`, with the value
+    /// being destructured on the stack.
+    fn compile_mismatch(&mut self, test: PatternTest, span: Span) -> Result<()> {
+        match test {
+            PatternTest::Array { value, len, rest } => {
+                self.load_symbol(&value)?;
+                self.emit_at(Opcode::MismatchArray, &[len, rest as usize], span)?;
+            }
+            PatternTest::Hash { value } => {
+                self.load_symbol(&value)?;
+                self.emit_at(Opcode::MismatchHash, &[], span)?;
+            }
+            PatternTest::Key { key } => {
+                self.load_symbol(&key)?;
+                self.emit_at(Opcode::MismatchKey, &[], span)?;
+            }
+            PatternTest::Literal { value, literal } => {
+                self.compile_expression(literal)?;
+                self.load_symbol(&value)?;
+                self.emit_at(Opcode::MismatchLiteral, &[], span)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Binds a new name in the current block for a value the compiler keeps around. The
+    /// name has a space in it, so no identifier can refer to it.
+    fn hidden_symbol(&mut self) -> Symbol {
+        self.hidden_symbols += 1;
+        let name = format!("hidden {}", self.hidden_symbols);
+        self.symbol_table.define(&name)
+    }
+
+    /// Compiles `expression` and turns its value into a boolean with a double `Bang`.
+    fn compile_truthiness(&mut self, expression: &Expression) -> Result<()> {
+        self.compile_expression(expression)?;
+        self.emit(Opcode::Bang, &[])?;
+        self.emit(Opcode::Bang, &[])?;
+        Ok(())
+    }
+
+    fn declare(&mut self, name: &str, constant: bool) -> Symbol {
+        match constant {
+            true => self.symbol_table.define_const(name),
+            false => self.symbol_table.define(name),
+        }
+    }
+
+    /// Declaring a constant's name again in its own scope is an error, as it is in the tree
+    /// walker.
+    fn check_redeclaration(&self, name: &str, span: Span) -> Result<()> {
+        if self.symbol_table.is_constant(name) {
+            return Err(at(span, anyhow!("Cannot redeclare constant: {}", name)));
+        }
+        Ok(())
+    }
+
+    fn compile_identifier(&mut self, name: &str, span: Span) -> Result<()> {
+        if let Some(symbol) = self.symbol_table.resolve(name) {
+            let position = self.load_symbol(&symbol)?;
+            self.current_scope_mut().positions.push((position, span));
+        } else if let Some(builtin) = self.builtin_functions.get_fn(name.to_owned()) {
+            let constant = self.add_constant(Object::BuiltinFunction(builtin));
+            self.emit(Opcode::Constant, &[constant])?;
+        } else {
+            // the name may still be bound by a later top level let, so reserve a global slot
+            // for it and let the vm report it if it is still unset when read
+            let symbol = self.symbol_table.define_global(name);
+            let position = self.load_symbol(&symbol)?;
+            self.current_scope_mut().positions.push((position, span));
+        }
+        Ok(())
+    }
+
+    /// Compiles a block used as an expression, leaving its last value on the stack.
+    fn compile_block(&mut self, block: &BlockStatement) -> Result<()> {
+        for statement in block.statements.iter() {
+            self.compile_statement(statement)?;
+        }
+        if self.last_instruction_is(Opcode::Pop) {
+            self.remove_last_instruction();
+        } else if block.statements.is_empty() {
+            self.emit(Opcode::Null, &[])?;
+        }
+        Ok(())
+    }
+
+    fn compile_function(&mut self, func: &FnExpression, name: Option<String>) -> Result<()> {
+        self.enter_scope();
+
+        let parameters = func.parameters.as_deref().unwrap_or_default();
+        // positional parameters take the first local slots, the rest parameter the next one
+        let (rest, positional): (Vec<&Parameter>, Vec<&Parameter>) =
+            parameters.iter().partition(|p| p.rest);
+        let mut names: Vec<String> = vec![];
+        for param in positional.iter().chain(rest.iter()) {
+            let name = match &param.name.token {
+                Token::Ident(s) => s,
+                _ => return Err(anyhow!("Wrong token type for parameter: {:?}", param)),
+            };
+            self.symbol_table.define(name);
+            names.push(name.to_owned());
+        }
+        names.truncate(positional.len());
+
+        // parameters left without an argument are set to their default in order, so a
+        // default can refer to the parameters before it
+        for (i, param) in positional.iter().enumerate() {
+            if let Some(default) = &param.default {
+                self.emit(Opcode::ArgumentMissing, &[i])?;
+                let jump = self.emit(Opcode::JumpNotTruthy, &[UNKNOWN_TARGET])?;
+                self.compile_expression(default)?;
+                self.emit(Opcode::SetLocal, &[i])?;
+                let after = self.current_scope().instructions.len();
+                self.change_operand(jump, after)?;
+            }
+        }
+
+        for statement in func.body.statements.iter() {
+            self.compile_statement(statement)?;
+        }
+        if self.last_instruction_is(Opcode::Pop) {
+            let position = self
+                .current_scope()
+                .last_instruction
+                .map(|l| l.position)
+                .unwrap_or_default();
+            self.current_scope_mut().instructions[position] = Opcode::ReturnValue as u8;
+            if let Some(last) = self.current_scope_mut().last_instruction.as_mut() {
+                last.opcode = Opcode::ReturnValue;
+            }
+        }
+        if !self.last_instruction_is(Opcode::ReturnValue) {
+            self.emit(Opcode::Return, &[])?;
+        }
+
+        let num_locals = self.symbol_table.num_definitions;
+        let captures: Vec<Capture> = self
+            .symbol_table
+            .free_symbols
+            .iter()
+            .map(|symbol| match symbol.scope {
+                SymbolScope::Free => Capture::Free(symbol.index),
+                _ => Capture::Local(symbol.index),
+            })
+            .collect();
+        let scope = self.leave_scope();
+
+        let function = CompiledFunction {
+            instructions: scope.instructions,
+            num_locals,
+            parameters: names,
+            num_defaults: positional.iter().filter(|p| p.default.is_some()).count(),
+            variadic: !rest.is_empty(),
+            name,
+            captures,
+            positions: scope.positions,
+        };
+        let constant = self.add_constant(Object::CompiledFunction(Rc::new(function)));
+        self.emit(Opcode::Closure, &[constant])?;
+        Ok(())
+    }
+
+    fn load_symbol(&mut self, symbol: &Symbol) -> Result<usize> {
+        match symbol.scope {
+            SymbolScope::Global => self.emit(Opcode::GetGlobal, &[symbol.index]),
+            SymbolScope::Local => self.emit(Opcode::GetLocal, &[symbol.index]),
+            SymbolScope::Free => self.emit(Opcode::GetFree, &[symbol.index]),
+        }
+    }
+
+    fn store_symbol(&mut self, symbol: &Symbol) -> Result<()> {
+        match symbol.scope {
+            SymbolScope::Global => self.emit(Opcode::SetGlobal, &[symbol.index])?,
+            _ => self.emit(Opcode::SetLocal, &[symbol.index])?,
+        };
+        Ok(())
+    }
+
+    /// Adds `object` to the constant pool, reusing the slot of an equal integer or string
+    /// so a long REPL session does not fill the pool with copies.
+    fn add_constant(&mut self, object: Object) -> usize {
+        let key = HashKey::try_from(&object).ok();
+        if let Some(index) = key.as_ref().and_then(|k| self.constant_indexes.get(k)) {
+            return *index;
+        }
+        self.constants.push(object);
+        let index = self.constants.len() - 1;
+        if let Some(key) = key {
+            self.constant_indexes.insert(key, index);
+        }
+        index
+    }
+
+    /// Appends an instruction to the current scope and returns its position. Fails when an
+    /// operand does not fit in the instruction.
+    fn emit(&mut self, opcode: Opcode, operands: &[usize]) -> Result<usize> {
+        check_operands(opcode, operands)?;
+        let instruction = make(opcode, operands);
+        let scope = self.current_scope_mut();
+        let position = scope.instructions.len();
+        scope.instructions.extend(instruction);
+        scope.previous_instruction = scope.last_instruction;
+        scope.last_instruction = Some(EmittedInstruction { opcode, position });
+        Ok(position)
+    }
+
+    /// Like `emit`, also recording `span` so the vm can report where a failing instruction
+    /// came from.
+    fn emit_at(&mut self, opcode: Opcode, operands: &[usize], span: Span) -> Result<usize> {
+        let position = self.emit(opcode, operands).map_err(|e| at(span, e))?;
+        self.current_scope_mut().positions.push((position, span));
+        Ok(position)
+    }
+
+    fn change_operand(&mut self, position: usize, operand: usize) -> Result<()> {
+        let scope = self.current_scope_mut();
+        let opcode = Opcode::try_from(scope.instructions[position])
+            .expect("instruction was emitted by the compiler");
+        check_operands(opcode, &[operand])?;
+        let instruction = make(opcode, &[operand]);
+        scope.instructions[position..position + instruction.len()].copy_from_slice(&instruction);
+        Ok(())
+    }
+
+    fn last_instruction_is(&self, opcode: Opcode) -> bool {
+        match self.current_scope().last_instruction {
+            Some(last) => last.opcode == opcode,
+            None => false,
+        }
+    }
+
+    fn remove_last_instruction(&mut self) {
+        let scope = self.current_scope_mut();
+        if let Some(last) = scope.last_instruction {
+            scope.instructions.truncate(last.position);
+            scope.last_instruction = scope.previous_instruction;
+        }
+    }
+
+    fn current_scope(&self) -> &CompilationScope {
+        self.scopes.last().expect("compiler always has a scope")
+    }
+
+    fn current_scope_mut(&mut self) -> &mut CompilationScope {
+        self.scopes.last_mut().expect("compiler always has a scope")
+    }
+
+    fn enter_scope(&mut self) {
+        self.scopes.push(CompilationScope::default());
+        let outer = std::mem::take(&mut self.symbol_table);
+        self.symbol_table = SymbolTable::new_enclosed(outer);
+    }
+
+    fn leave_scope(&mut self) -> CompilationScope {
+        let scope = self.scopes.pop().expect("compiler always has a scope");
+        if let Some(outer) = self.symbol_table.outer.take() {
+            self.symbol_table = *outer;
+        }
+        scope
+    }
+}
+
+/// The opcode evaluating a binary operator.
+fn infix_opcode(token: &Token) -> Result<Opcode> {
+    let opcode = match token {
+        Token::Plus => Opcode::Add,
+        Token::Minus => Opcode::Sub,
+        Token::Asterisk => Opcode::Mul,
+        Token::Slash => Opcode::Div,
+        Token::Equal => Opcode::Equal,
+        Token::NotEqual => Opcode::NotEqual,
+        Token::GreaterThan => Opcode::GreaterThan,
+        Token::LessThan => Opcode::LessThan,
+        Token::GreaterEqual => Opcode::GreaterEqual,
+        Token::LessEqual => Opcode::LessEqual,
+        Token::Percent => Opcode::Mod,
+        Token::Power => Opcode::Pow,
+        Token::Ampersand => Opcode::BitAnd,
+        Token::Pipe => Opcode::BitOr,
+        Token::Caret => Opcode::BitXor,
+        Token::ShiftLeft => Opcode::ShiftLeft,
+        Token::ShiftRight => Opcode::ShiftRight,
+        _ => return Err(anyhow!("Unknown infix operator: {}", token)),
+    };
+    Ok(opcode)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use crate::{
+        compiler::{
+            code::{make, Instructions, Opcode},
+            symbol_table::SymbolScope,
+        },
+        lexer::lexer::Lexer,
+        object::object::{Capture, CompiledFunction, Object},
+        parser::parser::Parser,
+    };
+
+    use super::{Bytecode, Compiler};
+
+    struct Test {
+        input: Vec<u8>,
+        constants: Vec<Object>,
+        instructions: Vec<Instructions>,
+    }
+
+    fn compile(input: Vec<u8>) -> Bytecode {
+        let lex = Lexer::new(input);
+        let mut parser = Parser::new(lex);
+        let program = parser.parse_program();
+        let mut compiler = Compiler::new();
+        compiler.compile(&program).unwrap()
+    }
+
+    fn run_tests(tests: Vec<Test>) {
+        for test in tests.into_iter() {
+            let bytecode = compile(test.input);
+            assert_eq!(
+                crate::compiler::code::disassemble(&bytecode.instructions),
+                crate::compiler::code::disassemble(&test.instructions.concat())
+            );
+            assert_eq!(without_positions(bytecode.constants), test.constants);
+        }
+    }
+
+    /// Positions are checked in `test_positions`, clear them so the other tests only compare
+    /// instructions.
+    fn without_positions(constants: Vec<Object>) -> Vec<Object> {
+        constants
+            .into_iter()
+            .map(|constant| match constant {
+                Object::CompiledFunction(f) => {
+                    let mut function = f.as_ref().clone();
+                    function.positions = vec![];
+                    Object::CompiledFunction(Rc::new(function))
+                }
+                other => other,
+            })
+            .collect()
+    }
+
+    fn function(
+        instructions: Vec<Instructions>,
+        num_locals: usize,
+        parameters: &[&str],
+        name: Option<&str>,
+        captures: Vec<Capture>,
+    ) -> Object {
+        Object::CompiledFunction(Rc::new(CompiledFunction {
+            instructions: instructions.concat(),
+            num_locals,
+            parameters: parameters.iter().map(|p| p.to_string()).collect(),
+            num_defaults: 0,
+            variadic: false,
+            name: name.map(String::from),
+            captures,
+            positions: vec![],
+        }))
+    }
+
+    #[test]
+    fn test_integer_arithmetic() {
+        let tests = vec![
+            Test {
+                input: "1 + 2".into(),
+                constants: vec![Object::Integer(1), Object::Integer(2)],
+                instructions: vec![
+                    make(Opcode::Constant, &[0]),
+                    make(Opcode::Constant, &[1]),
+                    make(Opcode::Add, &[]),
+                    make(Opcode::Pop, &[]),
+                ],
+            },
+            Test {
+                input: "1; 2".into(),
+                constants: vec![Object::Integer(1), Object::Integer(2)],
+                instructions: vec![
+                    make(Opcode::Constant, &[0]),
+                    make(Opcode::Pop, &[]),
+                    make(Opcode::Constant, &[1]),
+                    make(Opcode::Pop, &[]),
+                ],
+            },
+            Test {
+                input: "-1 < 2".into(),
+                constants: vec![Object::Integer(1), Object::Integer(2)],
+                instructions: vec![
+                    make(Opcode::Constant, &[0]),
+                    make(Opcode::Minus, &[]),
+                    make(Opcode::Constant, &[1]),
+                    make(Opcode::LessThan, &[]),
+                    make(Opcode::Pop, &[]),
+                ],
+            },
+        ];
+        run_tests(tests);
+    }
+
+    #[test]
+    fn test_conditionals() {
+        let tests = vec![
+            Test {
+                input: "if (true) { 10 }; 3333;".into(),
+                constants: vec![Object::Integer(10), Object::Integer(3333)],
+                instructions: vec![
+                    make(Opcode::True, &[]),
+                    make(Opcode::JumpNotTruthy, &[10]),
+                    make(Opcode::Constant, &[0]),
+                    make(Opcode::Jump, &[11]),
+                    make(Opcode::Null, &[]),
+                    make(Opcode::Pop, &[]),
+                    make(Opcode::Constant, &[1]),
+                    make(Opcode::Pop, &[]),
+                ],
+            },
+            Test {
+                input: "if (true) { 10 } else { 20 }".into(),
+                constants: vec![Object::Integer(10), Object::Integer(20)],
+                instructions: vec![
+                    make(Opcode::True, &[]),
+                    make(Opcode::JumpNotTruthy, &[10]),
+                    make(Opcode::Constant, &[0]),
+                    make(Opcode::Jump, &[13]),
+                    make(Opcode::Constant, &[1]),
+                    make(Opcode::Pop, &[]),
+                ],
+            },
+        ];
+        run_tests(tests);
+    }
+
+    #[test]
+    fn test_match() {
+        run_tests(vec![Test {
+            input: "match (5) { 1 => 2, n => n }".into(),
+            constants: vec![Object::Integer(5), Object::Integer(1), Object::Integer(2)],
+            instructions: vec![
+                // the value is kept in a hidden global
+                make(Opcode::Constant, &[0]),
+                make(Opcode::SetGlobal, &[0]),
+                make(Opcode::GetGlobal, &[0]),
+                make(Opcode::Constant, &[1]),
+                make(Opcode::MatchLiteral, &[]),
+                make(Opcode::JumpNotTruthy, &[22]),
+                make(Opcode::Constant, &[2]),
+                make(Opcode::Jump, &[38]),
+                make(Opcode::GetGlobal, &[0]),
+                make(Opcode::SetGlobal, &[1]),
+                make(Opcode::GetGlobal, &[1]),
+                make(Opcode::Jump, &[38]),
+                make(Opcode::GetGlobal, &[0]),
+                make(Opcode::NoMatch, &[]),
+                make(Opcode::Pop, &[]),
+            ],
+        }]);
+    }
+
+    #[test]
+    fn test_destructuring_let() {
+        run_tests(vec![Test {
+            input: "let [a] = [1]".into(),
+            constants: vec![Object::Integer(1), Object::Integer(0)],
+            instructions: vec![
+                make(Opcode::Constant, &[0]),
+                make(Opcode::Array, &[1]),
+                make(Opcode::SetGlobal, &[0]),
+                make(Opcode::GetGlobal, &[0]),
+                make(Opcode::MatchArray, &[1, 0]),
+                make(Opcode::JumpNotTruthy, &[32]),
+                make(Opcode::GetGlobal, &[0]),
+                make(Opcode::Constant, &[1]),
+                make(Opcode::Index, &[]),
+                make(Opcode::SetGlobal, &[1]),
+                make(Opcode::Jump, &[42]),
+                // a failed test reports the value and the part of it that did not match
+                make(Opcode::GetGlobal, &[0]),
+                make(Opcode::GetGlobal, &[0]),
+                make(Opcode::MismatchArray, &[1, 0]),
+                // names are only bound once every test has passed
+                make(Opcode::GetGlobal, &[1]),
+                make(Opcode::SetGlobal, &[2]),
+                make(Opcode::GetGlobal, &[0]),
+                make(Opcode::Pop, &[]),
+            ],
+        }]);
+    }
+
+    #[test]
+    fn test_global_let_statements() {
+        let tests = vec![Test {
+            input: "let one = 1; one;".into(),
+            constants: vec![Object::Integer(1)],
+            instructions: vec![
+                make(Opcode::Constant, &[0]),
+                make(Opcode::SetGlobal, &[0]),
+                make(Opcode::GetGlobal, &[0]),
+                make(Opcode::Pop, &[]),
+                make(Opcode::GetGlobal, &[0]),
+                make(Opcode::Pop, &[]),
+            ],
+        }];
+        run_tests(tests);
+    }
+
+    #[test]
+    fn test_collections() {
+        let tests = vec![
+            Test {
+                input: "[1, 2][0]".into(),
+                constants: vec![Object::Integer(1), Object::Integer(2), Object::Integer(0)],
+                instructions: vec![
+                    make(Opcode::Constant, &[0]),
+                    make(Opcode::Constant, &[1]),
+                    make(Opcode::Array, &[2]),
+                    make(Opcode::Constant, &[2]),
+                    make(Opcode::Index, &[]),
+                    make(Opcode::Pop, &[]),
+                ],
+            },
+            Test {
+                input: r#"{"a": 1}"#.into(),
+                constants: vec![Object::String(String::from("a")), Object::Integer(1)],
+                instructions: vec![
+                    make(Opcode::Constant, &[0]),
+                    make(Opcode::Constant, &[1]),
+                    make(Opcode::Hash, &[1]),
+                    make(Opcode::Pop, &[]),
+                ],
+            },
+        ];
+        run_tests(tests);
+    }
+
+    #[test]
+    fn test_functions() {
+        let tests = vec![
+            Test {
+                input: "fn() { return 5 + 10 }".into(),
+                constants: vec![
+                    Object::Integer(5),
+                    Object::Integer(10),
+                    function(
+                        vec![
+                            make(Opcode::Constant, &[0]),
+                            make(Opcode::Constant, &[1]),
+                            make(Opcode::Add, &[]),
+                            make(Opcode::ReturnValue, &[]),
+                        ],
+                        0,
+                        &[],
+                        None,
+                        vec![],
+                    ),
+                ],
+                instructions: vec![make(Opcode::Closure, &[2]), make(Opcode::Pop, &[])],
+            },
+            Test {
+                input: "fn() { }".into(),
+                constants: vec![function(
+                    vec![make(Opcode::Return, &[])],
+                    0,
+                    &[],
+                    None,
+                    vec![],
+                )],
+                instructions: vec![make(Opcode::Closure, &[0]), make(Opcode::Pop, &[])],
+            },
+            Test {
+                input: "let identity = fn(a) { a }; identity(1);".into(),
+                constants: vec![
+                    function(
+                        vec![make(Opcode::GetLocal, &[0]), make(Opcode::ReturnValue, &[])],
+                        1,
+                        &["a"],
+                        Some("identity"),
+                        vec![],
+                    ),
+                    Object::Integer(1),
+                ],
+                instructions: vec![
+                    make(Opcode::Closure, &[0]),
+                    make(Opcode::SetGlobal, &[0]),
+                    make(Opcode::GetGlobal, &[0]),
+                    make(Opcode::Pop, &[]),
+                    make(Opcode::GetGlobal, &[0]),
+                    make(Opcode::Constant, &[1]),
+                    make(Opcode::Call, &[1]),
+                    make(Opcode::Pop, &[]),
+                ],
+            },
+        ];
+        run_tests(tests);
+    }
+
+    #[test]
+    fn test_defaults_and_keyword_arguments() {
+        let function = CompiledFunction {
+            instructions: [
+                // a default is only evaluated when its argument is missing
+                make(Opcode::ArgumentMissing, &[1]),
+                make(Opcode::JumpNotTruthy, &[10]),
+                make(Opcode::Constant, &[0]),
+                make(Opcode::SetLocal, &[1]),
+                make(Opcode::GetLocal, &[1]),
+                make(Opcode::ReturnValue, &[]),
+            ]
+            .concat(),
+            num_locals: 2,
+            parameters: vec![String::from("x"), String::from("y")],
+            num_defaults: 1,
+            variadic: false,
+            name: None,
+            captures: vec![],
+            positions: vec![],
+        };
+        run_tests(vec![Test {
+            input: "fn(x, y = 1) { y }(1, y: 2)".into(),
+            constants: vec![
+                Object::Integer(1),
+                Object::CompiledFunction(Rc::new(function)),
+                Object::Integer(2),
+                Object::Array(vec![Object::String(String::from("y"))]),
+            ],
+            instructions: vec![
+                make(Opcode::Closure, &[1]),
+                make(Opcode::Constant, &[0]),
+                make(Opcode::Constant, &[2]),
+                make(Opcode::Constant, &[3]),
+                make(Opcode::CallKeywords, &[2]),
+                make(Opcode::Pop, &[]),
+            ],
+        }]);
+    }
+
+    #[test]
+    fn test_closures() {
+        let tests = vec![Test {
+            input: "fn(a) { fn(b) { a + b } }".into(),
+            constants: vec![
+                function(
+                    vec![
+                        make(Opcode::GetFree, &[0]),
+                        make(Opcode::GetLocal, &[0]),
+                        make(Opcode::Add, &[]),
+                        make(Opcode::ReturnValue, &[]),
+                    ],
+                    1,
+                    &["b"],
+                    None,
+                    vec![Capture::Local(0)],
+                ),
+                function(
+                    vec![make(Opcode::Closure, &[0]), make(Opcode::ReturnValue, &[])],
+                    1,
+                    &["a"],
+                    None,
+                    vec![],
+                ),
+            ],
+            instructions: vec![make(Opcode::Closure, &[1]), make(Opcode::Pop, &[])],
+        }];
+        run_tests(tests);
+    }
+
+    #[test]
+    fn test_builtins_and_forward_references() {
+        let tests = vec![Test {
+            input: "len; later;".into(),
+            constants: vec![Object::BuiltinFunction(
+                crate::parser::builtin_functions::BuiltinFunctions::setup()
+                    .get_fn(String::from("len"))
+                    .unwrap(),
+            )],
+            instructions: vec![
+                make(Opcode::Constant, &[0]),
+                make(Opcode::Pop, &[]),
+                make(Opcode::GetGlobal, &[0]),
+                make(Opcode::Pop, &[]),
+            ],
+        }];
+        run_tests(tests);
+    }
+
+    #[test]
+    fn test_compiler_keeps_state_between_programs() {
+        let mut compiler = Compiler::new();
+        for (input, expected_global) in [("let a = 1;", 0), ("let b = a;", 1)] {
+            let lex = Lexer::new(input.into());
+            let mut parser = Parser::new(lex);
+            let program = parser.parse_program();
+            let bytecode = compiler.compile(&program).unwrap();
+            assert_eq!(bytecode.global_names.len(), expected_global + 1);
+        }
+    }
+
+    #[test]
+    fn test_positions() {
+        let bytecode = compile("let a = 1;\n  a + b;".into());
+        let positions: Vec<(usize, usize, usize)> = bytecode
+            .positions
+            .iter()
+            .map(|(offset, span)| (*offset, span.line, span.column))
+            .collect();
+
+        // GetGlobal a, GetGlobal b, Add
+        assert_eq!(positions, vec![(10, 2, 3), (13, 2, 7), (16, 2, 5)]);
+    }
+
+    /// `count` copies of `item` joined by `sep`. In each copy `{}` is replaced by its number
+    /// and `{name}` by a distinct identifier.
+    fn repeat(count: usize, item: &str, sep: &str) -> String {
+        let items: Vec<String> = (0..count)
+            .map(|i| {
+                let name: String = format!("{:o}", i)
+                    .bytes()
+                    .map(|digit| (digit - b'0' + b'a') as char)
+                    .collect();
+                item.replace("{name}", &name).replace("{}", &i.to_string())
+            })
+            .collect();
+        items.join(sep)
+    }
+
+    #[test]
+    fn test_operand_limits() {
+        let tests = vec![
+            (format!("len({})", repeat(255, "0", ", ")), None),
+            (
+                format!("len({})", repeat(256, "0", ", ")),
+                Some("line 1, column 1: Too many arguments in a call, the limit is 255"),
+            ),
+            (
+                format!("fn() {{ {} }}", repeat(256, "let {name} = 0;", " ")),
+                None,
+            ),
+            (
+                format!("fn() {{ {} }}", repeat(257, "let {name} = 0;", " ")),
+                Some("line 1, column 3264: Too many local bindings in one function, the limit is 256"),
+            ),
+            (repeat(65536, "{}", "; "), None),
+            (
+                repeat(65537, "{}", "; "),
+                Some("Too many constants, the limit is 65536"),
+            ),
+            (
+                format!("if (true) {{ {} }}", repeat(6000, "x = 1;", " ")),
+                None,
+            ),
+            (
+                format!("if (true) {{ {} }}", repeat(7000, "x = 1;", " ")),
+                Some("Too many bytes of instructions in one function, the limit is 65536"),
+            ),
+        ];
+
+        for (input, expected) in tests.into_iter() {
+            let lex = Lexer::new(input.clone().into());
+            let mut parser = Parser::new(lex);
+            let program = parser.parse_program();
+            assert!(parser.errors().is_empty(), "input: {:.40}", input);
+            let error = Compiler::new()
+                .compile(&program)
+                .err()
+                .map(|e| e.to_string());
+            assert_eq!(error.as_deref(), expected, "input: {:.40}", input);
+        }
+    }
+
+    #[test]
+    fn test_failed_compile_closes_scopes() {
+        let mut compiler = Compiler::new();
+        for input in [
+            "for (x in [1]) { fn() { const c = 1; c = 2 } }",
+            "let y = 1;",
+        ] {
+            let lex = Lexer::new(input.into());
+            let mut parser = Parser::new(lex);
+            let program = parser.parse_program();
+            let _ = compiler.compile(&program);
+        }
+        assert_eq!(compiler.symbol_table.resolve("x"), None);
+        assert_eq!(
+            compiler.symbol_table.resolve("y").map(|s| s.scope),
+            Some(SymbolScope::Global)
+        );
+    }
+
+    #[test]
+    fn test_constants_are_reused() {
+        let bytecode = compile(r#"1 + 1; "a"; "a"; 1.5; 1.5"#.into());
+        assert_eq!(
+            bytecode.constants,
+            vec![
+                Object::Integer(1),
+                Object::String(String::from("a")),
+                Object::Float(1.5),
+                Object::Float(1.5),
+            ]
+        );
+    }
+}
@@ -0,0 +1,4 @@
+pub mod code;
+#[allow(clippy::module_inception)]
+pub mod compiler;
+pub mod symbol_table;
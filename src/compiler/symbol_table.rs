@@ -0,0 +1,320 @@
+use std::collections::HashMap;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SymbolScope {
+    Global,
+    Local,
+    Free,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Symbol {
+    pub name: String,
+    pub scope: SymbolScope,
+    pub index: usize,
+    /// Declared with `const`, so it cannot be assigned to.
+    pub constant: bool,
+}
+
+impl Symbol {
+    pub fn new(name: String, scope: SymbolScope, index: usize) -> Self {
+        Symbol {
+            name,
+            scope,
+            index,
+            constant: false,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct SymbolTable {
+    pub outer: Option<Box<SymbolTable>>,
+    store: HashMap<String, Symbol>,
+    pub num_definitions: usize,
+    /// Symbols of the enclosing scope captured by this one, in the order they were first
+    /// resolved. The index of a `Free` symbol points into this list.
+    pub free_symbols: Vec<Symbol>,
+    /// For each block entered in this scope, innermost last, the names bound in it and the
+    /// symbols they shadow.
+    blocks: Vec<HashMap<String, Option<Symbol>>>,
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        SymbolTable::default()
+    }
+
+    pub fn new_enclosed(outer: SymbolTable) -> Self {
+        SymbolTable {
+            outer: Some(Box::new(outer)),
+            ..SymbolTable::default()
+        }
+    }
+
+    /// Binds `name` in this scope. Redefining a name that already lives in this scope
+    /// reuses its slot, matching how `let` overwrites a binding in the tree walker.
+    pub fn define(&mut self, name: &str) -> Symbol {
+        self.bind(name, false)
+    }
+
+    /// Binds `name` in this scope as a constant, see `define`.
+    pub fn define_const(&mut self, name: &str) -> Symbol {
+        self.bind(name, true)
+    }
+
+    /// Whether `name` was declared with `const` in this scope itself. A block may shadow
+    /// the constants around it.
+    pub fn is_constant(&self, name: &str) -> bool {
+        if self.blocks.last().is_some_and(|b| !b.contains_key(name)) {
+            return false;
+        }
+        self.store
+            .get(name)
+            .is_some_and(|s| s.constant && s.scope != SymbolScope::Free)
+    }
+
+    fn bind(&mut self, name: &str, constant: bool) -> Symbol {
+        let scope = match self.outer {
+            Some(_) => SymbolScope::Local,
+            None => SymbolScope::Global,
+        };
+        // a name from outside the current block is shadowed rather than overwritten
+        let shadows = match self.blocks.last_mut() {
+            Some(block) if !block.contains_key(name) => {
+                block.insert(name.to_owned(), self.store.get(name).cloned());
+                true
+            }
+            _ => false,
+        };
+        if let Some(existing) = self.store.get_mut(name).filter(|_| !shadows) {
+            if existing.scope == scope {
+                existing.constant = constant;
+                return existing.clone();
+            }
+        }
+        self.insert_new(name, scope, constant)
+    }
+
+    fn insert_new(&mut self, name: &str, scope: SymbolScope, constant: bool) -> Symbol {
+        let symbol = Symbol {
+            constant,
+            ..Symbol::new(name.to_owned(), scope, self.num_definitions)
+        };
+        self.num_definitions += 1;
+        self.store.insert(name.to_owned(), symbol.clone());
+        symbol
+    }
+
+    /// Binds `name` in the outermost scope, outside of any block.
+    pub fn define_global(&mut self, name: &str) -> Symbol {
+        match &mut self.outer {
+            Some(outer) => outer.define_global(name),
+            None => self.insert_new(name, SymbolScope::Global, false),
+        }
+    }
+
+    /// Leaves the functions and blocks a failed compile stopped in, keeping the globals.
+    pub fn close_open_scopes(&mut self) {
+        while let Some(outer) = self.outer.take() {
+            *self = *outer;
+        }
+        while !self.blocks.is_empty() {
+            self.leave_block();
+        }
+    }
+
+    /// Starts a block, whose bindings go out of scope at `leave_block`.
+    pub fn enter_block(&mut self) {
+        self.blocks.push(HashMap::new());
+    }
+
+    /// Ends the innermost block and brings back the bindings it shadowed. The slots of the
+    /// block's bindings are not reused.
+    pub fn leave_block(&mut self) {
+        let block = self
+            .blocks
+            .pop()
+            .expect("leave_block is paired with enter_block");
+        for (name, shadowed) in block.into_iter() {
+            match shadowed {
+                Some(symbol) => self.store.insert(name, symbol),
+                None => self.store.remove(&name),
+            };
+        }
+    }
+
+    pub fn resolve(&mut self, name: &str) -> Option<Symbol> {
+        if let Some(symbol) = self.store.get(name) {
+            return Some(symbol.clone());
+        }
+        let symbol = self.outer.as_mut()?.resolve(name)?;
+        match symbol.scope {
+            SymbolScope::Global => Some(symbol),
+            _ => Some(self.define_free(symbol)),
+        }
+    }
+
+    /// The names of the global bindings, indexed by their slot.
+    pub fn global_names(&self) -> Vec<String> {
+        match &self.outer {
+            Some(outer) => outer.global_names(),
+            None => {
+                let mut names = vec![String::new(); self.num_definitions];
+                for symbol in self.store.values() {
+                    names[symbol.index] = symbol.name.to_owned();
+                }
+                names
+            }
+        }
+    }
+
+    fn define_free(&mut self, original: Symbol) -> Symbol {
+        let symbol = Symbol {
+            constant: original.constant,
+            ..Symbol::new(
+                original.name.to_owned(),
+                SymbolScope::Free,
+                self.free_symbols.len(),
+            )
+        };
+        self.free_symbols.push(original);
+        self.store.insert(symbol.name.to_owned(), symbol.clone());
+        symbol
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Symbol, SymbolScope, SymbolTable};
+
+    #[test]
+    fn test_define_and_resolve() {
+        let mut global = SymbolTable::new();
+        assert_eq!(
+            global.define("a"),
+            Symbol::new(String::from("a"), SymbolScope::Global, 0)
+        );
+        assert_eq!(
+            global.define("b"),
+            Symbol::new(String::from("b"), SymbolScope::Global, 1)
+        );
+        // redefining reuses the slot
+        assert_eq!(
+            global.define("a"),
+            Symbol::new(String::from("a"), SymbolScope::Global, 0)
+        );
+
+        let mut local = SymbolTable::new_enclosed(global);
+        assert_eq!(
+            local.define("c"),
+            Symbol::new(String::from("c"), SymbolScope::Local, 0)
+        );
+        assert_eq!(
+            local.resolve("a"),
+            Some(Symbol::new(String::from("a"), SymbolScope::Global, 0))
+        );
+        assert_eq!(local.resolve("d"), None);
+    }
+
+    #[test]
+    fn test_resolve_free() {
+        let mut global = SymbolTable::new();
+        global.define("a");
+        let mut first = SymbolTable::new_enclosed(global);
+        first.define("b");
+        first.define("c");
+        let mut second = SymbolTable::new_enclosed(first);
+        second.define("d");
+
+        struct Test {
+            name: &'static str,
+            expected: Symbol,
+        }
+        let tests = vec![
+            Test {
+                name: "a",
+                expected: Symbol::new(String::from("a"), SymbolScope::Global, 0),
+            },
+            Test {
+                name: "c",
+                expected: Symbol::new(String::from("c"), SymbolScope::Free, 0),
+            },
+            Test {
+                name: "b",
+                expected: Symbol::new(String::from("b"), SymbolScope::Free, 1),
+            },
+            Test {
+                name: "d",
+                expected: Symbol::new(String::from("d"), SymbolScope::Local, 0),
+            },
+        ];
+
+        for test in tests.into_iter() {
+            assert_eq!(second.resolve(test.name), Some(test.expected));
+        }
+        assert_eq!(
+            second.free_symbols,
+            vec![
+                Symbol::new(String::from("c"), SymbolScope::Local, 1),
+                Symbol::new(String::from("b"), SymbolScope::Local, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_constants() {
+        let mut global = SymbolTable::new();
+        global.define_const("a");
+        global.define("b");
+        assert!(global.is_constant("a"));
+        assert!(!global.is_constant("b"));
+
+        let mut local = SymbolTable::new_enclosed(global);
+        assert!(local.resolve("a").is_some_and(|s| s.constant));
+        assert!(!local.is_constant("a"));
+        local.define_const("c");
+        let mut inner = SymbolTable::new_enclosed(local);
+        let captured = inner.resolve("c").unwrap();
+        assert_eq!(captured.scope, SymbolScope::Free);
+        assert!(captured.constant);
+        assert!(!inner.is_constant("c"));
+    }
+
+    #[test]
+    fn test_blocks() {
+        let mut global = SymbolTable::new();
+        global.define_const("a");
+        global.enter_block();
+        assert!(!global.is_constant("a"));
+        assert_eq!(
+            global.define("a"),
+            Symbol::new(String::from("a"), SymbolScope::Global, 1)
+        );
+        assert_eq!(
+            global.define("a"),
+            Symbol::new(String::from("a"), SymbolScope::Global, 1)
+        );
+        global.define("b");
+        global.leave_block();
+
+        assert!(global.is_constant("a"));
+        assert_eq!(global.resolve("a").map(|s| s.index), Some(0));
+        assert_eq!(global.resolve("b"), None);
+        assert_eq!(global.define("b").index, 3);
+    }
+
+    #[test]
+    fn test_define_global_from_nested_scope() {
+        let mut local = SymbolTable::new_enclosed(SymbolTable::new());
+        assert_eq!(
+            local.define_global("x"),
+            Symbol::new(String::from("x"), SymbolScope::Global, 0)
+        );
+        assert_eq!(
+            local.resolve("x"),
+            Some(Symbol::new(String::from("x"), SymbolScope::Global, 0))
+        );
+        assert_eq!(local.global_names(), vec![String::from("x")]);
+    }
+}
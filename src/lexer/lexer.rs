@@ -1,28 +1,56 @@
 use std::fmt::Display;
 
 #[allow(dead_code)]
-#[derive(Debug, PartialEq, Clone, Eq, Hash)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum Token {
     Illegal,
     EOF,
 
     Ident(String),
     Int(isize),
+    Float(f64),
     String(String),
+    /// A string containing `${...}`, split into its text and the source of each embedded
+    /// expression.
+    InterpolatedString(Vec<StringSegment>),
+    /// The text of a `//` or `/* */` comment, only produced by `Lexer::with_comments`.
+    Comment(String),
+    /// Input that could not be lexed, like an unterminated string, with the reason.
+    Error(String),
 
     Assign,
+    PlusAssign,
+    MinusAssign,
+    AsteriskAssign,
+    SlashAssign,
+    PercentAssign,
     Plus,
     Minus,
     Bang,
     Asterisk,
     Slash,
+    Percent,
+    Power,
     GreaterThan,
     LessThan,
+    GreaterEqual,
+    LessEqual,
     Equal,
     NotEqual,
+    Ampersand,
+    Pipe,
+    Pipeline,
+    Caret,
+    And,
+    Or,
+    ShiftLeft,
+    ShiftRight,
 
     Comma,
     Semicolon,
+    Colon,
+    FatArrow,
+    Ellipsis,
 
     LParen,
     RParen,
@@ -33,13 +61,21 @@ pub enum Token {
 
     Function,
     Let,
+    Const,
     If,
     Else,
     Return,
     True,
     False,
+    While,
+    For,
+    In,
+    Break,
+    Continue,
+    Match,
 }
 
+#[allow(clippy::needless_return)]
 impl Display for Token {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         return match self {
@@ -48,21 +84,45 @@ impl Display for Token {
 
             Token::Ident(s) => write!(f, "Ident: {}", s),
             Token::Int(s) => write!(f, "Int: {}", s),
+            Token::Float(s) => write!(f, "Float: {:?}", s),
             Token::String(s) => write!(f, "String: {}", s),
+            Token::InterpolatedString(_) => write!(f, "Interpolated String"),
+            Token::Comment(s) => write!(f, "Comment: {}", s),
+            Token::Error(s) => write!(f, "Error: {}", s),
 
             Token::Assign => write!(f, "Assign"),
+            Token::PlusAssign => write!(f, "PlusAssign"),
+            Token::MinusAssign => write!(f, "MinusAssign"),
+            Token::AsteriskAssign => write!(f, "AsteriskAssign"),
+            Token::SlashAssign => write!(f, "SlashAssign"),
+            Token::PercentAssign => write!(f, "PercentAssign"),
             Token::Plus => write!(f, "Plus"),
             Token::Minus => write!(f, "Minus"),
             Token::Bang => write!(f, "Bang"),
             Token::Asterisk => write!(f, "Asterisk"),
             Token::Slash => write!(f, "Slash"),
+            Token::Percent => write!(f, "Percent"),
+            Token::Power => write!(f, "Power"),
             Token::GreaterThan => write!(f, "GreaterThan"),
             Token::LessThan => write!(f, "LessThan"),
+            Token::GreaterEqual => write!(f, "GreaterEqual"),
+            Token::LessEqual => write!(f, "LessEqual"),
             Token::Equal => write!(f, "Equal"),
             Token::NotEqual => write!(f, "Not Equal"),
+            Token::Ampersand => write!(f, "Ampersand"),
+            Token::Pipe => write!(f, "Pipe"),
+            Token::Pipeline => write!(f, "Pipeline"),
+            Token::Caret => write!(f, "Caret"),
+            Token::And => write!(f, "And"),
+            Token::Or => write!(f, "Or"),
+            Token::ShiftLeft => write!(f, "ShiftLeft"),
+            Token::ShiftRight => write!(f, "ShiftRight"),
 
             Token::Comma => write!(f, "Commma"),
             Token::Semicolon => write!(f, "Semicolin"),
+            Token::Colon => write!(f, "Colon"),
+            Token::FatArrow => write!(f, "FatArrow"),
+            Token::Ellipsis => write!(f, "Ellipsis"),
 
             Token::LParen => write!(f, "Left Paran"),
             Token::RParen => write!(f, "Right Paran"),
@@ -73,51 +133,145 @@ impl Display for Token {
 
             Token::Function => write!(f, "Function"),
             Token::Let => write!(f, "Let"),
+            Token::Const => write!(f, "Const"),
             Token::If => write!(f, "If"),
             Token::Else => write!(f, "Else"),
             Token::Return => write!(f, "Return"),
             Token::True => write!(f, "True"),
             Token::False => write!(f, "False"),
+            Token::While => write!(f, "While"),
+            Token::For => write!(f, "For"),
+            Token::In => write!(f, "In"),
+            Token::Break => write!(f, "Break"),
+            Token::Continue => write!(f, "Continue"),
+            Token::Match => write!(f, "Match"),
         };
     }
 }
 
+/// A position in the source, both line and column start at 1.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    pub fn new(line: usize, column: usize) -> Self {
+        Span { line, column }
+    }
+}
+
+impl Display for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
+
+/// A piece of an interpolated string.
+#[derive(Debug, PartialEq, Clone)]
+pub enum StringSegment {
+    Text(String),
+    /// The source between `${` and `}`, and where it starts.
+    Code(String, Span),
+}
+
+/// A token along with where it starts in the source.
+#[derive(Debug, PartialEq, Clone)]
+pub struct SpannedToken {
+    pub token: Token,
+    pub span: Span,
+}
+
+impl SpannedToken {
+    pub fn new(token: Token, span: Span) -> Self {
+        SpannedToken { token, span }
+    }
+}
+
 pub struct Lexer {
     input: Vec<u8>,
     position: usize,
     read_position: usize,
     ch: u8,
+    line: usize,
+    column: usize,
+    keep_comments: bool,
 }
 
+#[allow(clippy::needless_return)]
 impl Lexer {
     pub fn new(input: Vec<u8>) -> Lexer {
+        return Lexer::starting_at(input, Span::new(1, 1));
+    }
+
+    /// A lexer for input that is part of a larger source, like the code embedded in an
+    /// interpolated string, so its spans point into that source.
+    pub fn starting_at(input: Vec<u8>, span: Span) -> Lexer {
         let mut lex = Lexer {
             input,
             position: 0,
             read_position: 0,
             ch: 0,
+            line: span.line,
+            column: span.column - 1,
+            keep_comments: false,
         };
         lex.read_char();
         return lex;
     }
 
-    pub fn next_token(&mut self) -> Token {
-        self.skip_whitespace();
+    /// A lexer that returns comments as `Token::Comment` instead of skipping them.
+    pub fn with_comments(input: Vec<u8>) -> Lexer {
+        let mut lex = Lexer::new(input);
+        lex.keep_comments = true;
+        return lex;
+    }
+
+    pub fn next_token(&mut self) -> SpannedToken {
+        loop {
+            self.skip_whitespace();
+            let span = Span::new(self.line, self.column);
+            match self.read_token() {
+                Token::Comment(_) if !self.keep_comments => continue,
+                token => return SpannedToken::new(token, span),
+            }
+        }
+    }
 
+    fn read_token(&mut self) -> Token {
         let token = match self.ch {
-            b'=' => {
-                if self.peek_char() == b'=' {
+            b'=' => match self.peek_char() {
+                b'=' => {
                     self.read_char();
                     Token::Equal
-                } else {
-                    Token::Assign
                 }
+                b'>' => {
+                    self.read_char();
+                    Token::FatArrow
+                }
+                _ => Token::Assign,
+            },
+            b'.' if self.peek_char() == b'.'
+                && self.input.get(self.read_position + 1) == Some(&b'.') =>
+            {
+                self.read_char();
+                self.read_char();
+                Token::Ellipsis
             }
             b';' => Token::Semicolon,
+            b':' => Token::Colon,
             b'(' => Token::LParen,
             b')' => Token::RParen,
             b',' => Token::Comma,
-            b'+' => Token::Plus,
+            b'+' => {
+                if self.peek_char() == b'=' {
+                    self.read_char();
+                    Token::PlusAssign
+                } else {
+                    Token::Plus
+                }
+            }
             b'{' => Token::LBrace,
             b'}' => Token::RBrace,
             b'[' => Token::LBracket,
@@ -130,28 +284,96 @@ impl Lexer {
                     Token::Bang
                 }
             }
-            b'-' => Token::Minus,
-            b'*' => Token::Asterisk,
-            b'/' => Token::Slash,
-            b'<' => Token::LessThan,
-            b'>' => Token::GreaterThan,
-            0 => Token::EOF,
-            b'\'' | b'"' => {
-                let string = self.read_string();
-                return Token::String(string);
+            b'-' => {
+                if self.peek_char() == b'=' {
+                    self.read_char();
+                    Token::MinusAssign
+                } else {
+                    Token::Minus
+                }
             }
+            b'*' => match self.peek_char() {
+                b'*' => {
+                    self.read_char();
+                    Token::Power
+                }
+                b'=' => {
+                    self.read_char();
+                    Token::AsteriskAssign
+                }
+                _ => Token::Asterisk,
+            },
+            b'%' => {
+                if self.peek_char() == b'=' {
+                    self.read_char();
+                    Token::PercentAssign
+                } else {
+                    Token::Percent
+                }
+            }
+            b'&' => {
+                if self.peek_char() == b'&' {
+                    self.read_char();
+                    Token::And
+                } else {
+                    Token::Ampersand
+                }
+            }
+            b'|' => match self.peek_char() {
+                b'|' => {
+                    self.read_char();
+                    Token::Or
+                }
+                b'>' => {
+                    self.read_char();
+                    Token::Pipeline
+                }
+                _ => Token::Pipe,
+            },
+            b'^' => Token::Caret,
+            b'/' => match self.peek_char() {
+                b'/' => return self.read_line_comment(),
+                b'*' => return self.read_block_comment(),
+                b'=' => {
+                    self.read_char();
+                    Token::SlashAssign
+                }
+                _ => Token::Slash,
+            },
+            b'<' => match self.peek_char() {
+                b'=' => {
+                    self.read_char();
+                    Token::LessEqual
+                }
+                b'<' => {
+                    self.read_char();
+                    Token::ShiftLeft
+                }
+                _ => Token::LessThan,
+            },
+            b'>' => match self.peek_char() {
+                b'=' => {
+                    self.read_char();
+                    Token::GreaterEqual
+                }
+                b'>' => {
+                    self.read_char();
+                    Token::ShiftRight
+                }
+                _ => Token::GreaterThan,
+            },
+            0 => Token::EOF,
+            b'\'' | b'"' => return self.read_string(),
+            b'r' if matches!(self.peek_char(), b'"' | b'#') => return self.read_raw_string(),
             b'a'..=b'z' | b'A'..=b'Z' | b'_' => {
                 let ident: String = self.read_indetifier();
                 return self.look_up_ident(ident);
             }
             b'0'..=b'9' => {
-                return Token::Int(self.read_number());
+                return self.read_number();
             }
 
-            _ => {
-                println!("char: {:?}", self.ch as char);
-                Token::Illegal
-            }
+            _ => Token::Illegal,
         };
 
         self.read_char();
@@ -159,6 +381,11 @@ impl Lexer {
     }
 
     fn read_char(&mut self) {
+        if self.ch == b'\n' {
+            self.line += 1;
+            self.column = 0;
+        }
+        self.column += 1;
         if self.read_position >= self.input.len() {
             self.ch = 0;
         } else {
@@ -172,33 +399,241 @@ impl Lexer {
         return match ident.as_str() {
             "fn" => Token::Function,
             "let" => Token::Let,
+            "const" => Token::Const,
             "return" => Token::Return,
             "if" => Token::If,
             "else" => Token::Else,
             "true" => Token::True,
             "false" => Token::False,
+            "while" => Token::While,
+            "for" => Token::For,
+            "in" => Token::In,
+            "break" => Token::Break,
+            "continue" => Token::Continue,
+            "match" => Token::Match,
             _ => Token::Ident(ident),
         };
     }
 
     fn read_indetifier(&mut self) -> String {
         let position = self.position;
-        while self.ch.is_ascii_alphabetic() {
+        while self.ch.is_ascii_alphabetic() || self.ch == b'_' {
             self.read_char();
         }
         let buf = &self.input[position..self.position];
         return String::from_utf8_lossy(buf).into_owned();
     }
 
-    fn read_string(&mut self) -> String {
+    /// Reads a string closed by the same quote it was opened with, resolving escapes. The
+    /// string may span lines, and a string containing `${...}` is read as an
+    /// `InterpolatedString`. A string opened with `"""` is closed by `"""`, so it can hold
+    /// quotes without escaping them.
+    fn read_string(&mut self) -> Token {
+        let quote = self.ch;
+        let triple = quote == b'"' && self.peek_char() == b'"' && self.third_char() == b'"';
+        if triple {
+            self.read_char();
+            self.read_char();
+        }
+        let mut buf: Vec<u8> = vec![];
+        let mut segments: Vec<StringSegment> = vec![];
+        let mut error: Option<String> = None;
+        self.read_char();
+        while self.ch != quote || (triple && (self.peek_char(), self.third_char()) != (b'"', b'"'))
+        {
+            match self.ch {
+                0 => return Token::Error(String::from("Unterminated string")),
+                b'$' if self.peek_char() == b'{' => {
+                    if !buf.is_empty() {
+                        let text = String::from_utf8_lossy(&buf).into_owned();
+                        segments.push(StringSegment::Text(text));
+                        buf.clear();
+                    }
+                    match self.read_interpolation() {
+                        Ok(segment) => segments.push(segment),
+                        Err(e) => return Token::Error(e),
+                    }
+                }
+                b'\\' => {
+                    self.read_char();
+                    match self.read_escape() {
+                        Ok(c) => buf.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+                        // keep reading to the closing quote so the rest of the string is
+                        // not lexed as code
+                        Err(e) => error = error.or(Some(e)),
+                    }
+                }
+                c => buf.push(c),
+            }
+            self.read_char();
+        }
+        if triple {
+            self.read_char();
+            self.read_char();
+        }
         self.read_char();
+
+        if let Some(e) = error {
+            return Token::Error(e);
+        }
+        let text = String::from_utf8_lossy(&buf).into_owned();
+        if segments.is_empty() {
+            return Token::String(text);
+        }
+        if !text.is_empty() {
+            segments.push(StringSegment::Text(text));
+        }
+        Token::InterpolatedString(segments)
+    }
+
+    /// Reads the code of a `${...}` up to its matching `}`, leaving `ch` on the `}`. Braces
+    /// and strings inside the code are skipped over.
+    fn read_interpolation(&mut self) -> Result<StringSegment, String> {
+        self.read_char();
+        self.read_char();
+        let span = Span::new(self.line, self.column);
         let position = self.position;
-        while self.ch != b'\'' && self.ch != b'"' && self.ch != 0 {
+        let mut depth = 1;
+        loop {
+            match self.ch {
+                0 => return Err(String::from("Unterminated interpolation")),
+                b'{' => depth += 1,
+                b'}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                b'\'' | b'"' => {
+                    let quote = self.ch;
+                    self.read_char();
+                    while self.ch != quote {
+                        match self.ch {
+                            0 => return Err(String::from("Unterminated interpolation")),
+                            b'\\' => self.read_char(),
+                            _ => {}
+                        }
+                        self.read_char();
+                    }
+                }
+                _ => {}
+            }
             self.read_char();
         }
-        let end_postition = self.position;
+        let code = String::from_utf8_lossy(&self.input[position..self.position]).into_owned();
+        Ok(StringSegment::Code(code, span))
+    }
+
+    /// Reads the escape after a backslash, leaving `ch` on its last character.
+    fn read_escape(&mut self) -> Result<char, String> {
+        return match self.ch {
+            b'n' => Ok('\n'),
+            b't' => Ok('\t'),
+            b'\\' => Ok('\\'),
+            b'"' => Ok('"'),
+            b'\'' => Ok('\''),
+            b'$' => Ok('$'),
+            b'u' => {
+                if self.peek_char() != b'{' {
+                    return Err(String::from("Invalid unicode escape: expected {"));
+                }
+                self.read_char();
+                let mut digits = String::new();
+                while self.peek_char().is_ascii_hexdigit() {
+                    self.read_char();
+                    digits.push(self.ch as char);
+                }
+                if self.peek_char() != b'}' {
+                    return Err(format!("Invalid unicode escape: \\u{{{}", digits));
+                }
+                self.read_char();
+                u32::from_str_radix(&digits, 16)
+                    .ok()
+                    .filter(|_| digits.len() <= 6)
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| format!("Invalid unicode escape: \\u{{{}}}", digits))
+            }
+            0 => Err(String::from("Unterminated string")),
+            c => Err(format!("Unknown escape sequence: \\{}", c as char)),
+        };
+    }
+
+    /// Reads `r"..."` or `r#"..."#`, kept as written without escapes. The `#`s let the
+    /// string contain `"`, it ends at a quote followed by as many `#`s as it started with.
+    fn read_raw_string(&mut self) -> Token {
         self.read_char();
-        String::from_utf8_lossy(&self.input[position..end_postition]).into_owned()
+        let mut hashes = 0;
+        while self.ch == b'#' {
+            hashes += 1;
+            self.read_char();
+        }
+        if self.ch != b'"' {
+            return Token::Error(String::from("Expected \" to start raw string"));
+        }
+        self.read_char();
+
+        let position = self.position;
+        loop {
+            match self.ch {
+                0 => return Token::Error(String::from("Unterminated raw string")),
+                b'"' if self.closes_raw_string(hashes) => {
+                    let end_position = self.position;
+                    for _ in 0..=hashes {
+                        self.read_char();
+                    }
+                    return Token::String(
+                        String::from_utf8_lossy(&self.input[position..end_position]).into_owned(),
+                    );
+                }
+                _ => self.read_char(),
+            }
+        }
+    }
+
+    fn closes_raw_string(&self, hashes: usize) -> bool {
+        (1..=hashes).all(|i| self.input.get(self.position + i) == Some(&b'#'))
+    }
+
+    fn read_line_comment(&mut self) -> Token {
+        self.read_char();
+        self.read_char();
+        let position = self.position;
+        while self.ch != b'\n' && self.ch != 0 {
+            self.read_char();
+        }
+        Token::Comment(String::from_utf8_lossy(&self.input[position..self.position]).into_owned())
+    }
+
+    /// Reads a `/* */` comment, which may contain other block comments.
+    fn read_block_comment(&mut self) -> Token {
+        self.read_char();
+        self.read_char();
+        let position = self.position;
+        let mut depth = 1;
+        loop {
+            match (self.ch, self.peek_char()) {
+                (0, _) => return Token::Error(String::from("Unterminated block comment")),
+                (b'/', b'*') => {
+                    depth += 1;
+                    self.read_char();
+                }
+                (b'*', b'/') => {
+                    depth -= 1;
+                    if depth == 0 {
+                        let end_position = self.position;
+                        self.read_char();
+                        self.read_char();
+                        return Token::Comment(
+                            String::from_utf8_lossy(&self.input[position..end_position])
+                                .into_owned(),
+                        );
+                    }
+                    self.read_char();
+                }
+                _ => {}
+            }
+            self.read_char();
+        }
     }
 
     fn skip_whitespace(&mut self) {
@@ -207,30 +642,64 @@ impl Lexer {
         }
     }
 
-    fn read_number(&mut self) -> isize {
+    /// Reads an integer, or a float when the digits are followed by a fraction like `1.5`
+    /// or an exponent like `1e-3`.
+    fn read_number(&mut self) -> Token {
         let position = self.position;
-        while self.ch.is_ascii_digit() {
+        let mut is_float = false;
+        self.read_digits();
+        if self.ch == b'.' && self.peek_char().is_ascii_digit() {
+            is_float = true;
             self.read_char();
+            self.read_digits();
+        }
+        if matches!(self.ch, b'e' | b'E') {
+            let sign = matches!(self.peek_char(), b'+' | b'-') as usize;
+            let digit = self.input.get(self.read_position + sign).copied();
+            if digit.is_some_and(|d| d.is_ascii_digit()) {
+                is_float = true;
+                for _ in 0..=sign {
+                    self.read_char();
+                }
+                self.read_digits();
+            }
         }
         let buf = &self.input[position..self.position];
         let string = String::from_utf8_lossy(buf).to_string();
-        string.parse().unwrap()
+        if is_float {
+            return Token::Float(string.parse().unwrap());
+        }
+        match string.parse() {
+            Ok(int) => Token::Int(int),
+            Err(_) => Token::Error(format!("Integer literal is too large: {}", string)),
+        }
+    }
+
+    fn read_digits(&mut self) {
+        while self.ch.is_ascii_digit() {
+            self.read_char();
+        }
     }
 
-    fn peek_char(&mut self) -> u8 {
+    fn peek_char(&self) -> u8 {
         if self.read_position >= self.input.len() {
             return 0;
         } else {
             return self.input[self.read_position];
         }
     }
+
+    /// The character after the one `peek_char` returns.
+    fn third_char(&self) -> u8 {
+        self.input.get(self.read_position + 1).copied().unwrap_or(0)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use anyhow::Result;
 
-    use super::{Lexer, Token};
+    use super::{Lexer, Span, StringSegment, Token};
     #[test]
     fn test_string_token() -> Result<()> {
         let input: Vec<u8> = r#""Hello World""#.into();
@@ -239,13 +708,14 @@ mod tests {
         let tokens = vec![Token::String(String::from("Hello World"))];
 
         for token in tokens.into_iter() {
-            let tok = lex.next_token();
+            let tok = lex.next_token().token;
             println!("Expected:  {:?}, Got: {:?}", token, tok);
             assert_eq!(token, tok);
         }
         Ok(())
     }
     #[test]
+    #[allow(clippy::needless_return)]
     fn test_next_token() -> Result<()> {
         let input = r#"let five = 5;
             let ten = 10;
@@ -253,7 +723,7 @@ mod tests {
                 x + y;
             };
             let result = add(five, ten);
-            !-/*5;
+            !-/ *5;
             5 < 10 > 5;
             
             if (5 < 10) {
@@ -265,6 +735,7 @@ mod tests {
 10 != 9;
 "Hello World";
 [1,2];
+{"foo": "bar"}
             "#;
         let mut lexer = Lexer::new(input.into());
 
@@ -350,13 +821,283 @@ mod tests {
             Token::Int(2),
             Token::RBracket,
             Token::Semicolon,
+            Token::LBrace,
+            Token::String(String::from("foo")),
+            Token::Colon,
+            Token::String(String::from("bar")),
+            Token::RBrace,
+            Token::EOF,
         ];
 
         for token in tokens.into_iter() {
-            let tok = lexer.next_token();
+            let tok = lexer.next_token().token;
             println!("Expected:  {:?}, Got: {:?}", token, tok);
             assert_eq!(token, tok);
         }
         return Ok(());
     }
+
+    #[test]
+    fn test_token_spans() {
+        let input = "let x = 5;\n  x + 10;";
+        let mut lexer = Lexer::new(input.into());
+
+        let expected = vec![
+            (Token::Let, 1, 1),
+            (Token::Ident(String::from("x")), 1, 5),
+            (Token::Assign, 1, 7),
+            (Token::Int(5), 1, 9),
+            (Token::Semicolon, 1, 10),
+            (Token::Ident(String::from("x")), 2, 3),
+            (Token::Plus, 2, 5),
+            (Token::Int(10), 2, 7),
+            (Token::Semicolon, 2, 9),
+            (Token::EOF, 2, 10),
+        ];
+
+        for (token, line, column) in expected.into_iter() {
+            let tok = lexer.next_token();
+            assert_eq!(tok.token, token);
+            assert_eq!(tok.span, Span::new(line, column));
+        }
+
+        // the same token at another position has a different span
+        let mut lexer = Lexer::new("x\n  x".into());
+        let first = lexer.next_token();
+        let second = lexer.next_token();
+        assert_eq!(first.token, second.token);
+        assert_ne!(first.span, second.span);
+    }
+
+    #[test]
+    fn test_comments() {
+        let input = "let a = 1; // the first\n/* a /* nested */ block */ a / 2 /* open";
+
+        let mut lexer = Lexer::new(input.into());
+        let expected = vec![
+            Token::Let,
+            Token::Ident(String::from("a")),
+            Token::Assign,
+            Token::Int(1),
+            Token::Semicolon,
+            Token::Ident(String::from("a")),
+            Token::Slash,
+            Token::Int(2),
+            Token::Error(String::from("Unterminated block comment")),
+            Token::EOF,
+        ];
+        for token in expected.into_iter() {
+            assert_eq!(lexer.next_token().token, token);
+        }
+
+        let mut lexer = Lexer::with_comments(input.into());
+        let expected = vec![
+            (Token::Semicolon, 1, 10),
+            (Token::Comment(String::from(" the first")), 1, 12),
+            (Token::Comment(String::from(" a /* nested */ block ")), 2, 1),
+            (Token::Ident(String::from("a")), 2, 28),
+        ];
+        for _ in 0..4 {
+            lexer.next_token();
+        }
+        for (token, line, column) in expected.into_iter() {
+            let tok = lexer.next_token();
+            assert_eq!(tok.token, token);
+            assert_eq!((tok.span.line, tok.span.column), (line, column));
+        }
+    }
+
+    #[test]
+    fn test_string_literals() {
+        let tests = vec![
+            (r#""it's""#, Token::String(String::from("it's"))),
+            (r#"'say "hi"'"#, Token::String(String::from("say \"hi\""))),
+            (
+                r#""a\nb\tc\\d\"e\'f""#,
+                Token::String(String::from("a\nb\tc\\d\"e'f")),
+            ),
+            (
+                r#""\u{48}\u{e9}\u{1F600}""#,
+                Token::String(String::from("Hé😀")),
+            ),
+            ("\"two\nlines\"", Token::String(String::from("two\nlines"))),
+            (
+                "\"\"\"say \"hi\",\n  \"\"twice\"\" \\u{21}\"\"\"",
+                Token::String(String::from("say \"hi\",\n  \"\"twice\"\" !")),
+            ),
+            ("\"\"\"\"\"\"", Token::String(String::new())),
+            ("\"\"", Token::String(String::new())),
+            (
+                "\"\"\"a\"\"",
+                Token::Error(String::from("Unterminated string")),
+            ),
+            (r#"r"C:\path\n""#, Token::String(String::from(r"C:\path\n"))),
+            (
+                r###"r##"<a href="#">"##"###,
+                Token::String(String::from(r##"<a href="#">"##)),
+            ),
+            ("\"open", Token::Error(String::from("Unterminated string"))),
+            (
+                "r#\"open\"",
+                Token::Error(String::from("Unterminated raw string")),
+            ),
+            (
+                r#""bad \q escape""#,
+                Token::Error(String::from(r"Unknown escape sequence: \q")),
+            ),
+            (
+                r#""\u{110000}""#,
+                Token::Error(String::from(r"Invalid unicode escape: \u{110000}")),
+            ),
+            (
+                r#""\u{41""#,
+                Token::Error(String::from(r"Invalid unicode escape: \u{41")),
+            ),
+        ];
+
+        for (input, expected) in tests.into_iter() {
+            let mut lexer = Lexer::new(input.into());
+            assert_eq!(lexer.next_token().token, expected, "input: {}", input);
+        }
+
+        let mut lexer = Lexer::new(r#""a\q" + r"b""#.into());
+        assert!(matches!(lexer.next_token().token, Token::Error(_)));
+        assert_eq!(lexer.next_token().token, Token::Plus);
+        assert_eq!(lexer.next_token().token, Token::String(String::from("b")));
+    }
+
+    #[test]
+    fn test_interpolated_strings() {
+        let tests = vec![
+            (
+                r#""total: ${a + b}!""#,
+                Token::InterpolatedString(vec![
+                    StringSegment::Text(String::from("total: ")),
+                    StringSegment::Code(String::from("a + b"), Span::new(1, 11)),
+                    StringSegment::Text(String::from("!")),
+                ]),
+            ),
+            (
+                r#""${h["}"]}${ {1: 2}[1] }""#,
+                Token::InterpolatedString(vec![
+                    StringSegment::Code(String::from(r#"h["}"]"#), Span::new(1, 4)),
+                    StringSegment::Code(String::from(" {1: 2}[1] "), Span::new(1, 13)),
+                ]),
+            ),
+            (
+                "\n  \"a${b}\"",
+                Token::InterpolatedString(vec![
+                    StringSegment::Text(String::from("a")),
+                    StringSegment::Code(String::from("b"), Span::new(2, 7)),
+                ]),
+            ),
+            (
+                r#""""x ${a} "y" z""""#,
+                Token::InterpolatedString(vec![
+                    StringSegment::Text(String::from("x ")),
+                    StringSegment::Code(String::from("a"), Span::new(1, 8)),
+                    StringSegment::Text(String::from(" \"y\" z")),
+                ]),
+            ),
+            (
+                r#""cost: \${5} $5""#,
+                Token::String(String::from("cost: ${5} $5")),
+            ),
+            (
+                r#""${a""#,
+                Token::Error(String::from("Unterminated interpolation")),
+            ),
+        ];
+
+        for (input, expected) in tests.into_iter() {
+            let mut lexer = Lexer::new(input.into());
+            assert_eq!(lexer.next_token().token, expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_numbers() {
+        let input = "5 1.5 0.25 1e3 1e-3 2.5E+2 3.foo 4e x 7.";
+        let mut lexer = Lexer::new(input.into());
+
+        let expected = vec![
+            Token::Int(5),
+            Token::Float(1.5),
+            Token::Float(0.25),
+            Token::Float(1000.0),
+            Token::Float(0.001),
+            Token::Float(250.0),
+            Token::Int(3),
+            Token::Illegal,
+            Token::Ident(String::from("foo")),
+            Token::Int(4),
+            Token::Ident(String::from("e")),
+            Token::Ident(String::from("x")),
+            Token::Int(7),
+            Token::Illegal,
+            Token::EOF,
+        ];
+        for token in expected.into_iter() {
+            assert_eq!(lexer.next_token().token, token);
+        }
+
+        let mut lexer = Lexer::new("9223372036854775807 9223372036854775808".into());
+        let expected = vec![
+            Token::Int(isize::MAX),
+            Token::Error(String::from(
+                "Integer literal is too large: 9223372036854775808",
+            )),
+        ];
+        for token in expected.into_iter() {
+            assert_eq!(lexer.next_token().token, token);
+        }
+    }
+
+    #[test]
+    fn test_identifiers() {
+        let mut lexer = Lexer::new("_ snake_case _private match".into());
+        for expected in ["_", "snake_case", "_private"] {
+            assert_eq!(
+                lexer.next_token().token,
+                Token::Ident(String::from(expected))
+            );
+        }
+        assert_eq!(lexer.next_token().token, Token::Match);
+    }
+
+    #[test]
+    fn test_operators() {
+        let input = "a % b ** c <= d >= e & f | g ^ h << i >> j < k > l * m && n || o = p += q -= r *= s /= t %= u => v ... w |> x";
+        let mut lexer = Lexer::new(input.into());
+
+        let expected = vec![
+            Token::Percent,
+            Token::Power,
+            Token::LessEqual,
+            Token::GreaterEqual,
+            Token::Ampersand,
+            Token::Pipe,
+            Token::Caret,
+            Token::ShiftLeft,
+            Token::ShiftRight,
+            Token::LessThan,
+            Token::GreaterThan,
+            Token::Asterisk,
+            Token::And,
+            Token::Or,
+            Token::Assign,
+            Token::PlusAssign,
+            Token::MinusAssign,
+            Token::AsteriskAssign,
+            Token::SlashAssign,
+            Token::PercentAssign,
+            Token::FatArrow,
+            Token::Ellipsis,
+            Token::Pipeline,
+        ];
+        for token in expected.into_iter() {
+            lexer.next_token();
+            assert_eq!(lexer.next_token().token, token);
+        }
+    }
 }
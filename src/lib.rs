@@ -1,5 +1,7 @@
 pub mod ast;
+pub mod compiler;
 pub mod lexer;
 pub mod object;
 pub mod parser;
 pub mod repl;
+pub mod vm;
@@ -1,295 +1,1517 @@
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
-use std::fmt::Display;
+use std::fmt::{Debug, Display};
+use std::rc::Rc;
 
-use crate::ast::ast::{BlockStatement, Expression, Identifier, Statement};
-use crate::lexer::lexer::Token;
-use crate::parser::builtin_functions::{self, BuiltinFunctions};
-use anyhow::{anyhow, Ok, Result};
+use crate::ast::ast::{
+    AssignExpression, BlockStatement, CallExpression, Expression, For, Identifier, IfExpression,
+    InfixExpression, Let, LetPattern, MatchExpression, Parameter, Pattern, Statement, While,
+};
+use crate::compiler::code::Instructions;
+use crate::lexer::lexer::{Span, Token};
+use crate::parser::builtin_functions::BuiltinFunctions;
+use anyhow::{anyhow, Result};
 
-#[derive(PartialEq, Clone, Debug)]
+/// Calls nested deeper than this are reported as an error instead of overflowing the native
+/// stack, the tree walker's counterpart of the vm's frame limit.
+pub const MAX_CALL_DEPTH: usize = 2_000;
+
+/// Native stack a thread evaluating with the tree walker needs to reach `MAX_CALL_DEPTH`,
+/// with room for function bodies that nest deeper than a plain recursive call.
+pub const EVAL_STACK_SIZE: usize = 256 * 1024 * 1024;
+
+/// A scope of bindings. Cloning an `Environment` is cheap and yields a handle to the same
+/// scope, so closures capture their defining scope by reference and see bindings added to it
+/// after they were created.
+#[derive(Clone)]
 pub struct Environment {
-    pub builtin_functions: BuiltinFunctions,
-    pub store: HashMap<String, Object>,
-    pub outer_env: Option<Box<Environment>>,
+    pub builtin_functions: Rc<BuiltinFunctions>,
+    pub store: Rc<RefCell<HashMap<String, Binding>>>,
+    pub outer_env: Option<Rc<Environment>>,
+    /// Calls in progress, shared by all the scopes of one program.
+    pub call_depth: Rc<Cell<usize>>,
+}
+
+/// A value bound to a name, `constant` when it was declared with `const`.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Binding {
+    pub value: Object,
+    pub constant: bool,
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PartialEq for Environment {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.store, &other.store)
+    }
+}
+
+// Functions stored in a scope hold a handle to that same scope, so the derived Debug would
+// recurse forever. Only the names bound in each scope are printed.
+impl Debug for Environment {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut names: Vec<String> = self.store.borrow().keys().cloned().collect();
+        names.sort();
+        f.debug_struct("Environment")
+            .field("store", &names)
+            .field("outer_env", &self.outer_env)
+            .finish()
+    }
 }
 
 impl Environment {
     pub fn new() -> Self {
         Environment {
-            builtin_functions: BuiltinFunctions::setup(),
-            store: HashMap::new(),
+            builtin_functions: Rc::new(BuiltinFunctions::setup()),
+            store: Rc::new(RefCell::new(HashMap::new())),
             outer_env: None,
+            call_depth: Rc::new(Cell::new(0)),
         }
     }
     pub fn new_enclosed_environment(&self) -> Self {
         Environment {
-            builtin_functions: self.builtin_functions.clone(),
-            store: HashMap::new(),
-            outer_env: Some(Box::new(self.to_owned())),
+            builtin_functions: Rc::clone(&self.builtin_functions),
+            store: Rc::new(RefCell::new(HashMap::new())),
+            outer_env: Some(Rc::new(self.clone())),
+            call_depth: Rc::clone(&self.call_depth),
+        }
+    }
+    /// Binds `name` in this scope, replacing an earlier binding unless it is a constant.
+    pub fn set(&self, name: String, value: Object) -> Result<()> {
+        self.bind(name, value, false)
+    }
+    pub fn set_const(&self, name: String, value: Object) -> Result<()> {
+        self.bind(name, value, true)
+    }
+    fn bind(&self, name: String, value: Object, constant: bool) -> Result<()> {
+        let mut store = self.store.borrow_mut();
+        if store.get(&name).is_some_and(|b| b.constant) {
+            return Err(anyhow!("Cannot redeclare constant: {}", name));
         }
+        store.insert(name, Binding { value, constant });
+        Ok(())
     }
+    /// Looks a name up through the whole chain of enclosing scopes, falling back to the
+    /// builtin functions once the outermost scope has been checked.
     pub fn get(&self, name: &String) -> Result<Object> {
-        let obj = self.store.get(name);
-        match obj {
-            Some(o) => return Ok(o.to_owned()),
-            None => {
-                let env_check = if let Some(outer) = &self.outer_env {
-                    outer.store.get(name)
-                } else {
-                    None
+        if let Some(b) = self.store.borrow().get(name) {
+            return Ok(b.value.to_owned());
+        }
+        match &self.outer_env {
+            Some(outer) => outer.get(name),
+            None => match self.builtin_functions.get_fn(name.to_owned()) {
+                Some(b) => Ok(Object::BuiltinFunction(b)),
+                None => Err(anyhow!("Identifier not found: {}", name)),
+            },
+        }
+    }
+    /// Updates the binding in the nearest scope that defines `name`.
+    pub fn assign(&self, name: &String, value: Object) -> Result<()> {
+        if let Some(binding) = self.store.borrow_mut().get_mut(name) {
+            if binding.constant {
+                return Err(anyhow!("Cannot assign to constant: {}", name));
+            }
+            binding.value = value;
+            return Ok(());
+        }
+        match &self.outer_env {
+            Some(outer) => outer.assign(name, value),
+            None => Err(anyhow!("Cannot assign to undefined identifier: {}", name)),
+        }
+    }
+}
+
+#[allow(unpredictable_function_pointer_comparisons)]
+#[derive(PartialEq, Clone, Debug, Default)]
+pub enum Object {
+    Integer(isize),
+    Float(f64),
+    Boolean(bool),
+    String(String),
+    Array(Vec<Object>),
+    Hash(HashMap<HashKey, Object>),
+    #[default]
+    Null,
+    Return(Box<Object>),
+    /// Unwinds to the innermost loop, like `Return` does to the innermost function.
+    Break,
+    Continue,
+    Error(String),
+    Let(Box<Object>),
+    Function(FunctionObject),
+    CompiledFunction(Rc<CompiledFunction>),
+    Closure(Closure),
+    BuiltinFunction(fn(Option<Vec<Object>>) -> Result<Object>),
+}
+/// The subset of objects that can be used as keys in an `Object::Hash`.
+#[derive(PartialEq, Eq, Hash, PartialOrd, Ord, Clone, Debug)]
+pub enum HashKey {
+    Integer(isize),
+    Boolean(bool),
+    String(String),
+}
+
+impl TryFrom<&Object> for HashKey {
+    type Error = anyhow::Error;
+
+    fn try_from(object: &Object) -> Result<Self> {
+        match object {
+            Object::Integer(i) => Ok(HashKey::Integer(*i)),
+            Object::Boolean(b) => Ok(HashKey::Boolean(*b)),
+            Object::String(s) => Ok(HashKey::String(s.to_owned())),
+            _ => Err(anyhow!("Unusable as hash key: {}", object.inspect())),
+        }
+    }
+}
+
+impl From<&HashKey> for Object {
+    fn from(key: &HashKey) -> Self {
+        match key {
+            HashKey::Integer(i) => Object::Integer(*i),
+            HashKey::Boolean(b) => Object::Boolean(*b),
+            HashKey::String(s) => Object::String(s.to_owned()),
+        }
+    }
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct FunctionObject {
+    /// The name given by a `fn name(params) { body }` declaration.
+    pub name: Option<String>,
+    pub parameters: Option<Vec<Parameter>>,
+    pub body: BlockStatement,
+    pub environment: Environment,
+}
+impl FunctionObject {
+    pub fn new(
+        name: Option<String>,
+        parameters: Option<Vec<Parameter>>,
+        body: BlockStatement,
+        environment: Environment,
+    ) -> Self {
+        FunctionObject {
+            name,
+            parameters,
+            body,
+            environment,
+        }
+    }
+}
+
+/// Shows the signature, like `fn add(x, y, ...rest)` or `fn(x)` for anonymous functions.
+impl Display for FunctionObject {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let parameters: Vec<String> = self
+            .parameters
+            .iter()
+            .flatten()
+            .map(|p| match (&p.name.token, p.rest) {
+                (Token::Ident(name), true) => format!("...{}", name),
+                (Token::Ident(name), false) => name.to_owned(),
+                (token, _) => token.to_string(),
+            })
+            .collect();
+        match &self.name {
+            Some(name) => write!(f, "fn {}({})", name, parameters.join(", ")),
+            None => write!(f, "fn({})", parameters.join(", ")),
+        }
+    }
+}
+
+/// Where a closure finds a captured variable when it is created: in a local slot of the
+/// enclosing function or in the enclosing closure's own captures.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Capture {
+    Local(usize),
+    Free(usize),
+}
+
+/// A function lowered to bytecode by the compiler.
+#[derive(PartialEq, Clone, Debug)]
+pub struct CompiledFunction {
+    pub instructions: Instructions,
+    pub num_locals: usize,
+    /// The names of the parameters, not counting the rest parameter, used to match keyword
+    /// arguments.
+    pub parameters: Vec<String>,
+    /// How many of the last parameters have a default.
+    pub num_defaults: usize,
+    /// Whether the last local after the parameters collects the extra arguments.
+    pub variadic: bool,
+    pub name: Option<String>,
+    pub captures: Vec<Capture>,
+    /// Source positions of the instructions that can fail, keyed by instruction offset.
+    pub positions: Vec<(usize, Span)>,
+}
+
+/// A compiled function paired with the variables it captured. Captured variables are shared
+/// cells, so assignments made after the closure was created are visible to it.
+#[derive(Clone)]
+pub struct Closure {
+    pub function: Rc<CompiledFunction>,
+    pub free: Vec<Rc<RefCell<Object>>>,
+}
+
+impl Closure {
+    pub fn new(function: Rc<CompiledFunction>, free: Vec<Rc<RefCell<Object>>>) -> Self {
+        Closure { function, free }
+    }
+}
+
+// A recursive closure captures a cell holding itself, so equality and Debug only look at
+// the identity of the captured cells rather than their contents.
+impl PartialEq for Closure {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.function, &other.function)
+            && self.free.len() == other.free.len()
+            && self
+                .free
+                .iter()
+                .zip(&other.free)
+                .all(|(a, b)| Rc::ptr_eq(a, b))
+    }
+}
+
+impl Debug for Closure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Closure")
+            .field("function", &self.function)
+            .field("free", &self.free.len())
+            .finish()
+    }
+}
+
+fn binding_name(name: &Identifier) -> Result<String> {
+    match &name.token {
+        Token::Ident(s) => Ok(s.to_owned()),
+        _ => Err(anyhow!("Wrong token type for binding: {:?}", name.token)),
+    }
+}
+
+/// Describes how many arguments a function takes, for arity errors.
+pub(crate) fn expected_arguments(required: usize, max: usize, variadic: bool) -> String {
+    if variadic {
+        format!("at least {}", required)
+    } else if required == max {
+        required.to_string()
+    } else {
+        format!("{} to {}", required, max)
+    }
+}
+
+/// Why a value does not match a pattern, reported when a destructuring let fails.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Mismatch {
+    /// Not an array of `len` items, or of at least `len` items when the pattern has a rest.
+    Array { len: usize, rest: bool, got: Object },
+    /// Not a hash.
+    Hash(Object),
+    /// A hash without the key.
+    MissingKey(Object),
+    /// Not equal to the literal.
+    Literal { expected: Object, got: Object },
+}
+
+impl Display for Mismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Mismatch::Array { len, rest, got } => write!(
+                f,
+                "expected an array of {}{} item{}, got {}",
+                if *rest { "at least " } else { "" },
+                len,
+                if *len == 1 { "" } else { "s" },
+                got.inspect()
+            ),
+            Mismatch::Hash(got) => write!(f, "expected a hash, got {}", got.inspect()),
+            Mismatch::MissingKey(key) => write!(f, "missing key {}", key.inspect()),
+            Mismatch::Literal { expected, got } => {
+                write!(f, "expected {}, got {}", expected.inspect(), got.inspect())
+            }
+        }
+    }
+}
+
+/// Prefixes an error with the position of the node that raised it.
+pub fn at(span: Span, error: anyhow::Error) -> anyhow::Error {
+    anyhow!("{}: {}", span, error)
+}
+
+/// Evaluates an expression that is part of a larger one. A `return`, `break` or `continue`
+/// inside it, as in `[1, if (x) { return 2; }]`, ends the function or loop pass instead of
+/// giving the operand a value, so it is passed on out of the evaluation the operand belongs to.
+macro_rules! eval_operand {
+    ($expression:expr, $env:expr) => {
+        match Object::eval_expression($expression, $env)? {
+            signal @ (Object::Return(_) | Object::Break | Object::Continue) => return Ok(signal),
+            value => value,
+        }
+    };
+}
+
+#[allow(clippy::needless_return)]
+impl Display for Object {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return match self {
+            Object::Integer(u) => write!(f, "Integer Value: {}", u),
+            Object::Float(u) => write!(f, "Float Value: {:?}", u),
+            Object::String(s) => write!(f, "String value: {}", s),
+            Object::Boolean(b) => write!(f, "Bool value: {}", b),
+            Object::Array(_) => write!(f, "Array value: {}", self.inspect()),
+            Object::Hash(_) => write!(f, "Hash value: {}", self.inspect()),
+            Object::Null => write!(f, "Null value"),
+            Object::Return(o) => write!(f, "Return value: {}", o),
+            Object::Break => write!(f, "Break"),
+            Object::Continue => write!(f, "Continue"),
+            Object::Error(e) => write!(f, "Error: {}", e),
+            Object::Let(l) => write!(f, "Let Value: {}", l),
+            Object::Function(func) => write!(f, "Function Value: {}", func),
+            Object::CompiledFunction(func) => write!(f, "Compiled Function Value: {:?}", func),
+            Object::Closure(c) => write!(f, "Closure Value: {:?}", c),
+            Object::BuiltinFunction(bf) => write!(f, "Builtin Function: {:?}", bf),
+        };
+    }
+}
+
+impl Object {
+    pub fn eval(nodes: Vec<Statement>, env: &Environment) -> Self {
+        match Object::eval_block(&nodes, env) {
+            Ok(object) => object,
+            Err(e) => Object::Error(e.to_string()),
+        }
+    }
+
+    /// Evaluates the statements of a block. `Object::Return` is kept wrapped so the caller
+    /// can keep unwinding, and errors are passed up as they are.
+    fn eval_block(statements: &[Statement], env: &Environment) -> Result<Object> {
+        let mut result = Object::Null;
+        for node in statements.iter() {
+            result = match Object::eval_statement(node, env)? {
+                // return, break and continue end the block here
+                object @ (Object::Return(_) | Object::Break | Object::Continue) => {
+                    return Ok(object)
+                }
+                Object::Error(e) => return Err(anyhow!(e)),
+                object => object,
+            };
+        }
+        Ok(result)
+    }
+
+    fn eval_statement(node: &Statement, env: &Environment) -> Result<Object> {
+        match node {
+            Statement::Let(l) => Object::eval_let(l, env),
+            Statement::LetPattern(l) => Object::eval_let_pattern(l, env),
+            Statement::Return(r) => {
+                let val = eval_operand!(&r.return_value, env);
+                Ok(Object::Return(Box::new(val)))
+            }
+            Statement::While(w) => Object::eval_while(w, env),
+            Statement::For(f) => Object::eval_for(f, env),
+            Statement::Break(_) => Ok(Object::Break),
+            Statement::Continue(_) => Ok(Object::Continue),
+            Statement::Expression(e) => Object::eval_expression(e, env),
+        }
+    }
+
+    fn eval_let(l: &Let, env: &Environment) -> Result<Object> {
+        let val = eval_operand!(&l.value, env);
+        let name = match &l.token {
+            Token::Ident(s) => s.to_owned(),
+            _ => return Err(anyhow!("Wrong token type for let: {:?}", l.token)),
+        };
+        match l.constant {
+            true => env.set_const(name, val.clone()),
+            false => env.set(name, val.clone()),
+        }
+        .map_err(|e| at(l.span, e))?;
+        Ok(val)
+    }
+
+    fn eval_let_pattern(l: &LetPattern, env: &Environment) -> Result<Object> {
+        let val = eval_operand!(&l.value, env);
+        let mut bindings: Vec<(String, Object)> = vec![];
+        if let Some(mismatch) = Object::match_pattern(&l.pattern, &val, &mut bindings, env)
+            .map_err(|e| at(l.span, e))?
+        {
+            return Err(at(
+                l.span,
+                anyhow!("Cannot destructure {}: {}", val.inspect(), mismatch),
+            ));
+        }
+        for (name, bound) in bindings.into_iter() {
+            match l.constant {
+                true => env.set_const(name, bound),
+                false => env.set(name, bound),
+            }
+            .map_err(|e| at(l.span, e))?;
+        }
+        Ok(val)
+    }
+
+    fn eval_while(w: &While, env: &Environment) -> Result<Object> {
+        while eval_operand!(&w.condition, env).is_truthy() {
+            // like a for loop, every pass gets its own scope for the lets in the body
+            let loop_env = env.new_enclosed_environment();
+            match Object::eval_block(&w.body.statements, &loop_env)? {
+                Object::Break => break,
+                Object::Return(r) => return Ok(Object::Return(r)),
+                _ => {}
+            }
+        }
+        Ok(Object::Null)
+    }
+
+    fn eval_for(f: &For, env: &Environment) -> Result<Object> {
+        let name = match &f.variable.token {
+            Token::Ident(s) => s,
+            _ => return Err(anyhow!("Wrong token type for loop variable")),
+        };
+        let span = f.iterable.span();
+        let iterable = eval_operand!(&f.iterable, env);
+        for item in iterable.iteration_items().map_err(|e| at(span, e))? {
+            // every pass gets its own scope, so neither the variable nor the lets in
+            // the body outlive the loop
+            let loop_env = env.new_enclosed_environment();
+            loop_env
+                .set(name.clone(), item)
+                .map_err(|e| at(f.variable.span, e))?;
+            match Object::eval_block(&f.body.statements, &loop_env)? {
+                Object::Break => break,
+                Object::Return(r) => return Ok(Object::Return(r)),
+                _ => {}
+            }
+        }
+        Ok(Object::Null)
+    }
+
+    fn eval_expression(expression: &Expression, env: &Environment) -> Result<Object> {
+        match expression {
+            Expression::Integer(i, _) => match i {
+                Token::Int(int) => Ok(Object::Integer(*int)),
+                _ => Err(anyhow!(
+                    "Wrong Token Type: Expected: {:?}, Got: {:?}",
+                    Token::Int(0),
+                    i
+                )),
+            },
+            Expression::Float(fl, _) => match fl {
+                Token::Float(float) => Ok(Object::Float(*float)),
+                _ => Err(anyhow!("Wrong token type for float: {:?}", fl)),
+            },
+            Expression::String(s, _) => Ok(Object::String(s.to_owned())),
+            Expression::Interpolated(parts, _) => {
+                let mut values: Vec<Object> = vec![];
+                for part in parts.iter() {
+                    values.push(eval_operand!(part, env));
+                }
+                Ok(Object::interpolate(&values))
+            }
+            Expression::Boolean(b, _) => match b {
+                Token::True => Ok(Object::Boolean(true)),
+                Token::False => Ok(Object::Boolean(false)),
+                _ => Err(anyhow!("Wrong token type. Expected Boolean, Got: {:?}", b)),
+            },
+            Expression::Array(a, _) => {
+                let mut elements: Vec<Object> = vec![];
+                for item in a.iter() {
+                    elements.push(eval_operand!(item, env));
+                }
+                Ok(Object::Array(elements))
+            }
+            Expression::Hash(h, span) => Object::eval_hash_literal(h, *span, env),
+            Expression::Index(i) => {
+                let left = eval_operand!(&i.left, env);
+                let index = eval_operand!(&i.index, env);
+                Object::eval_index_expression(left, index).map_err(|e| at(i.span, e))
+            }
+            Expression::Prefix(p) => {
+                let right = eval_operand!(&p.right, env);
+                Object::eval_prefix_expression(&p.token, right).map_err(|e| at(p.span, e))
+            }
+            Expression::Infix(inf) => Object::eval_infix(inf, env),
+            Expression::If(i) => Object::eval_if(i, env),
+            Expression::Identifier(i, span) => match i {
+                Token::Ident(s) => env.get(s).map_err(|e| at(*span, e)),
+                _ => Err(anyhow!("Wrong token type for identifier")),
+            },
+            Expression::Assign(a) => Object::eval_assign(a, env),
+            Expression::Match(m) => Object::eval_match(m, env),
+            Expression::Fn(func) => Ok(Object::Function(FunctionObject::new(
+                func.name.as_ref().map(binding_name).transpose()?,
+                func.parameters.clone(),
+                func.body.clone(),
+                env.clone(),
+            ))),
+            Expression::Call(call) => Object::eval_call(call, env),
+        }
+    }
+
+    fn eval_hash_literal(
+        h: &[(Expression, Expression)],
+        span: Span,
+        env: &Environment,
+    ) -> Result<Object> {
+        let mut pairs: HashMap<HashKey, Object> = HashMap::new();
+        for (key, value) in h.iter() {
+            let key = eval_operand!(key, env);
+            let value = eval_operand!(value, env);
+            let key = HashKey::try_from(&key).map_err(|e| at(span, e))?;
+            pairs.insert(key, value);
+        }
+        Ok(Object::Hash(pairs))
+    }
+
+    fn eval_infix(inf: &InfixExpression, env: &Environment) -> Result<Object> {
+        if matches!(inf.token, Token::And | Token::Or) {
+            // the right side is only evaluated when the left does not decide the result
+            let left = eval_operand!(&inf.left, env).is_truthy();
+            if left == (inf.token == Token::Or) {
+                return Ok(Object::Boolean(left));
+            }
+            let right = eval_operand!(&inf.right, env);
+            return Ok(Object::Boolean(right.is_truthy()));
+        }
+        let left = eval_operand!(&inf.left, env);
+        let right = eval_operand!(&inf.right, env);
+        Object::eval_infix_expression(&inf.token, left, right).map_err(|e| at(inf.span, e))
+    }
+
+    fn eval_if(i: &IfExpression, env: &Environment) -> Result<Object> {
+        let condition = eval_operand!(&i.condition, env);
+        // returns from the consequence or alternative are passed up as
+        // Object::Return to facilitiate nested block statements that have returns
+        if condition.is_truthy() {
+            Object::eval_block(&i.consequence.statements, env)
+        } else if let Some(alt) = &i.alternative {
+            Object::eval_block(&alt.statements, env)
+        } else {
+            Ok(Object::Null)
+        }
+    }
+
+    fn eval_assign(a: &AssignExpression, env: &Environment) -> Result<Object> {
+        let name = match &a.name.token {
+            Token::Ident(s) => s.to_owned(),
+            _ => return Err(anyhow!("Wrong token type for assignment")),
+        };
+        let value = match a.operator() {
+            Some(operator) => {
+                let current = env.get(&name).map_err(|e| at(a.name.span, e))?;
+                let right = eval_operand!(&a.value, env);
+                Object::eval_infix_expression(&operator, current, right)
+                    .map_err(|e| at(a.span, e))?
+            }
+            None => eval_operand!(&a.value, env),
+        };
+        env.assign(&name, value.clone())
+            .map_err(|e| at(a.span, e))?;
+        Ok(value)
+    }
+
+    fn eval_match(m: &MatchExpression, env: &Environment) -> Result<Object> {
+        let value = eval_operand!(&m.value, env);
+        for arm in m.arms.iter() {
+            let mut bindings: Vec<(String, Object)> = vec![];
+            if Object::match_pattern(&arm.pattern, &value, &mut bindings, env)
+                .map_err(|e| at(arm.span, e))?
+                .is_some()
+            {
+                continue;
+            }
+            // names bound by the pattern are only visible in its arm
+            let arm_env = env.new_enclosed_environment();
+            for (name, bound) in bindings.into_iter() {
+                arm_env.set(name, bound)?;
+            }
+            if let Some(guard) = &arm.guard {
+                if !eval_operand!(guard, &arm_env).is_truthy() {
+                    continue;
+                }
+            }
+            return Object::eval_block(&arm.body.statements, &arm_env);
+        }
+        Err(at(m.span, anyhow!("No match arm for {}", value.inspect())))
+    }
+
+    fn eval_call(call: &CallExpression, env: &Environment) -> Result<Object> {
+        // Get function from call
+        let func = eval_operand!(&call.function, env);
+        // name used to describe the function in errors, a declared name comes first as it
+        // does in the vm
+        let name = match (&func, &call.function) {
+            (Object::Function(FunctionObject { name: Some(s), .. }), _) => s.to_owned(),
+            (_, Expression::Identifier(Token::Ident(s), _)) => s.to_owned(),
+            _ => String::from("anonymous function"),
+        };
+        // turn arguments into objects
+        let mut args: Vec<Object> = vec![];
+        if let Some(arguments) = &call.arguments {
+            for arg in arguments.iter() {
+                args.push(eval_operand!(arg, env))
+            }
+        }
+        let mut keyword_args: Vec<(String, Object)> = vec![];
+        for (keyword, arg) in call.keyword_arguments.iter() {
+            keyword_args.push((binding_name(keyword)?, eval_operand!(arg, env)));
+        }
+
+        Object::apply_function(&name, func, args, keyword_args, call.span)
+    }
+
+    /// Calls `func` with `args`. Errors about the call itself are reported at `span`, errors
+    /// raised inside the function body keep their own position.
+    fn apply_function(
+        name: &str,
+        func: Object,
+        args: Vec<Object>,
+        keyword_args: Vec<(String, Object)>,
+        span: Span,
+    ) -> Result<Object> {
+        match func {
+            Object::Function(f) => {
+                let depth = Rc::clone(&f.environment.call_depth);
+                if depth.get() >= MAX_CALL_DEPTH {
+                    return Err(at(
+                        span,
+                        anyhow!(
+                            "Maximum recursion depth exceeded: more than {} nested calls",
+                            MAX_CALL_DEPTH
+                        ),
+                    ));
+                }
+                depth.set(depth.get() + 1);
+                let result = Object::call_function(name, f, args, keyword_args, span);
+                depth.set(depth.get() - 1);
+                result
+            }
+            Object::BuiltinFunction(_) if !keyword_args.is_empty() => Err(at(
+                span,
+                anyhow!("Builtin functions do not take keyword arguments"),
+            )),
+            Object::BuiltinFunction(bf) => bf(Some(args)).map_err(|e| at(span, e)),
+            _ => Err(at(span, anyhow!("Not a function: {}", func.inspect()))),
+        }
+    }
+
+    fn call_function(
+        name: &str,
+        f: FunctionObject,
+        args: Vec<Object>,
+        keyword_args: Vec<(String, Object)>,
+        span: Span,
+    ) -> Result<Object> {
+        let extended_env = f.environment.new_enclosed_environment();
+        let params = f.parameters.unwrap_or_default();
+        Object::bind_arguments(name, params, args, keyword_args, &extended_env, span)?;
+        let eval_body = Object::eval_block(&f.body.statements, &extended_env)?;
+        match eval_body {
+            Object::Return(r) => Ok(r.as_ref().to_owned()),
+            _ => Ok(eval_body),
+        }
+    }
+
+    /// Binds the arguments of a call to the parameters in `env`. Positional arguments fill
+    /// the parameters in order and any left over are collected by the rest parameter, then
+    /// keyword arguments fill parameters by name. Parameters still missing an argument take
+    /// their default, which can refer to the parameters before it.
+    fn bind_arguments(
+        name: &str,
+        params: Vec<Parameter>,
+        args: Vec<Object>,
+        keyword_args: Vec<(String, Object)>,
+        env: &Environment,
+        span: Span,
+    ) -> Result<()> {
+        let (rest, params): (Vec<Parameter>, Vec<Parameter>) =
+            params.into_iter().partition(|p| p.rest);
+        let required = params.iter().filter(|p| p.default.is_none()).count();
+        let wrong_number = |got: usize| {
+            at(
+                span,
+                anyhow!(
+                    "Wrong number of arguments for {}: expected {}, got {}",
+                    name,
+                    expected_arguments(required, params.len(), !rest.is_empty()),
+                    got
+                ),
+            )
+        };
+        let num_args = args.len();
+        if num_args > params.len() && rest.is_empty() {
+            return Err(wrong_number(num_args));
+        }
+
+        let mut args = args.into_iter();
+        let mut values: Vec<Option<Object>> = params.iter().map(|_| args.next()).collect();
+        let extra: Vec<Object> = args.collect();
+
+        let has_keywords = !keyword_args.is_empty();
+        for (keyword, value) in keyword_args.into_iter() {
+            let position = params
+                .iter()
+                .position(|p| matches!(&p.name.token, Token::Ident(s) if *s == keyword));
+            let Some(i) = position else {
+                return Err(at(
+                    span,
+                    anyhow!("Unknown keyword argument for {}: {}", name, keyword),
+                ));
+            };
+            if values[i].is_some() {
+                return Err(at(
+                    span,
+                    anyhow!("Argument given twice for {}: {}", name, keyword),
+                ));
+            }
+            values[i] = Some(value);
+        }
+
+        for (param, value) in params.iter().zip(values) {
+            let value = match (value, &param.default) {
+                (Some(value), _) => value,
+                (None, Some(default)) => Object::eval_expression(default, env)?,
+                (None, None) if has_keywords => {
+                    return Err(at(
+                        span,
+                        anyhow!(
+                            "Missing argument for {}: {}",
+                            name,
+                            binding_name(&param.name)?
+                        ),
+                    ))
+                }
+                (None, None) => return Err(wrong_number(num_args)),
+            };
+            env.set(binding_name(&param.name)?, value)?;
+        }
+        if let Some(rest) = rest.first() {
+            env.set(binding_name(&rest.name)?, Object::Array(extra))?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn eval_prefix_expression(token: &Token, right: Object) -> Result<Object> {
+        match token {
+            Token::Bang => match right {
+                Object::Boolean(true) => Ok(Object::Boolean(false)),
+                Object::Boolean(false) => Ok(Object::Boolean(true)),
+                Object::Null => Ok(Object::Boolean(true)),
+                _ => Ok(Object::Boolean(false)),
+            },
+            Token::Minus => match right {
+                Object::Integer(int) => int
+                    .checked_neg()
+                    .map(Object::Integer)
+                    .ok_or_else(|| anyhow!("Integer overflow: Minus {}", int)),
+                Object::Float(float) => Ok(Object::Float(-float)),
+                _ => Err(anyhow!("Minus prefix can only be used with a number")),
+            },
+            _ => Err(anyhow!("Wrong Token Type")),
+        }
+    }
+
+    pub(crate) fn eval_infix_expression(
+        token: &Token,
+        left: Object,
+        right: Object,
+    ) -> Result<Object> {
+        match (left, right) {
+            (Object::Integer(il), Object::Integer(ir)) => match token {
+                Token::Plus => Object::checked_integer(token, il, ir, il.checked_add(ir)),
+                Token::Minus => Object::checked_integer(token, il, ir, il.checked_sub(ir)),
+                Token::Asterisk => Object::checked_integer(token, il, ir, il.checked_mul(ir)),
+                Token::Slash => {
+                    if ir == 0 {
+                        return Err(anyhow!("Division by zero: {} {} {}", il, token, ir));
+                    }
+                    Object::checked_integer(token, il, ir, il.checked_div(ir))
+                }
+                Token::Percent => {
+                    if ir == 0 {
+                        return Err(anyhow!("Division by zero: {} {} {}", il, token, ir));
+                    }
+                    Object::checked_integer(token, il, ir, il.checked_rem(ir))
+                }
+                Token::Power => {
+                    if ir < 0 {
+                        return Err(anyhow!("Negative exponent: {} {} {}", il, token, ir));
+                    }
+                    let result = u32::try_from(ir).ok().and_then(|ir| il.checked_pow(ir));
+                    Object::checked_integer(token, il, ir, result)
+                }
+                Token::ShiftLeft | Token::ShiftRight => {
+                    if ir < 0 {
+                        return Err(anyhow!("Negative shift: {} {} {}", il, token, ir));
+                    }
+                    let result = u32::try_from(ir).ok().and_then(|ir| match token {
+                        Token::ShiftLeft => il.checked_shl(ir),
+                        _ => il.checked_shr(ir),
+                    });
+                    Object::checked_integer(token, il, ir, result)
+                }
+                Token::Ampersand => Ok(Object::Integer(il & ir)),
+                Token::Pipe => Ok(Object::Integer(il | ir)),
+                Token::Caret => Ok(Object::Integer(il ^ ir)),
+                Token::LessThan => Ok(Object::Boolean(il < ir)),
+                Token::GreaterThan => Ok(Object::Boolean(il > ir)),
+                Token::LessEqual => Ok(Object::Boolean(il <= ir)),
+                Token::GreaterEqual => Ok(Object::Boolean(il >= ir)),
+                Token::Equal => Ok(Object::Boolean(il == ir)),
+                Token::NotEqual => Ok(Object::Boolean(il != ir)),
+                _ => Err(anyhow!("Unknown operator: Integer {} Integer", token)),
+            },
+            // a float on either side makes the operation a float one
+            (Object::Float(fl), Object::Float(fr)) => {
+                Object::eval_float_infix_expression(token, fl, fr)
+            }
+            (Object::Integer(il), Object::Float(fr)) => {
+                Object::eval_float_infix_expression(token, il as f64, fr)
+            }
+            (Object::Float(fl), Object::Integer(ir)) => {
+                Object::eval_float_infix_expression(token, fl, ir as f64)
+            }
+            (Object::Boolean(bl), Object::Boolean(br)) => match token {
+                Token::Equal => Ok(Object::Boolean(bl == br)),
+                Token::NotEqual => Ok(Object::Boolean(bl != br)),
+                Token::Ampersand => Ok(Object::Boolean(bl & br)),
+                Token::Pipe => Ok(Object::Boolean(bl | br)),
+                Token::Caret => Ok(Object::Boolean(bl ^ br)),
+                _ => Err(anyhow!("Unknown operator: Boolean {} Boolean", token)),
+            },
+            (Object::String(sl), Object::String(sr)) => match token {
+                Token::Plus => Ok(Object::String(sl + &sr)),
+                _ => Err(anyhow!("Unknown operator: String {} String", token)),
+            },
+            (left, right) => Err(anyhow!(
+                "Type mismatch: {} {} {}",
+                left.inspect(),
+                token,
+                right.inspect()
+            )),
+        }
+    }
+
+    /// The result of checked integer arithmetic, `None` meaning the result overflowed.
+    fn checked_integer(
+        token: &Token,
+        il: isize,
+        ir: isize,
+        result: Option<isize>,
+    ) -> Result<Object> {
+        result
+            .map(Object::Integer)
+            .ok_or_else(|| anyhow!("Integer overflow: {} {} {}", il, token, ir))
+    }
+
+    fn eval_float_infix_expression(token: &Token, fl: f64, fr: f64) -> Result<Object> {
+        match token {
+            Token::Plus => Ok(Object::Float(fl + fr)),
+            Token::Minus => Ok(Object::Float(fl - fr)),
+            Token::Asterisk => Ok(Object::Float(fl * fr)),
+            Token::Slash => Ok(Object::Float(fl / fr)),
+            Token::Percent => Ok(Object::Float(fl % fr)),
+            Token::Power => Ok(Object::Float(fl.powf(fr))),
+            Token::LessThan => Ok(Object::Boolean(fl < fr)),
+            Token::GreaterThan => Ok(Object::Boolean(fl > fr)),
+            Token::LessEqual => Ok(Object::Boolean(fl <= fr)),
+            Token::GreaterEqual => Ok(Object::Boolean(fl >= fr)),
+            Token::Equal => Ok(Object::Boolean(fl == fr)),
+            Token::NotEqual => Ok(Object::Boolean(fl != fr)),
+            _ => Err(anyhow!("Unknown operator: Float {} Float", token)),
+        }
+    }
+
+    pub(crate) fn eval_index_expression(left: Object, index: Object) -> Result<Object> {
+        match (left, index) {
+            (Object::Array(elements), Object::Integer(i)) => {
+                if i < 0 || i as usize >= elements.len() {
+                    return Err(anyhow!(
+                        "Index out of bounds: index {} but length is {}",
+                        i,
+                        elements.len()
+                    ));
+                }
+                Ok(elements[i as usize].to_owned())
+            }
+            (Object::Hash(pairs), index) => {
+                let key = HashKey::try_from(&index)?;
+                Ok(pairs.get(&key).cloned().unwrap_or(Object::Null))
+            }
+            (left, index) => Err(anyhow!(
+                "Index operator not supported: {}[{}]",
+                left.inspect(),
+                index.inspect()
+            )),
+        }
+    }
+
+    /// Renders the object the way it would be written in source, used when
+    /// displaying objects nested inside other objects.
+    pub fn inspect(&self) -> String {
+        match self {
+            Object::Integer(i) => i.to_string(),
+            // debug formatting keeps the decimal point, so 1.0 is not shown as an integer
+            Object::Float(f) => format!("{:?}", f),
+            Object::Boolean(b) => b.to_string(),
+            Object::String(s) => format!("\"{}\"", s),
+            Object::Array(a) => {
+                let items: Vec<String> = a.iter().map(|o| o.inspect()).collect();
+                format!("[{}]", items.join(", "))
+            }
+            Object::Hash(h) => {
+                let mut items: Vec<String> = h
+                    .iter()
+                    .map(|(k, v)| format!("{}: {}", Object::from(k).inspect(), v.inspect()))
+                    .collect();
+                // HashMap iteration order is unstable, sort so output is repeatable
+                items.sort();
+                format!("{{{}}}", items.join(", "))
+            }
+            Object::Null => String::from("null"),
+            Object::Return(o) | Object::Let(o) => o.inspect(),
+            Object::Break => String::from("break"),
+            Object::Continue => String::from("continue"),
+            Object::Error(e) => format!("Error: {}", e),
+            Object::Function(_) | Object::CompiledFunction(_) | Object::Closure(_) => {
+                String::from("fn")
+            }
+            Object::BuiltinFunction(_) => String::from("builtin fn"),
+        }
+    }
+
+    /// Renders the object for embedding in a string: strings as their text, everything
+    /// else as it would be written in source.
+    pub fn to_display_string(&self) -> String {
+        match self {
+            Object::String(s) => s.to_owned(),
+            other => other.inspect(),
+        }
+    }
+
+    /// Joins the values of an interpolated string's parts.
+    pub(crate) fn interpolate(values: &[Object]) -> Object {
+        Object::String(values.iter().map(Object::to_display_string).collect())
+    }
+
+    /// The values a `for` loop visits: the elements of an array, the characters of a string
+    /// or the sorted keys of a hash.
+    pub(crate) fn iteration_items(&self) -> Result<Vec<Object>> {
+        match self {
+            Object::Array(elements) => Ok(elements.clone()),
+            Object::String(s) => Ok(s.chars().map(|c| Object::String(c.to_string())).collect()),
+            Object::Hash(pairs) => {
+                let mut keys: Vec<&HashKey> = pairs.keys().collect();
+                keys.sort();
+                Ok(keys.into_iter().map(Object::from).collect())
+            }
+            _ => Err(anyhow!("Cannot iterate over {}", self.inspect())),
+        }
+    }
+
+    /// Matches `value` against `pattern`, collecting the names it binds. Returns why the value
+    /// does not match, or `None` when it does.
+    fn match_pattern(
+        pattern: &Pattern,
+        value: &Object,
+        bindings: &mut Vec<(String, Object)>,
+        env: &Environment,
+    ) -> Result<Option<Mismatch>> {
+        match pattern {
+            Pattern::Wildcard(_) => Ok(None),
+            Pattern::Binding(name) => {
+                bindings.push((binding_name(name)?, value.clone()));
+                Ok(None)
+            }
+            Pattern::Literal(literal) => {
+                let expected = Object::eval_expression(literal, env)?;
+                match Object::matches_literal(&expected, value) {
+                    true => Ok(None),
+                    false => Ok(Some(Mismatch::Literal {
+                        expected,
+                        got: value.clone(),
+                    })),
+                }
+            }
+            Pattern::Array(items, rest, _) => {
+                let mismatch = Mismatch::Array {
+                    len: items.len(),
+                    rest: rest.is_some(),
+                    got: value.clone(),
+                };
+                let elements = match value {
+                    Object::Array(elements) => elements,
+                    _ => return Ok(Some(mismatch)),
                 };
+                if elements.len() < items.len() || (rest.is_none() && elements.len() > items.len())
+                {
+                    return Ok(Some(mismatch));
+                }
+                for (item, element) in items.iter().zip(elements) {
+                    if let Some(mismatch) = Object::match_pattern(item, element, bindings, env)? {
+                        return Ok(Some(mismatch));
+                    }
+                }
+                if let Some(rest) = rest {
+                    let name = binding_name(rest)?;
+                    if name != "_" {
+                        bindings.push((name, Object::Array(elements[items.len()..].to_vec())));
+                    }
+                }
+                Ok(None)
+            }
+            Pattern::Hash(entries, _) => {
+                let pairs = match value {
+                    Object::Hash(pairs) => pairs,
+                    _ => return Ok(Some(Mismatch::Hash(value.clone()))),
+                };
+                for (key, pattern) in entries.iter() {
+                    let key = Object::eval_expression(key, env)?;
+                    let found = match pairs.get(&HashKey::try_from(&key)?) {
+                        Some(found) => found,
+                        None => return Ok(Some(Mismatch::MissingKey(key))),
+                    };
+                    if let Some(mismatch) = Object::match_pattern(pattern, found, bindings, env)? {
+                        return Ok(Some(mismatch));
+                    }
+                }
+                Ok(None)
+            }
+        }
+    }
+
+    /// Whether `value` matches a literal pattern. Values `==` can compare are matched with
+    /// it, so `1` matches `1.0`, anything else has to be the same value.
+    pub(crate) fn matches_literal(literal: &Object, value: &Object) -> bool {
+        match Object::eval_infix_expression(&Token::Equal, literal.clone(), value.clone()) {
+            Ok(Object::Boolean(equal)) => equal,
+            _ => literal == value,
+        }
+    }
+
+    pub(crate) fn is_truthy(&self) -> bool {
+        match &self {
+            Object::Null => false,
+            Object::Boolean(false) => false,
+            Object::Boolean(true) => true,
+            _ => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use crate::lexer::lexer::Lexer;
+    use crate::object::object::Object;
+    use crate::parser::parser::Parser;
+
+    use super::{Environment, HashKey, EVAL_STACK_SIZE};
+
+    #[test]
+    fn test_array_literals() {
+        struct Test {
+            input: Vec<u8>,
+            expected: Object,
+        }
+        let tests = vec![
+            Test {
+                input: "[1, 2 * 2, 3 + 3]".into(),
+                expected: Object::Array(vec![
+                    Object::Integer(1),
+                    Object::Integer(4),
+                    Object::Integer(6),
+                ]),
+            },
+            Test {
+                input: "[]".into(),
+                expected: Object::Array(vec![]),
+            },
+        ];
+
+        for test in tests.into_iter() {
+            let evaluated = test_eval(test.input);
+            assert_eq!(test.expected, evaluated);
+        }
+    }
+
+    #[test]
+    fn test_array_index_expressions() {
+        struct Test {
+            input: Vec<u8>,
+            expected: Object,
+        }
+        let tests = vec![
+            Test {
+                input: "[1, 2, 3][0]".into(),
+                expected: Object::Integer(1),
+            },
+            Test {
+                input: "[1, 2, 3][1 + 1]".into(),
+                expected: Object::Integer(3),
+            },
+            Test {
+                input: "let i = 0; [1][i];".into(),
+                expected: Object::Integer(1),
+            },
+            Test {
+                input: "let myArray = [1, 2, 3]; myArray[0] + myArray[1] + myArray[2];".into(),
+                expected: Object::Integer(6),
+            },
+        ];
+
+        for test in tests.into_iter() {
+            let evaluated = test_eval(test.input);
+            assert_eq!(test.expected, evaluated);
+        }
+    }
+
+    #[test]
+    fn test_hash_literals() {
+        let input = r#"let two = "two";
+        {"one": 10 - 9, two: 1 + 1, "thr" + "ee": 6 / 2, 4: 4, true: 5, false: 6}"#;
+        let mut expected: HashMap<HashKey, Object> = HashMap::new();
+        expected.insert(HashKey::String(String::from("one")), Object::Integer(1));
+        expected.insert(HashKey::String(String::from("two")), Object::Integer(2));
+        expected.insert(HashKey::String(String::from("three")), Object::Integer(3));
+        expected.insert(HashKey::Integer(4), Object::Integer(4));
+        expected.insert(HashKey::Boolean(true), Object::Integer(5));
+        expected.insert(HashKey::Boolean(false), Object::Integer(6));
+
+        assert_eq!(Object::Hash(expected), test_eval(input.into()));
+    }
+
+    #[test]
+    fn test_hash_index_expressions() {
+        struct Test {
+            input: Vec<u8>,
+            expected: Object,
+        }
+        let tests = vec![
+            Test {
+                input: r#"{"foo": 5}["foo"]"#.into(),
+                expected: Object::Integer(5),
+            },
+            Test {
+                input: r#"{"foo": 5}["bar"]"#.into(),
+                expected: Object::Null,
+            },
+            Test {
+                input: r#"let key = "foo"; {"foo": 5}[key]"#.into(),
+                expected: Object::Integer(5),
+            },
+            Test {
+                input: r#"{}["foo"]"#.into(),
+                expected: Object::Null,
+            },
+            Test {
+                input: "{5: 5}[5]".into(),
+                expected: Object::Integer(5),
+            },
+            Test {
+                input: "{true: 5}[true]".into(),
+                expected: Object::Integer(5),
+            },
+        ];
+
+        for test in tests.into_iter() {
+            let evaluated = test_eval(test.input);
+            assert_eq!(test.expected, evaluated);
+        }
+    }
+
+    #[test]
+    fn test_unusable_hash_key() {
+        let hash = Object::Hash(HashMap::new());
+        let err = Object::eval_index_expression(hash, Object::Array(vec![])).unwrap_err();
+        assert_eq!(err.to_string(), "Unusable as hash key: []");
+    }
+
+    #[test]
+    fn test_array_index_out_of_bounds() {
+        let tests = vec![
+            (
+                "[1, 2][5]",
+                Object::Error(String::from(
+                    "line 1, column 7: Index out of bounds: index 5 but length is 2",
+                )),
+            ),
+            (
+                "let a = [1, 2]; a[-1]",
+                Object::Error(String::from(
+                    "line 1, column 18: Index out of bounds: index -1 but length is 2",
+                )),
+            ),
+        ];
+
+        for (input, expected) in tests.into_iter() {
+            assert_eq!(test_eval(input.into()), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_error_handling() {
+        struct Test {
+            input: Vec<u8>,
+            expected: Object,
+        }
+        let tests = vec![
+            Test {
+                input: "5 + true;".into(),
+                expected: Object::Error(String::from(
+                    "line 1, column 3: Type mismatch: 5 Plus true",
+                )),
+            },
+            Test {
+                input: "5 + true; 5;".into(),
+                expected: Object::Error(String::from(
+                    "line 1, column 3: Type mismatch: 5 Plus true",
+                )),
+            },
+            Test {
+                input: "-true".into(),
+                expected: Object::Error(String::from(
+                    "line 1, column 1: Minus prefix can only be used with a number",
+                )),
+            },
+            Test {
+                input: "true + false;".into(),
+                expected: Object::Error(String::from(
+                    "line 1, column 6: Unknown operator: Boolean Plus Boolean",
+                )),
+            },
+            Test {
+                input: "if (10 > 1) { if (10 > 1) { return true + false; } return 1; }".into(),
+                expected: Object::Error(String::from(
+                    "line 1, column 41: Unknown operator: Boolean Plus Boolean",
+                )),
+            },
+            Test {
+                input: "foobar".into(),
+                expected: Object::Error(String::from(
+                    "line 1, column 1: Identifier not found: foobar",
+                )),
+            },
+            Test {
+                input: "let f = fn(x) { x + true; 10 }; f(1);".into(),
+                expected: Object::Error(String::from(
+                    "line 1, column 19: Type mismatch: 1 Plus true",
+                )),
+            },
+            Test {
+                input: "let a = 1; a();".into(),
+                expected: Object::Error(String::from("line 1, column 12: Not a function: 1")),
+            },
+            Test {
+                input: "[1, 2][2]".into(),
+                expected: Object::Error(String::from(
+                    "line 1, column 7: Index out of bounds: index 2 but length is 2",
+                )),
+            },
+        ];
+
+        for test in tests.into_iter() {
+            let evaluated = test_eval(test.input);
+            assert_eq!(test.expected, evaluated);
+        }
+    }
+
+    #[test]
+    fn test_error_does_not_bind() {
+        let lex = Lexer::new("let a = 1; let a = 1 + true;".into());
+        let mut parser = Parser::new(lex);
+        let program = parser.parse_program();
+        let env = Environment::new();
+        Object::eval(program.statements, &env);
+
+        assert_eq!(env.get(&String::from("a")).unwrap(), Object::Integer(1));
+    }
+
+    #[test]
+    fn test_builtin() {
+        struct Test {
+            input: Vec<u8>,
+            expected: Object,
+        }
+        let tests = vec![
+            Test {
+                input: r#"len("four")"#.into(),
+                expected: Object::Integer(4),
+            },
+            Test {
+                input: "int(2.9) + int(-2.9)".into(),
+                expected: Object::Integer(0),
+            },
+            Test {
+                input: r#"int(" 42 ")"#.into(),
+                expected: Object::Integer(42),
+            },
+            Test {
+                input: "float(3) / 2".into(),
+                expected: Object::Float(1.5),
+            },
+            Test {
+                input: r#"float("2.5e1")"#.into(),
+                expected: Object::Float(25.0),
+            },
+            Test {
+                input: r#"str(1.0) + str(2) + str("3") + str([true])"#.into(),
+                expected: Object::String(String::from("1.023[true]")),
+            },
+            Test {
+                input: r#"int("abc")"#.into(),
+                expected: Object::Error(String::from(
+                    r#"line 1, column 1: Cannot convert "abc" to an integer"#,
+                )),
+            },
+            Test {
+                input: "int(1e300)".into(),
+                expected: Object::Error(String::from(
+                    "line 1, column 1: Cannot convert 1e300 to an integer",
+                )),
+            },
+            Test {
+                input: "float(true)".into(),
+                expected: Object::Error(String::from(
+                    "line 1, column 1: Wrong argument type for float function: true",
+                )),
+            },
+        ];
+
+        for test in tests.into_iter() {
+            let evaluated = test_eval(test.input);
+            assert_eq!(test.expected, evaluated);
+        }
+    }
+
+    #[test]
+    fn test_string_interpolation() {
+        struct Test {
+            input: Vec<u8>,
+            expected: Object,
+        }
+        let tests = vec![
+            Test {
+                input: r#"let a = 1; let b = 2; "total: ${a + b}""#.into(),
+                expected: Object::String(String::from("total: 3")),
+            },
+            Test {
+                input: r#"let name = "bob"; "hi ${name}!""#.into(),
+                expected: Object::String(String::from("hi bob!")),
+            },
+            Test {
+                input: r#""${[1, "x"]} ${ {"k": true} } ${len("abc")} ${if (false) { 1 }}""#.into(),
+                expected: Object::String(String::from(r#"[1, "x"] {"k": true} 3 null"#)),
+            },
+            Test {
+                input: r#""a ${"b ${1 + 1} c"} d""#.into(),
+                expected: Object::String(String::from("a b 2 c d")),
+            },
+            Test {
+                input: "\"x\n${missing}\"".into(),
+                expected: Object::Error(String::from(
+                    "line 2, column 3: Identifier not found: missing",
+                )),
+            },
+        ];
 
-                match env_check {
-                    Some(e) => Ok(e.to_owned()),
-                    None => {
-                        let builtin_check = self.builtin_functions.get_fn(name.to_owned());
-                        match builtin_check {
-                            Some(b) => Ok(Object::BuiltinFunction(b)),
-                            None => Err(anyhow!("Not found in builtin_functions")),
-                        }
-                    }
-                }
-            }
+        for test in tests.into_iter() {
+            let evaluated = test_eval(test.input);
+            assert_eq!(test.expected, evaluated);
         }
     }
-}
 
-#[derive(PartialEq, Clone, Debug)]
-pub enum Object {
-    Integer(isize),
-    Boolean(bool),
-    String(String),
-    Null,
-    Return(Box<Object>),
-    Let(Box<Object>),
-    Function(FunctionObject),
-    BuiltinFunction(fn(Option<Vec<Object>>) -> Result<Object>),
-}
-#[derive(PartialEq, Clone, Debug)]
-pub struct FunctionObject {
-    pub parameters: Option<Vec<Identifier>>,
-    pub body: BlockStatement,
-    pub environment: Environment,
-}
-impl FunctionObject {
-    pub fn new(
-        parameters: Option<Vec<Identifier>>,
-        body: BlockStatement,
-        environment: Environment,
-    ) -> Self {
-        FunctionObject {
-            parameters,
-            body,
-            environment,
+    #[test]
+    fn test_closures() {
+        struct Test {
+            input: Vec<u8>,
+            expected: Object,
         }
-    }
-}
-
-impl Display for Object {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        return match self {
-            Object::Integer(u) => write!(f, "Integer Value: {}", u),
-            Object::String(s) => write!(f, "String value: {}", s),
-            Object::Boolean(b) => write!(f, "Bool value: {}", b),
-            Object::Null => write!(f, "Null value"),
-            Object::Return(o) => write!(f, "Return value: {}", o),
-            Object::Let(l) => write!(f, "Let Value: {}", l),
-            Object::Function(func) => write!(f, "Function Value: {:?}", func),
-            Object::BuiltinFunction(bf) => write!(f, "Builtin Function: {:?}", bf),
-        };
-    }
-}
+        let tests = vec![Test {
+            input: "let newAdder = fn(x) {fn(y) { x + y };};let addTwo = newAdder(2);addTwo(2);"
+                .into(),
+            expected: Object::Integer(4),
+        }];
 
-impl Object {
-    pub fn eval(nodes: Vec<Statement>, env: &mut Environment) -> Self {
-        let mut result: Result<Object> = Ok(Object::Null);
-        for node in nodes.into_iter() {
-            result = match node {
-                Statement::Let(l) => {
-                    // println!("This is the val: {:?}", l);
-
-                    let val = Object::eval(vec![Statement::Expression(l.value)], env);
-                    // println!("Eval value: {:?}", val);
-                    match l.token {
-                        Token::Ident(s) => env.store.insert(s, val.clone()),
-                        _ => panic!("Wrong token here"),
-                    };
-                    Ok(val)
-                }
-                Statement::Return(r) => {
-                    let val = Object::eval(vec![Statement::Expression(r.return_value)], env);
-                    // return breaks the loop here
-                    return Object::Return(Box::new(val));
-                }
-                Statement::Expression(e) => match e {
-                    Expression::Integer(i) => match i {
-                        Token::Int(int) => Ok(Object::Integer(int)),
-                        _ => Err(anyhow!(
-                            "Wrong Token Type: Expected: {:?}, Got: {:?}",
-                            Token::Int(0),
-                            i
-                        )),
-                    },
-                    Expression::String(s) => Ok(Object::String(s.to_owned())),
-                    Expression::Boolean(b) => match b {
-                        Token::True => Ok(Object::Boolean(true)),
-                        Token::False => Ok(Object::Boolean(false)),
-                        _ => Err(anyhow!("Wrong token type. Expected Boolean, Got: {:?}", b)),
-                    },
-                    // TODO: finish eval into object
-                    Expression::Array(a) => Ok(Object::Null),
-                    Expression::Prefix(p) => {
-                        let right = Object::eval(vec![Statement::Expression(p.right)], env);
-                        match &p.token {
-                            Token::Bang => match right {
-                                Object::Boolean(true) => Ok(Object::Boolean(false)),
-                                Object::Boolean(false) => Ok(Object::Boolean(true)),
-                                Object::Null => Ok(Object::Boolean(true)),
-                                _ => Ok(Object::Boolean(false)),
-                            },
-                            Token::Minus => match right {
-                                Object::Integer(int) => Ok(Object::Integer(-int)),
-                                _ => Err(anyhow!("Minus prefix can only be used with an integer")),
-                            },
-                            _ => Err(anyhow!("Wrong Token Type")),
-                        }
-                    }
-                    Expression::Infix(inf) => {
-                        let left = Object::eval(vec![Statement::Expression(inf.left)], env);
-                        let right = Object::eval(vec![Statement::Expression(inf.right)], env);
-
-                        match (left, right) {
-                            (Object::Integer(il), Object::Integer(ir)) => match inf.token {
-                                Token::Plus => Ok(Object::Integer(il + ir)),
-                                Token::Minus => Ok(Object::Integer(il - ir)),
-                                Token::Asterisk => Ok(Object::Integer(il * ir)),
-                                Token::Slash => Ok(Object::Integer(il / ir)),
-                                Token::LessThan => Ok(Object::Boolean(il < ir)),
-                                Token::GreaterThan => Ok(Object::Boolean(il > ir)),
-                                Token::Equal => Ok(Object::Boolean(il == ir)),
-                                Token::NotEqual => Ok(Object::Boolean(il != ir)),
-                                _ => Err(anyhow!("Wrong oeprator token for infix")),
-                            },
-                            (Object::Boolean(bl), Object::Boolean(br)) => match inf.token {
-                                Token::Equal => Ok(Object::Boolean(bl == br)),
-                                Token::NotEqual => Ok(Object::Boolean(bl != br)),
-                                _ => Err(anyhow!("Wrong opertor for infix")),
-                            },
-                            (Object::String(sl), Object::String(sr)) => match inf.token {
-                                Token::Plus => Ok(Object::String(sl + &sr)),
-                                _ => Err(anyhow!("Wrong operator used to concatinate strings")),
-                            },
-                            _ => Err(anyhow!("Wrong token type in infix")),
-                        }
-                    }
-                    Expression::If(i) => {
-                        let condition = Object::eval(vec![Statement::Expression(i.condition)], env);
-                        // checking both if and else if for return statement to facilitiate nested
-                        // block statements that have returns
-                        if condition.is_truthy() {
-                            let object = Object::eval(i.consequence.statements, env);
-                            if object.expect_object_is(&Object::Return(Box::new(Object::Null))) {
-                                return object;
-                            };
-                            Ok(object)
-                        } else if let Some(alt) = i.alternative {
-                            let object = Object::eval(alt.statements, env);
-                            if object.expect_object_is(&Object::Return(Box::new(Object::Null))) {
-                                return object;
-                            };
-                            Ok(object)
-                        } else {
-                            Ok(Object::Null)
-                        }
-                    }
-                    Expression::Identifier(i) => match i {
-                        Token::Ident(s) => env.get(&s),
-                        _ => Err(anyhow!("Wrong token type for identifier")),
-                    },
-                    Expression::Fn(func) => {
-                        let clone_env = env.clone();
-                        Ok(Object::Function(FunctionObject::new(
-                            func.parameters,
-                            func.body,
-                            clone_env,
-                        )))
-                    }
-                    Expression::Call(call) => {
-                        // Get function from call
-                        let func = Object::eval(vec![Statement::Expression(call.function)], env);
-                        // turn arguments into objects
-                        let mut args: Vec<Object> = vec![];
-                        if let Some(arguments) = call.arguments {
-                            for arg in arguments.into_iter() {
-                                let eval = Object::eval(vec![Statement::Expression(arg)], env);
-                                args.push(eval)
-                            }
-                        }
-
-                        //apply the fucntion
-                        match func {
-                            Object::Function(f) => {
-                                let mut extended_env = f.environment.new_enclosed_environment();
-                                // gets the params from function and adds the idents to extended_env
-                                if let Some(params) = f.parameters {
-                                    for (i, param) in params.into_iter().enumerate() {
-                                        match param.token {
-                                            Token::Ident(s) => {
-                                                extended_env
-                                                    .store
-                                                    .insert(s.clone(), args[i].to_owned());
-                                            }
-                                            _ => todo!(),
-                                        }
-                                    }
-                                }
-                                let eval_body = Object::eval(f.body.statements, &mut extended_env);
-                                match eval_body {
-                                    Object::Return(r) => Ok(r.as_ref().to_owned()),
-                                    _ => Ok(eval_body),
-                                }
-                            }
-                            Object::BuiltinFunction(bf) => bf(Some(args)),
-                            _ => Err(anyhow!("Not a function")),
-                        }
-                    } // _ => Ok(Object::Null),
-                },
-            };
+        for test in tests.into_iter() {
+            let evaluated = test_eval(test.input);
+            assert_eq!(test.expected, evaluated);
         }
-        result.unwrap()
     }
-    fn is_truthy(&self) -> bool {
-        match &self {
-            Object::Null => false,
-            Object::Boolean(false) => false,
-            Object::Boolean(true) => true,
-            _ => true,
+    #[test]
+    fn test_recursive_functions() {
+        struct Test {
+            input: Vec<u8>,
+            expected: Object,
         }
-    }
+        let tests = vec![
+            Test {
+                input:
+                    "let fib = fn(n) { if (n < 2) { return n; } fib(n - 1) + fib(n - 2) }; fib(15);"
+                        .into(),
+                expected: Object::Integer(610),
+            },
+            Test {
+                input: "let isEven = fn(n) { if (n == 0) { true } else { isOdd(n - 1) } };
+                let isOdd = fn(n) { if (n == 0) { false } else { isEven(n - 1) } };
+                isEven(10);"
+                    .into(),
+                expected: Object::Boolean(true),
+            },
+            Test {
+                input: "let getX = fn() { x }; let x = 5; getX();".into(),
+                expected: Object::Integer(5),
+            },
+        ];
 
-    fn expect_object_is(&self, object: &Object) -> bool {
-        std::mem::discriminant(self) == std::mem::discriminant(object)
+        for test in tests.into_iter() {
+            let evaluated = test_eval(test.input);
+            assert_eq!(test.expected, evaluated);
+        }
     }
-}
 
-#[cfg(test)]
-mod test {
-    use crate::lexer::lexer::Lexer;
-    use crate::object::object::Object;
-    use crate::parser::parser::Parser;
+    #[test]
+    fn test_closures_share_environment() {
+        let env = Environment::new();
+        let enclosed = env.new_enclosed_environment();
+        env.set(String::from("a"), Object::Integer(1)).unwrap();
 
-    use super::Environment;
+        assert_eq!(
+            enclosed.get(&String::from("a")).unwrap(),
+            Object::Integer(1)
+        );
+        assert_eq!(env.clone(), env);
+        assert_ne!(enclosed, env);
+    }
 
     #[test]
-    fn test_builtin() {
+    fn test_nested_closures() {
         struct Test {
             input: Vec<u8>,
             expected: Object,
         }
-        let tests = vec![Test {
-            input: r#"len("four")"#.into(),
-            expected: Object::Integer(4),
-        }];
+        let tests = vec![
+            Test {
+                input:
+                    "let a = 1; let f = fn(b) { fn(c) { fn(d) { a + b + c + d } } }; f(2)(3)(4);"
+                        .into(),
+                expected: Object::Integer(10),
+            },
+            Test {
+                input: "let a = 1; let f = fn() { let a = 2; fn() { fn() { a } } }; f()()();"
+                    .into(),
+                expected: Object::Integer(2),
+            },
+            Test {
+                input: "let f = fn() { fn() { fn() { len(\"four\") } } }; f()()();".into(),
+                expected: Object::Integer(4),
+            },
+        ];
 
         for test in tests.into_iter() {
             let evaluated = test_eval(test.input);
@@ -298,22 +1520,246 @@ mod test {
     }
 
     #[test]
-    fn test_closures() {
+    fn test_environment_assign() {
+        let global = Environment::new();
+        global.set(String::from("a"), Object::Integer(1)).unwrap();
+        let middle = global.new_enclosed_environment();
+        middle.set(String::from("b"), Object::Integer(2)).unwrap();
+        let inner = middle.new_enclosed_environment();
+
+        inner
+            .assign(&String::from("a"), Object::Integer(10))
+            .unwrap();
+        inner
+            .assign(&String::from("b"), Object::Integer(20))
+            .unwrap();
+
+        assert_eq!(global.get(&String::from("a")).unwrap(), Object::Integer(10));
+        assert_eq!(middle.get(&String::from("b")).unwrap(), Object::Integer(20));
+        assert!(!inner.store.borrow().contains_key("a"));
+        assert!(inner
+            .assign(&String::from("c"), Object::Integer(3))
+            .is_err());
+    }
+
+    #[test]
+    fn test_function_arity() {
         struct Test {
             input: Vec<u8>,
             expected: Object,
         }
-        let tests = vec![Test {
-            input: "let newAdder = fn(x) {fn(y) { x + y };};let addTwo = newAdder(2);addTwo(2);"
-                .into(),
-            expected: Object::Integer(4),
-        }];
+        let tests = vec![
+            Test {
+                input: "let add = fn(x, y) { x + y }; add(1);".into(),
+                expected: Object::Error(String::from(
+                    "line 1, column 31: Wrong number of arguments for add: expected 2, got 1",
+                )),
+            },
+            Test {
+                input: "let add = fn(x, y) { x + y }; add(1, 2, 3);".into(),
+                expected: Object::Error(String::from(
+                    "line 1, column 31: Wrong number of arguments for add: expected 2, got 3",
+                )),
+            },
+            Test {
+                input: "fn() { 1 }(1);".into(),
+                expected: Object::Error(String::from(
+                    "line 1, column 1: Wrong number of arguments for anonymous function: expected 0, got 1",
+                )),
+            },
+            Test {
+                input: "len();".into(),
+                expected: Object::Error(String::from(
+                    "line 1, column 1: Wrong number of arguments for len: expected 1, got 0",
+                )),
+            },
+            Test {
+                input: r#"len("one", "two");"#.into(),
+                expected: Object::Error(String::from(
+                    "line 1, column 1: Wrong number of arguments for len: expected 1, got 2",
+                )),
+            },
+        ];
 
         for test in tests.into_iter() {
             let evaluated = test_eval(test.input);
             assert_eq!(test.expected, evaluated);
         }
     }
+
+    #[test]
+    fn test_function_declarations() {
+        let tests = vec![
+            (
+                "fn fact(n) { if (n < 2) { 1 } else { n * fact(n - 1) } } fact(5)",
+                Object::Integer(120),
+            ),
+            (
+                "fn even(n) { if (n == 0) { true } else { odd(n - 1) } } fn odd(n) { if (n == 0) { false } else { even(n - 1) } } [even(4), odd(4)]",
+                Object::Array(vec![Object::Boolean(true), Object::Boolean(false)]),
+            ),
+            (
+                "fn outer() { fn inner() { 2 }; inner() + 1 } outer()",
+                Object::Integer(3),
+            ),
+            (
+                "const add = 1; fn add(x, y) { x + y }",
+                Object::Error(String::from(
+                    "line 1, column 16: Cannot redeclare constant: add",
+                )),
+            ),
+        ];
+
+        for (input, expected) in tests.into_iter() {
+            assert_eq!(test_eval(input.into()), expected, "input: {}", input);
+        }
+
+        let displayed = vec![
+            (
+                "fn add(x, y = 1, ...rest) { x }",
+                "Function Value: fn add(x, y, ...rest)",
+            ),
+            ("fn() { 1 }", "Function Value: fn()"),
+        ];
+        for (input, expected) in displayed.into_iter() {
+            assert_eq!(
+                test_eval(input.into()).to_string(),
+                expected,
+                "input: {}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_pipeline() {
+        let tests = vec![
+            ("\"four\" |> len", Object::Integer(4)),
+            (
+                "fn add(x, y) { x + y } 1 |> add(2) |> add(3) |> str",
+                Object::String(String::from("6")),
+            ),
+            (
+                "let double = fn(x) { x * 2 }; 1 + 2 |> double",
+                Object::Integer(6),
+            ),
+            (
+                "fn scale(x, by = 2) { x * by } [3 |> scale, 3 |> scale(by: 3)]",
+                Object::Array(vec![Object::Integer(6), Object::Integer(9)]),
+            ),
+            ("let x = 0; x = 5 |> fn(n) { n - 1 }; x", Object::Integer(4)),
+            (
+                "let f = fn(x) { fn(y) { fn(z) { x * 100 + y * 10 + z } } }; 3 |> f(1)(2)",
+                Object::Integer(123),
+            ),
+            (
+                "let add = fn(x) { fn(y) { x - y } }; 1 |> (add(3))",
+                Object::Integer(2),
+            ),
+            (
+                "1 |> len(2)",
+                Object::Error(String::from(
+                    "line 1, column 6: Wrong number of arguments for len: expected 1, got 2",
+                )),
+            ),
+            (
+                "1 |> 2",
+                Object::Error(String::from("line 1, column 6: Not a function: 2")),
+            ),
+        ];
+
+        for (input, expected) in tests.into_iter() {
+            assert_eq!(test_eval(input.into()), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_function_parameters() {
+        let tests = vec![
+            (
+                "let f = fn(x, y = 10) { x + y }; [f(1), f(1, 2)]",
+                Object::Array(vec![Object::Integer(11), Object::Integer(3)]),
+            ),
+            (
+                "let f = fn(x, y = x * 2) { y }; f(4)",
+                Object::Integer(8),
+            ),
+            (
+                "let f = fn(first, ...others) { [first, others] }; f(1, 2, 3)",
+                Object::Array(vec![
+                    Object::Integer(1),
+                    Object::Array(vec![Object::Integer(2), Object::Integer(3)]),
+                ]),
+            ),
+            (
+                "let f = fn(...all) { all }; f()",
+                Object::Array(vec![]),
+            ),
+            (
+                "let f = fn(x, y = 1, z = 2) { [x, y, z] }; f(z: 5, x: 0)",
+                Object::Array(vec![
+                    Object::Integer(0),
+                    Object::Integer(1),
+                    Object::Integer(5),
+                ]),
+            ),
+            (
+                "let f = fn(x, y) { x - y }; f(1, y: 3)",
+                Object::Integer(-2),
+            ),
+            (
+                "let f = fn(x, y = 2) { x }; f(1, 2, 3)",
+                Object::Error(String::from(
+                    "line 1, column 29: Wrong number of arguments for f: expected 1 to 2, got 3",
+                )),
+            ),
+            (
+                "fn fact(n) { if (n < 2) { 1 } else { n * fact(n - 1) } } let g = fact; g(1, 2)",
+                Object::Error(String::from(
+                    "line 1, column 72: Wrong number of arguments for fact: expected 1, got 2",
+                )),
+            ),
+            (
+                "let f = fn(x, y, ...z) { x }; f(1)",
+                Object::Error(String::from(
+                    "line 1, column 31: Wrong number of arguments for f: expected at least 2, got 1",
+                )),
+            ),
+            (
+                "let f = fn(x, y) { x }; f(1, z: 2)",
+                Object::Error(String::from(
+                    "line 1, column 25: Unknown keyword argument for f: z",
+                )),
+            ),
+            (
+                "let f = fn(x, y) { x }; f(1, x: 2)",
+                Object::Error(String::from(
+                    "line 1, column 25: Argument given twice for f: x",
+                )),
+            ),
+            (
+                "let f = fn(x, y) { x }; f(y: 2)",
+                Object::Error(String::from("line 1, column 25: Missing argument for f: x")),
+            ),
+            (
+                "let f = fn(x, ...r) { x }; f(1, r: 2)",
+                Object::Error(String::from(
+                    "line 1, column 28: Unknown keyword argument for f: r",
+                )),
+            ),
+            (
+                "len(s: \"abc\")",
+                Object::Error(String::from(
+                    "line 1, column 1: Builtin functions do not take keyword arguments",
+                )),
+            ),
+        ];
+
+        for (input, expected) in tests.into_iter() {
+            assert_eq!(test_eval(input.into()), expected, "input: {}", input);
+        }
+    }
+
     #[test]
     fn test_function() {
         struct Test {
@@ -393,6 +1839,14 @@ mod test {
                 input: "if (10 > 1){if (10 > 1){return 10;}return 1;}".into(),
                 expected: Object::Return(Box::new(Object::Integer(10))),
             },
+            Test {
+                input: "let f = fn() { let a = [1, if (true) { return 5; }]; 7 }; f()".into(),
+                expected: Object::Integer(5),
+            },
+            Test {
+                input: "let f = fn() { 1 + len(if (true) { return 2; }) }; f()".into(),
+                expected: Object::Integer(2),
+            },
         ];
 
         for test in tests.into_iter() {
@@ -436,6 +1890,26 @@ mod test {
                 input: "if (1 > 2){10}else{20}".into(),
                 expected: Object::Integer(20),
             },
+            Test {
+                input: "if (1 > 2) { 10 } else if (2 > 1) { 20 } else { 30 }".into(),
+                expected: Object::Integer(20),
+            },
+            Test {
+                input: "if (false) { 10 } else if (false) { 20 } else { 30 }".into(),
+                expected: Object::Integer(30),
+            },
+            Test {
+                input: "if (false) { 10 } else if (false) { 20 }".into(),
+                expected: Object::Null,
+            },
+            Test {
+                input: "let f = fn(x) { if (x == 1) { return 10; } else if (x == 2) { return 20; } 30 }; [f(1), f(2), f(3)]".into(),
+                expected: Object::Array(vec![
+                    Object::Integer(10),
+                    Object::Integer(20),
+                    Object::Integer(30),
+                ]),
+            },
         ];
 
         for test in tests.into_iter() {
@@ -491,6 +1965,490 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_integer_arithmetic_errors() {
+        let tests = vec![
+            ("1 / 0", "line 1, column 3: Division by zero: 1 Slash 0"),
+            (
+                "let f = fn(x) { 10 / x }; f(0)",
+                "line 1, column 20: Division by zero: 10 Slash 0",
+            ),
+            (
+                "9223372036854775807 + 1",
+                "line 1, column 21: Integer overflow: 9223372036854775807 Plus 1",
+            ),
+            (
+                "-9223372036854775807 - 2",
+                "line 1, column 22: Integer overflow: -9223372036854775807 Minus 2",
+            ),
+            (
+                "4611686018427387904 * 2",
+                "line 1, column 21: Integer overflow: 4611686018427387904 Asterisk 2",
+            ),
+            (
+                "let min = -9223372036854775807 - 1; min / -1",
+                "line 1, column 41: Integer overflow: -9223372036854775808 Slash -1",
+            ),
+            (
+                "let min = -9223372036854775807 - 1; -min",
+                "line 1, column 37: Integer overflow: Minus -9223372036854775808",
+            ),
+        ];
+
+        for (input, expected) in tests.into_iter() {
+            assert_eq!(
+                test_eval(input.into()),
+                Object::Error(String::from(expected)),
+                "input: {}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_extended_operators() {
+        let tests = vec![
+            ("7 % 3", Object::Integer(1)),
+            ("-7 % 3", Object::Integer(-1)),
+            ("7.5 % 2", Object::Float(1.5)),
+            ("2 ** 10", Object::Integer(1024)),
+            ("2 ** 3 ** 2", Object::Integer(512)),
+            ("-2 ** 2", Object::Integer(-4)),
+            ("2 ** 0.5 > 1.41", Object::Boolean(true)),
+            (
+                "4 ** -1",
+                Object::Error(String::from(
+                    "line 1, column 3: Negative exponent: 4 Power -1",
+                )),
+            ),
+            ("3 <= 3", Object::Boolean(true)),
+            ("3 >= 4", Object::Boolean(false)),
+            ("1.5 <= 2", Object::Boolean(true)),
+            ("6 & 3", Object::Integer(2)),
+            ("6 | 3", Object::Integer(7)),
+            ("6 ^ 3", Object::Integer(5)),
+            ("true ^ true", Object::Boolean(false)),
+            ("1 << 4", Object::Integer(16)),
+            ("-16 >> 2", Object::Integer(-4)),
+            ("1 + 1 << 2", Object::Integer(8)),
+            ("1 | 2 == 3", Object::Boolean(true)),
+            (
+                "1 << 64",
+                Object::Error(String::from(
+                    "line 1, column 3: Integer overflow: 1 ShiftLeft 64",
+                )),
+            ),
+            (
+                "1 % 0",
+                Object::Error(String::from(
+                    "line 1, column 3: Division by zero: 1 Percent 0",
+                )),
+            ),
+            (
+                "1.5 & 1",
+                Object::Error(String::from(
+                    "line 1, column 5: Unknown operator: Float Ampersand Float",
+                )),
+            ),
+        ];
+
+        for (input, expected) in tests.into_iter() {
+            assert_eq!(test_eval(input.into()), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_logical_operators() {
+        let tests = vec![
+            ("true && true", Object::Boolean(true)),
+            ("true && false", Object::Boolean(false)),
+            ("false || true", Object::Boolean(true)),
+            ("false || false", Object::Boolean(false)),
+            ("1 && \"a\"", Object::Boolean(true)),
+            ("if (false) { 1 } || 0", Object::Boolean(true)),
+            ("1 < 2 && 2 < 3 || false", Object::Boolean(true)),
+            ("false && missing()", Object::Boolean(false)),
+            ("true || 1 / 0", Object::Boolean(true)),
+            (
+                "true && missing",
+                Object::Error(String::from(
+                    "line 1, column 9: Identifier not found: missing",
+                )),
+            ),
+        ];
+
+        for (input, expected) in tests.into_iter() {
+            assert_eq!(test_eval(input.into()), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_loops() {
+        let tests = vec![
+            (
+                "let i = 0; let sum = 0; while (i < 5) { i += 1; sum += i; }; sum",
+                Object::Integer(15),
+            ),
+            ("while (false) { 1 }", Object::Null),
+            (
+                "let out = \"\"; for (c in \"abc\") { out = c + out; } out",
+                Object::String(String::from("cba")),
+            ),
+            (
+                r#"let total = 0; for (k in {3: "c", 1: "a", 2: "b"}) { total = total * 10 + k; } total"#,
+                Object::Integer(123),
+            ),
+            (
+                "let last = 0; for (x in [1, 2, 3, 4]) { if (x == 3) { break; } last = x; } last",
+                Object::Integer(2),
+            ),
+            (
+                "let sum = 0; for (x in [1, 2, 3, 4]) { if (x % 2 == 0) { continue; } sum += x; } sum",
+                Object::Integer(4),
+            ),
+            (
+                "let sum = 0; for (x in [1, 2]) { for (y in [10, 20]) { if (y > 10) { break; } sum += x * y; } } sum",
+                Object::Integer(30),
+            ),
+            (
+                "let x = 100; for (x in [1, 2]) { let y = x; } [x, y]",
+                Object::Error(String::from("line 1, column 51: Identifier not found: y")),
+            ),
+            (
+                "let x = 100; for (x in [1, 2]) {}; x",
+                Object::Integer(100),
+            ),
+            (
+                "let s = 0; for (x in [1, 2, 3]) { s += 1 + if (x == 2) { continue; } else { 0 }; } s",
+                Object::Integer(2),
+            ),
+            (
+                "let s = 0; let i = 0; while (i < 5) { i += 1; s += [1, i, if (i == 3) { break; } else { 2 }][1]; } [s, i]",
+                Object::Array(vec![Object::Integer(3), Object::Integer(3)]),
+            ),
+            (
+                "let find = fn(xs) { for (x in xs) { if (x > 1) { return x; } } -1 }; find([1, 5, 7])",
+                Object::Integer(5),
+            ),
+            (
+                "let i = 0; while (true) { i += 1; if (i > 2) { break; } } i",
+                Object::Integer(3),
+            ),
+            (
+                "let i = 0; while (i < 2) { let y = i; i += 1; } y",
+                Object::Error(String::from("line 1, column 49: Identifier not found: y")),
+            ),
+            (
+                "for (x in 5) { x }",
+                Object::Error(String::from("line 1, column 11: Cannot iterate over 5")),
+            ),
+        ];
+
+        for (input, expected) in tests.into_iter() {
+            assert_eq!(test_eval(input.into()), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_assignment() {
+        let tests = vec![
+            ("let x = 1; x = 2; x", Object::Integer(2)),
+            (
+                "let x = 1; x += 2; x *= 5; x -= 1; x /= 2; x %= 4; x",
+                Object::Integer(3),
+            ),
+            (
+                "let a = 1; let b = 2; a = b = 5; a + b",
+                Object::Integer(10),
+            ),
+            ("let x = 5; (x = 3) + x", Object::Integer(6)),
+            (
+                "let x = 0; let inc = fn() { x += 1 }; inc(); inc(); x",
+                Object::Integer(2),
+            ),
+            (
+                "let x = 0; let f = fn() { let x = 1; x = 2; x }; [f(), x]",
+                Object::Array(vec![Object::Integer(2), Object::Integer(0)]),
+            ),
+            (
+                r#"let s = "a"; s += "b"; s"#,
+                Object::String(String::from("ab")),
+            ),
+            (
+                "let i = 0; let sum = 0; while (i < 4) { i += 1; sum += i; } sum",
+                Object::Integer(10),
+            ),
+            (
+                "y = 1",
+                Object::Error(String::from(
+                    "line 1, column 3: Cannot assign to undefined identifier: y",
+                )),
+            ),
+            (
+                "y += 1",
+                Object::Error(String::from("line 1, column 1: Identifier not found: y")),
+            ),
+            (
+                "let x = 1; x /= 0",
+                Object::Error(String::from(
+                    "line 1, column 14: Division by zero: 1 Slash 0",
+                )),
+            ),
+        ];
+
+        for (input, expected) in tests.into_iter() {
+            assert_eq!(test_eval(input.into()), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_constants() {
+        let tests = vec![
+            ("const x = 1; x + 1", Object::Integer(2)),
+            (
+                "const x = 1; x = 2",
+                Object::Error(String::from(
+                    "line 1, column 16: Cannot assign to constant: x",
+                )),
+            ),
+            (
+                "const x = 1; x += 2",
+                Object::Error(String::from(
+                    "line 1, column 16: Cannot assign to constant: x",
+                )),
+            ),
+            (
+                "const x = 1; let x = 2",
+                Object::Error(String::from(
+                    "line 1, column 14: Cannot redeclare constant: x",
+                )),
+            ),
+            (
+                "const x = 1; const x = 2",
+                Object::Error(String::from(
+                    "line 1, column 14: Cannot redeclare constant: x",
+                )),
+            ),
+            (
+                "const x = 1; for (x in [2]) { const y = x; }; x",
+                Object::Integer(1),
+            ),
+            (
+                "const x = 1; let f = fn() { let x = 2; x }; f() + x",
+                Object::Integer(3),
+            ),
+            (
+                "const x = 1; let f = fn() { x = 2 }; f()",
+                Object::Error(String::from(
+                    "line 1, column 31: Cannot assign to constant: x",
+                )),
+            ),
+            ("let x = 1; const x = 2; x", Object::Integer(2)),
+        ];
+
+        for (input, expected) in tests.into_iter() {
+            assert_eq!(test_eval(input.into()), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_match() {
+        let tests = vec![
+            (
+                r#"match (2) { 1 => "one", 2 => "two", _ => "many" }"#,
+                Object::String(String::from("two")),
+            ),
+            (
+                r#"match (-1.5) { -1.5 => "neg", _ => "other" }"#,
+                Object::String(String::from("neg")),
+            ),
+            (r#"match ("b") { "a" => 1, "b" => 2 }"#, Object::Integer(2)),
+            ("match (true) { false => 0, true => 1 }", Object::Integer(1)),
+            (
+                "match (7) { n if (n > 5) => n * 2, n => n }",
+                Object::Integer(14),
+            ),
+            (
+                "match (3) { n if (n > 5) => n * 2, n => n }",
+                Object::Integer(3),
+            ),
+            (
+                "match ([1, 2, 3]) { [] => 0, [x] => x, [x, y] => x + y, [x, ...rest] => rest }",
+                Object::Array(vec![Object::Integer(2), Object::Integer(3)]),
+            ),
+            (
+                "match ([1]) { [x, y, ..._] => 0, [_, ..._] => 1 }",
+                Object::Integer(1),
+            ),
+            (
+                "match ([1, [2, 3]]) { [a, [b, c]] => a + b + c }",
+                Object::Integer(6),
+            ),
+            (
+                r#"match ({"name": "ann", "age": 30}) { {"age": 31} => "", {"age": 30, name} => name }"#,
+                Object::String(String::from("ann")),
+            ),
+            (
+                r#"match ({"name": "bob"}) { {age} => age, {name: [n]} => n, {name: n} => n }"#,
+                Object::String(String::from("bob")),
+            ),
+            ("let x = 10; match (1) { x => x }; x", Object::Integer(10)),
+            (
+                "let count = 0; match (5) { n => count += n }; count",
+                Object::Integer(5),
+            ),
+            (
+                r#"let classify = fn(x) { match (x) { 0 => "zero", n if (n < 0) => "negative", _ => "positive" } }; [classify(0), classify(-3), classify(4)]"#,
+                Object::Array(vec![
+                    Object::String(String::from("zero")),
+                    Object::String(String::from("negative")),
+                    Object::String(String::from("positive")),
+                ]),
+            ),
+            (
+                r#"match (1.0) { 1 => "int", _ => "other" }"#,
+                Object::String(String::from("int")),
+            ),
+            (
+                r#"match (1) { "1" => "string", true => "bool", 1.0 => "float" }"#,
+                Object::String(String::from("float")),
+            ),
+            (
+                r#"let f = fn(x) { match (x) { 0 => { return "z" }, n => { let m = n * 2; m } }; "after" }; [f(0), f(2)]"#,
+                Object::Array(vec![
+                    Object::String(String::from("z")),
+                    Object::String(String::from("after")),
+                ]),
+            ),
+            (
+                "match (3) { n => { let m = n * 2; m + 1 } }",
+                Object::Integer(7),
+            ),
+            (
+                "match (5) { 1 => 1 }",
+                Object::Error(String::from("line 1, column 1: No match arm for 5")),
+            ),
+            (
+                "match (5) { n if (n + true) => 1 }",
+                Object::Error(String::from(
+                    "line 1, column 21: Type mismatch: 5 Plus true",
+                )),
+            ),
+        ];
+
+        for (input, expected) in tests.into_iter() {
+            assert_eq!(test_eval(input.into()), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_destructuring_let() {
+        let tests = vec![
+            (
+                "let [a, b, ...rest] = [1, 2, 3, 4]; [a, b, rest]",
+                Object::Array(vec![
+                    Object::Integer(1),
+                    Object::Integer(2),
+                    Object::Array(vec![Object::Integer(3), Object::Integer(4)]),
+                ]),
+            ),
+            ("let [a, ...rest] = [1]; rest", Object::Array(vec![])),
+            (
+                r#"let person = {"name": "ann", "age": 30}; let {name, age} = person; name + " " + str(age)"#,
+                Object::String(String::from("ann 30")),
+            ),
+            (
+                r#"let {"pos": [x, y], name: n} = {"pos": [3, 4], "name": "p"}; x * y"#,
+                Object::Integer(12),
+            ),
+            ("let [_, second] = [1, 2]; second", Object::Integer(2)),
+            (
+                "let f = fn(pair) { let [a, b] = pair; a - b }; f([5, 3])",
+                Object::Integer(2),
+            ),
+            (
+                "const [a] = [1]; a = 2",
+                Object::Error(String::from(
+                    "line 1, column 20: Cannot assign to constant: a",
+                )),
+            ),
+            (
+                "let [a, b] = [1, 2, 3]",
+                Object::Error(String::from(
+                    "line 1, column 1: Cannot destructure [1, 2, 3]: expected an array of 2 items, got [1, 2, 3]",
+                )),
+            ),
+            (
+                r#"let {name} = {"age": 1}"#,
+                Object::Error(String::from(
+                    r#"line 1, column 1: Cannot destructure {"age": 1}: missing key "name""#,
+                )),
+            ),
+            (
+                "let [a] = 5",
+                Object::Error(String::from(
+                    "line 1, column 1: Cannot destructure 5: expected an array of 1 item, got 5",
+                )),
+            ),
+            (
+                "let [a, ...rest] = []",
+                Object::Error(String::from(
+                    "line 1, column 1: Cannot destructure []: expected an array of at least 1 item, got []",
+                )),
+            ),
+            (
+                r#"let [x, {k}] = [1, {"j": 2}]"#,
+                Object::Error(String::from(
+                    r#"line 1, column 1: Cannot destructure [1, {"j": 2}]: missing key "k""#,
+                )),
+            ),
+            (
+                "let [x, {k}] = [1, [2]]",
+                Object::Error(String::from(
+                    "line 1, column 1: Cannot destructure [1, [2]]: expected a hash, got [2]",
+                )),
+            ),
+            (
+                "let [1, y] = [2, 3]",
+                Object::Error(String::from(
+                    "line 1, column 1: Cannot destructure [2, 3]: expected 1, got 2",
+                )),
+            ),
+            (
+                "const a = 1; let [a] = [2]",
+                Object::Error(String::from(
+                    "line 1, column 14: Cannot redeclare constant: a",
+                )),
+            ),
+        ];
+
+        for (input, expected) in tests.into_iter() {
+            assert_eq!(test_eval(input.into()), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_float_expressions() {
+        let tests = vec![
+            ("1.5", Object::Float(1.5)),
+            ("-2.5e-1", Object::Float(-0.25)),
+            ("1.5 + 1.5", Object::Float(3.0)),
+            ("1 + 0.5", Object::Float(1.5)),
+            ("0.5 * 4", Object::Float(2.0)),
+            ("7 / 2", Object::Integer(3)),
+            ("7 / 2.0", Object::Float(3.5)),
+            ("1 / 0.0", Object::Float(f64::INFINITY)),
+            ("1 < 1.5", Object::Boolean(true)),
+            ("2.0 > 3", Object::Boolean(false)),
+            ("1 == 1.0", Object::Boolean(true)),
+            ("0.1 + 0.2 != 0.3", Object::Boolean(true)),
+        ];
+
+        for (input, expected) in tests.into_iter() {
+            assert_eq!(test_eval(input.into()), expected, "input: {}", input);
+        }
+    }
+
     #[test]
     fn test_boolean_experssion() {
         struct Test {
@@ -620,12 +2578,55 @@ mod test {
             assert_eq!(test.expected, evaluated);
         }
     }
+    #[test]
+    fn test_recursion_limit() {
+        // a test thread does not have enough stack for deep recursion
+        std::thread::Builder::new()
+            .stack_size(EVAL_STACK_SIZE)
+            .spawn(|| {
+                let probe = "let f = fn(n) { if (n == 0) { 0 } else { 1 + f(n - 1) } };";
+                let tests = vec![
+                    (format!("{} f(1000)", probe), Object::Integer(1000)),
+                    (
+                        format!("{} f(5000)", probe),
+                        Object::Error(String::from(
+                            "line 1, column 46: Maximum recursion depth exceeded: more than 2000 nested calls",
+                        )),
+                    ),
+                    (
+                        String::from("let f = fn() { f() }; f()"),
+                        Object::Error(String::from(
+                            "line 1, column 16: Maximum recursion depth exceeded: more than 2000 nested calls",
+                        )),
+                    ),
+                ];
+                for (input, expected) in tests.into_iter() {
+                    assert_eq!(test_eval(input.clone().into()), expected, "input: {}", input);
+                }
+
+                // the depth goes back down when an error unwinds the calls
+                let env = Environment::new();
+                for (input, expected) in [
+                    (format!("{} f(5000)", probe), false),
+                    (String::from("f(1000)"), true),
+                ] {
+                    let program = Parser::new(Lexer::new(input.into())).parse_program();
+                    let result = Object::eval(program.statements, &env);
+                    assert_eq!(result == Object::Integer(1000), expected);
+                }
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
+    #[allow(clippy::needless_return)]
     fn test_eval(input: Vec<u8>) -> Object {
         let lex = Lexer::new(input);
         let mut parser = Parser::new(lex);
-        let program = parser.parse_program().unwrap();
-        let mut env = Environment::new();
+        let program = parser.parse_program();
+        let env = Environment::new();
 
-        return Object::eval(program.statements, &mut env);
+        return Object::eval(program.statements, &env);
     }
 }
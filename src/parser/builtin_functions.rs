@@ -15,24 +15,92 @@ impl BuiltinFunctions {
         let map: HashMap<String, BuiltinFn> = HashMap::new();
         let mut bf = BuiltinFunctions { fns: map };
         bf.set_fn("len".to_string(), len);
+        bf.set_fn("int".to_string(), int);
+        bf.set_fn("float".to_string(), float);
+        bf.set_fn("str".to_string(), str);
         bf
     }
     pub fn get_fn(&self, name: String) -> Option<BuiltinFn> {
         self.fns.get(&name).copied()
     }
 
-    fn set_fn(&mut self, name: String, func: BuiltinFn) -> () {
+    fn set_fn(&mut self, name: String, func: BuiltinFn) {
         self.fns.insert(name, func);
     }
 }
 
+/// Unwraps the arguments passed to a builtin, checking that exactly `expected` were given.
+fn expect_arguments(
+    name: &str,
+    arguments: Option<Vec<Object>>,
+    expected: usize,
+) -> Result<Vec<Object>> {
+    let args = arguments.unwrap_or_default();
+    if args.len() != expected {
+        return Err(anyhow!(
+            "Wrong number of arguments for {}: expected {}, got {}",
+            name,
+            expected,
+            args.len()
+        ));
+    }
+    Ok(args)
+}
+
 fn len(arguments: Option<Vec<Object>>) -> Result<Object> {
-    if let Some(args) = arguments {
-        match &args[0] {
-            Object::String(s) => Ok(Object::Integer(s.len() as isize)),
-            _ => Err(anyhow!("Wrong argument type for len function.")),
+    let args = expect_arguments("len", arguments, 1)?;
+    match &args[0] {
+        Object::String(s) => Ok(Object::Integer(s.len() as isize)),
+        _ => Err(anyhow!("Wrong argument type for len function.")),
+    }
+}
+
+/// Converts to an integer, truncating floats toward zero.
+fn int(arguments: Option<Vec<Object>>) -> Result<Object> {
+    let args = expect_arguments("int", arguments, 1)?;
+    match &args[0] {
+        Object::Integer(i) => Ok(Object::Integer(*i)),
+        Object::Float(f) => {
+            let truncated = f.trunc();
+            if !truncated.is_finite()
+                || truncated < isize::MIN as f64
+                || truncated >= isize::MAX as f64
+            {
+                return Err(anyhow!("Cannot convert {:?} to an integer", f));
+            }
+            Ok(Object::Integer(truncated as isize))
         }
-    } else {
-        Err(anyhow!("Argument must be suplied"))
+        Object::String(s) => s
+            .trim()
+            .parse()
+            .map(Object::Integer)
+            .map_err(|_| anyhow!("Cannot convert {:?} to an integer", s)),
+        other => Err(anyhow!(
+            "Wrong argument type for int function: {}",
+            other.inspect()
+        )),
     }
 }
+
+fn float(arguments: Option<Vec<Object>>) -> Result<Object> {
+    let args = expect_arguments("float", arguments, 1)?;
+    match &args[0] {
+        Object::Integer(i) => Ok(Object::Float(*i as f64)),
+        Object::Float(f) => Ok(Object::Float(*f)),
+        Object::String(s) => s
+            .trim()
+            .parse()
+            .map(Object::Float)
+            .map_err(|_| anyhow!("Cannot convert {:?} to a float", s)),
+        other => Err(anyhow!(
+            "Wrong argument type for float function: {}",
+            other.inspect()
+        )),
+    }
+}
+
+/// Converts any value to a string, the same way string interpolation does.
+fn str(arguments: Option<Vec<Object>>) -> Result<Object> {
+    let args = expect_arguments("str", arguments, 1)?;
+    Ok(Object::String(args[0].to_display_string()))
+}
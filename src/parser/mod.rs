@@ -1,3 +1,3 @@
 pub mod builtin_functions;
+#[allow(clippy::module_inception)]
 pub mod parser;
-
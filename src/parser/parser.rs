@@ -1,35 +1,69 @@
 use crate::{
     ast::ast::{
-        BlockStatement, CallExpression, Expression, FnExpression, Identifier, IfExpression,
-        InfixExpression, Let, PrefixExpression, Program, Return, Statement,
+        AssignExpression, BlockStatement, CallExpression, Expression, FnExpression, For,
+        Identifier, IfExpression, IndexExpression, InfixExpression, Let, LetPattern, MatchArm,
+        MatchExpression, Parameter, Pattern, PrefixExpression, Program, Return, Statement, While,
     },
-    lexer::lexer::{Lexer, Token},
+    lexer::lexer::{Lexer, Span, SpannedToken, StringSegment, Token},
 };
-use anyhow::{anyhow, Ok, Result};
+use anyhow::{anyhow, Result};
+
+/// The positional and the keyword arguments of a call.
+type Arguments = (Option<Vec<Expression>>, Vec<(Identifier, Expression)>);
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Precidence {
     Lowest,
+    Pipeline,
+    Assign,
+    Or,
+    And,
     Equals,
     LessGreater,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Shift,
     Sum,
     Product,
     Prefix,
+    // binds tighter than prefix operators, so -2 ** 2 is -(2 ** 2)
+    Power,
     Call,
+    Index,
 }
 
 impl From<&Token> for Precidence {
     fn from(token: &Token) -> Self {
         match token {
+            Token::Pipeline => Precidence::Pipeline,
+            Token::Assign
+            | Token::PlusAssign
+            | Token::MinusAssign
+            | Token::AsteriskAssign
+            | Token::SlashAssign
+            | Token::PercentAssign => Precidence::Assign,
+            Token::Or => Precidence::Or,
+            Token::And => Precidence::And,
             Token::Equal => Precidence::Equals,
             Token::NotEqual => Precidence::Equals,
             Token::LessThan => Precidence::LessGreater,
             Token::GreaterThan => Precidence::LessGreater,
+            Token::LessEqual => Precidence::LessGreater,
+            Token::GreaterEqual => Precidence::LessGreater,
+            Token::Pipe => Precidence::BitOr,
+            Token::Caret => Precidence::BitXor,
+            Token::Ampersand => Precidence::BitAnd,
+            Token::ShiftLeft => Precidence::Shift,
+            Token::ShiftRight => Precidence::Shift,
             Token::Plus => Precidence::Sum,
             Token::Minus => Precidence::Sum,
             Token::Slash => Precidence::Product,
             Token::Asterisk => Precidence::Product,
+            Token::Percent => Precidence::Product,
+            Token::Power => Precidence::Power,
             Token::LParen => Precidence::Call,
+            Token::LBracket => Precidence::Index,
             _ => Precidence::Lowest,
         }
     }
@@ -38,24 +72,55 @@ impl From<&Token> for Precidence {
 pub struct Parser {
     lexer: Lexer,
     current_token: Token,
+    current_span: Span,
     peek_token: Token,
+    peek_span: Span,
     errors: Vec<String>,
+    /// How many loops enclose the current token within the current function, used to reject
+    /// `break` and `continue` outside of a loop.
+    loop_depth: usize,
 }
 
+/// Rejects a pattern binding the same name twice, as in `let [a, a] = pair`.
+fn check_duplicate_bindings(pattern: &Pattern) -> Result<()> {
+    let bindings = pattern.bindings();
+    for (i, name) in bindings.iter().enumerate() {
+        if let Token::Ident(s) = &name.token {
+            if bindings[..i]
+                .iter()
+                .any(|earlier| earlier.token == name.token)
+            {
+                return Err(anyhow!(
+                    "{}: {} is bound more than once in the pattern",
+                    name.span,
+                    s
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[allow(clippy::needless_return)]
 impl Parser {
     pub fn new(mut lexer: Lexer) -> Parser {
-        let current_token = lexer.next_token();
-        let peek_token = lexer.next_token();
+        let current = Parser::read_token(&mut lexer);
+        let peek = Parser::read_token(&mut lexer);
         let parser = Parser {
             lexer,
-            current_token: current_token.clone(),
-            peek_token: peek_token.clone(),
+            current_token: current.token,
+            current_span: current.span,
+            peek_token: peek.token,
+            peek_span: peek.span,
             errors: vec![],
+            loop_depth: 0,
         };
 
         return parser;
     }
-    pub fn parse_program(&mut self) -> Option<Program> {
+    /// Parses the whole input. Statements that fail to parse are left out of the program and
+    /// their errors collected in `errors`, so one pass reports every syntax problem.
+    pub fn parse_program(&mut self) -> Program {
         let mut statements: Vec<Statement> = Vec::new();
         while self.current_token != Token::EOF {
             if let Some(s) = self.parse_statement() {
@@ -63,70 +128,253 @@ impl Parser {
             };
             self.next_token();
         }
-        return Some(Program::new(statements));
+        return Program::new(statements);
     }
 
     pub fn errors(&mut self) -> Vec<String> {
         return self.errors.clone();
     }
 
-    fn peek_error(&mut self, t: Token) {
-        self.errors
-            .push(format!("Expected {} but got {}", self.peek_token, t));
+    fn peek_error(&self, t: Token) -> anyhow::Error {
+        // only the kind of token is expected, so leave out the empty identifier name
+        let expected = match t {
+            Token::Ident(_) => String::from("Ident"),
+            t => t.to_string(),
+        };
+        anyhow!(
+            "{}: Expected {} but got {}",
+            self.peek_span,
+            expected,
+            self.peek_token
+        )
+    }
+
+    /// The next token that is not a comment, so the parser also accepts a lexer that keeps
+    /// comments.
+    fn read_token(lexer: &mut Lexer) -> SpannedToken {
+        loop {
+            let next = lexer.next_token();
+            if !matches!(next.token, Token::Comment(_)) {
+                return next;
+            }
+        }
     }
 
     fn next_token(&mut self) {
-        self.current_token = self.peek_token.clone();
-        self.peek_token = self.lexer.next_token();
+        let next = Parser::read_token(&mut self.lexer);
+        self.current_token = std::mem::replace(&mut self.peek_token, next.token);
+        self.current_span = std::mem::replace(&mut self.peek_span, next.span);
     }
+    /// Parses one statement, or records its error and skips to the end of it.
     fn parse_statement(&mut self) -> Option<Statement> {
-        match self.current_token {
-            Token::Let => {
-                let inside = self.parse_let_statement().unwrap();
-                return Some(Statement::Let(inside));
+        let statement = match self.current_token {
+            Token::Let | Token::Const
+                if matches!(self.peek_token, Token::LBracket | Token::LBrace) =>
+            {
+                self.parse_let_pattern_statement()
             }
-            Token::Return => {
-                return Some(self.parse_return_statement().unwrap());
+            Token::Let | Token::Const => self.parse_let_statement().map(Statement::Let),
+            Token::Function if matches!(self.peek_token, Token::Ident(_)) => {
+                self.parse_function_statement()
             }
-            // calls self.next_token to move the token forward when it is not a let statment. This is because
-            // the funciton is not fully done.
-            _ => {
-                return Some(self.parse_expression_statement().unwrap());
+            Token::Return => self.parse_return_statement(),
+            Token::While => self.parse_while_statement(),
+            Token::For => self.parse_for_statement(),
+            Token::Break | Token::Continue => self.parse_loop_control(),
+            _ => self.parse_expression_statement(),
+        };
+        match statement {
+            Ok(statement) => Some(statement),
+            Err(e) => {
+                self.errors.push(e.to_string());
+                self.synchronize();
+                None
             }
         }
     }
-    fn parse_let_statement(&mut self) -> Result<Let> {
-        if !self.expect_peek_and_skip_token(Token::Ident(String::new())) {
-            return Err(anyhow!("Wrong token type"));
+
+    /// Skips past the rest of a statement that failed to parse, stopping on its `;`, on the
+    /// `}` closing a block it opened, or before the `}` of the enclosing block, so the
+    /// caller's `next_token` moves on to the next statement.
+    fn synchronize(&mut self) {
+        let mut depth = 0;
+        loop {
+            match self.current_token {
+                Token::EOF => return,
+                Token::Semicolon if depth == 0 => return,
+                Token::LBrace => depth += 1,
+                Token::RBrace if depth > 0 => {
+                    depth -= 1;
+                    if depth == 0 && !self.peek_token_is(Token::Else) {
+                        if self.peek_token_is(Token::Semicolon) {
+                            self.next_token();
+                        }
+                        return;
+                    }
+                }
+                _ => {}
+            }
+            if depth == 0 && (self.peek_token_is(Token::RBrace) || self.peek_token_is(Token::EOF)) {
+                return;
+            }
+            self.next_token();
         }
+    }
+
+    fn parse_let_statement(&mut self) -> Result<Let> {
+        let span = self.current_span;
+        let constant = self.current_token == Token::Const;
+        self.expect_peek_and_skip_token(Token::Ident(String::new()))?;
         let ident_token = self.current_token.clone();
-        let identifier = Identifier::new(self.current_token.clone());
+        let identifier = Identifier::new(self.current_token.clone(), self.current_span);
 
-        if !self.expect_peek_and_skip_token(Token::Assign) {
-            return Err(anyhow!("Expected token of Assign"));
-        }
+        self.expect_peek_and_skip_token(Token::Assign)?;
+
+        self.next_token();
+        let expression = self.parse_expression(Precidence::Lowest)?;
+
+        if self.peek_token_is(Token::Semicolon) {
+            self.next_token();
+        };
+
+        return Ok(Let::new(
+            ident_token,
+            identifier,
+            expression,
+            constant,
+            span,
+        ));
+    }
 
+    /// Parses `fn name(params) { body }` as a `let` binding the function to its name.
+    fn parse_function_statement(&mut self) -> Result<Statement> {
+        let span = self.current_span;
+        let token = self.current_token.clone();
         self.next_token();
-        let expression = self.parse_expression(Precidence::Lowest).unwrap();
+        let name = Identifier::new(self.current_token.clone(), self.current_span);
 
+        let function = self.parse_function_literal(token, Some(name.clone()), span)?;
         if self.peek_token_is(Token::Semicolon) {
             self.next_token();
         };
 
-        return Ok(Let::new(ident_token, identifier, expression));
+        Ok(Statement::Let(Let::new(
+            name.token.clone(),
+            name,
+            function,
+            false,
+            span,
+        )))
+    }
+
+    fn parse_let_pattern_statement(&mut self) -> Result<Statement> {
+        let token = self.current_token.clone();
+        let span = self.current_span;
+        let constant = self.current_token == Token::Const;
+
+        self.next_token();
+        let braces = self.current_token_is(Token::LBrace);
+        let pattern = match self.parse_pattern() {
+            Ok(pattern) => pattern,
+            Err(e) => {
+                // synchronize stops before a `}`, so get out of the braces of a hash pattern
+                if braces && !self.current_token_is(Token::RBrace) {
+                    self.skip_to_closing_brace();
+                }
+                return Err(e);
+            }
+        };
+        check_duplicate_bindings(&pattern)?;
+
+        self.expect_peek_and_skip_token(Token::Assign)?;
+        self.next_token();
+        let value = self.parse_expression(Precidence::Lowest)?;
+
+        if self.peek_token_is(Token::Semicolon) {
+            self.next_token();
+        };
+
+        Ok(Statement::LetPattern(LetPattern::new(
+            token, pattern, value, constant, span,
+        )))
     }
 
     fn parse_return_statement(&mut self) -> Result<Statement> {
         let token = self.current_token.clone();
+        let span = self.current_span;
 
         self.next_token();
 
-        let expression = self.parse_expression(Precidence::Lowest).unwrap();
+        let expression = self.parse_expression(Precidence::Lowest)?;
+
+        if self.peek_token_is(Token::Semicolon) {
+            self.next_token();
+        };
+        Ok(Statement::Return(Return::new(token, expression, span)))
+    }
+
+    fn parse_while_statement(&mut self) -> Result<Statement> {
+        let token = self.current_token.clone();
+        let span = self.current_span;
+
+        self.expect_peek_and_skip_token(Token::LParen)?;
+        self.next_token();
+        let condition = self.parse_expression(Precidence::Lowest)?;
+        self.expect_peek_and_skip_token(Token::RParen)?;
+        self.expect_peek_and_skip_token(Token::LBrace)?;
+
+        let body = self.parse_loop_body()?;
+        Ok(Statement::While(While::new(token, condition, body, span)))
+    }
+
+    fn parse_for_statement(&mut self) -> Result<Statement> {
+        let token = self.current_token.clone();
+        let span = self.current_span;
+
+        self.expect_peek_and_skip_token(Token::LParen)?;
+        self.expect_peek_and_skip_token(Token::Ident(String::new()))?;
+        let variable = Identifier::new(self.current_token.clone(), self.current_span);
+        self.expect_peek_and_skip_token(Token::In)?;
+        self.next_token();
+        let iterable = self.parse_expression(Precidence::Lowest)?;
+        self.expect_peek_and_skip_token(Token::RParen)?;
+        self.expect_peek_and_skip_token(Token::LBrace)?;
+
+        let body = self.parse_loop_body()?;
+        Ok(Statement::For(For::new(
+            token, variable, iterable, body, span,
+        )))
+    }
+
+    fn parse_loop_body(&mut self) -> Result<BlockStatement> {
+        self.loop_depth += 1;
+        let body = self.parse_block_statement();
+        self.loop_depth -= 1;
+
+        if self.peek_token_is(Token::Semicolon) {
+            self.next_token();
+        };
+        body
+    }
+
+    fn parse_loop_control(&mut self) -> Result<Statement> {
+        let span = self.current_span;
+        if self.loop_depth == 0 {
+            return Err(anyhow!(
+                "{}: {} outside of a loop",
+                span,
+                self.current_token
+            ));
+        }
+        let statement = match self.current_token {
+            Token::Break => Statement::Break(span),
+            _ => Statement::Continue(span),
+        };
 
         if self.peek_token_is(Token::Semicolon) {
             self.next_token();
         };
-        Ok(Statement::Return(Return::new(token, expression)))
+        Ok(statement)
     }
 
     fn current_token_is(&mut self, t: Token) -> bool {
@@ -136,18 +384,17 @@ impl Parser {
         std::mem::discriminant(&self.peek_token) == std::mem::discriminant(&t)
     }
 
-    fn expect_peek_and_skip_token(&mut self, t: Token) -> bool {
+    fn expect_peek_and_skip_token(&mut self, t: Token) -> Result<()> {
         if self.peek_token_is(t.clone()) {
             self.next_token();
-            return true;
+            return Ok(());
         } else {
-            self.peek_error(t.clone());
-            return false;
+            return Err(self.peek_error(t));
         }
     }
 
     fn parse_expression_statement(&mut self) -> Result<Statement> {
-        let expression = self.parse_expression(Precidence::Lowest).unwrap();
+        let expression = self.parse_expression(Precidence::Lowest)?;
 
         if self.peek_token_is(Token::Semicolon) {
             self.next_token();
@@ -157,206 +404,609 @@ impl Parser {
     }
 
     fn parse_expression(&mut self, precidence: Precidence) -> Result<Expression> {
+        let (expression, _) = self.parse_grouped_expression(precidence)?;
+        Ok(expression)
+    }
+
+    /// Parses an expression, also telling whether the whole of it is one parenthesized
+    /// expression. `(f(x))` is, while `(f)(x)` and `(f) + x` are not.
+    fn parse_grouped_expression(&mut self, precidence: Precidence) -> Result<(Expression, bool)> {
         // Base and prefix
+        let span = self.current_span;
+        let mut grouped = false;
         let mut expression = match &self.current_token {
-            Token::Ident(_) => Ok(Expression::Identifier(self.current_token.clone())),
-            Token::Int(_) => Ok(Expression::Integer(self.current_token.clone())),
-            Token::String(s) => Ok(Expression::String(s.to_owned())),
-            Token::True | Token::False => Ok(Expression::Boolean(self.current_token.clone())),
-            Token::Bang | Token::Minus => self.parse_prefix(),
+            Token::Ident(_) => Expression::Identifier(self.current_token.clone(), span),
+            Token::Int(_) => Expression::Integer(self.current_token.clone(), span),
+            Token::Float(_) => Expression::Float(self.current_token.clone(), span),
+            Token::String(s) => Expression::String(s.to_owned(), span),
+            Token::InterpolatedString(segments) => {
+                self.parse_interpolated_string(segments.clone(), span)?
+            }
+            Token::True | Token::False => Expression::Boolean(self.current_token.clone(), span),
+            Token::Bang | Token::Minus => self.parse_prefix()?,
             Token::LParen => {
                 self.next_token();
-                let expression = self.parse_expression(Precidence::Lowest);
-
-                if !self.expect_peek_and_skip_token(Token::RParen) {
-                    return Err(anyhow!("Wrong closing token. Did not get RParen"));
-                };
+                let expression = self.parse_expression(Precidence::Lowest)?;
+                self.expect_peek_and_skip_token(Token::RParen)?;
+                grouped = true;
                 expression
             }
-            Token::LBracket => {
-                let mut array_items: Vec<Expression> = vec![];
+            Token::LBracket => self.parse_array_literal()?,
+            Token::LBrace => self.parse_hash_literal()?,
+            Token::If => self.parse_if_expression()?,
+            Token::Match => self.parse_match_expression()?,
+            Token::Function => {
+                self.parse_function_literal(self.current_token.clone(), None, span)?
+            }
+            Token::Error(message) => return Err(anyhow!("{}: {}", span, message)),
+            token => return Err(anyhow!("{}: Unexpected token {}", span, token)),
+        };
 
-                if self.peek_token_is(Token::RBracket) {
-                    self.next_token();
-                    return Ok(Expression::Array(array_items));
-                };
+        // infix
+        while !self.peek_token_is(Token::Semicolon)
+            && (precidence.clone() as i32) < (self.peek_precedence() as i32)
+        {
+            self.next_token();
+            grouped = false;
+            expression = match &self.current_token {
+                Token::LParen => {
+                    let token = self.current_token.clone();
+                    let (args, keyword_args) = self.parse_call_arguments()?;
+                    let span = expression.span();
+                    Expression::Call(Box::new(CallExpression::new(
+                        token,
+                        expression,
+                        args,
+                        keyword_args,
+                        span,
+                    )))
+                }
+                Token::LBracket => self.parse_index_expression(expression)?,
+                Token::Pipeline => self.parse_pipeline_expression(expression)?,
+                token if Precidence::from(token) == Precidence::Assign => {
+                    self.parse_assign_expression(expression)?
+                }
+                _ => self.parse_infix(expression)?,
+            };
+        }
+        Ok((expression, grouped))
+    }
 
-                self.next_token();
-                array_items.push(self.parse_expression(Precidence::Lowest).unwrap());
+    fn parse_if_expression(&mut self) -> Result<Expression> {
+        let token = self.current_token.clone();
+        let span = self.current_span;
 
-                while self.peek_token_is(Token::Comma) {
-                    self.next_token();
-                    self.next_token();
+        self.expect_peek_and_skip_token(Token::LParen)?;
 
-                    array_items.push(self.parse_expression(Precidence::Lowest).unwrap())
-                }
+        self.next_token();
+        let condition = self.parse_expression(Precidence::Lowest)?;
 
-                if !self.expect_peek_and_skip_token(Token::RBracket) {
-                    return Err(anyhow!("Expected closing bracket for array."));
-                };
-                return Ok(Expression::Array(array_items));
-            }
-            Token::If => {
-                let token = self.current_token.clone();
+        self.expect_peek_and_skip_token(Token::RParen)?;
+        self.expect_peek_and_skip_token(Token::LBrace)?;
 
-                if !self.expect_peek_and_skip_token(Token::LParen) {
-                    return Err(anyhow!("Next token should be LParen"));
-                };
+        let consequence = self.parse_block_statement()?;
 
+        let alternative = if self.peek_token_is(Token::Else) {
+            self.next_token();
+            if self.peek_token_is(Token::If) {
+                // `else if` is an alternative block holding just the nested if
+                let else_token = self.current_token.clone();
                 self.next_token();
-                let condition = self.parse_expression(Precidence::Lowest).unwrap();
+                let nested = self.parse_if_expression()?;
+                let nested_span = nested.span();
+                Some(BlockStatement::new(
+                    else_token,
+                    vec![Statement::Expression(nested)],
+                    nested_span,
+                ))
+            } else {
+                self.expect_peek_and_skip_token(Token::LBrace)?;
+                Some(self.parse_block_statement()?)
+            }
+        } else {
+            None
+        };
 
-                if !self.expect_peek_and_skip_token(Token::RParen) {
-                    return Err(anyhow!("Next token should be RParen"));
-                };
+        Ok(Expression::If(Box::new(IfExpression::new(
+            token,
+            condition,
+            consequence,
+            alternative,
+            span,
+        ))))
+    }
 
-                if !self.expect_peek_and_skip_token(Token::LBrace) {
-                    return Err(anyhow!("Expected left brace"));
-                };
+    fn parse_match_expression(&mut self) -> Result<Expression> {
+        let token = self.current_token.clone();
+        let span = self.current_span;
 
-                let consequence = self.parse_block_statement().unwrap();
+        self.expect_peek_and_skip_token(Token::LParen)?;
+        self.next_token();
+        let value = self.parse_expression(Precidence::Lowest)?;
+        self.expect_peek_and_skip_token(Token::RParen)?;
+        self.expect_peek_and_skip_token(Token::LBrace)?;
+
+        let arms = match self.parse_match_arms() {
+            Ok(arms) => arms,
+            Err(e) => {
+                // arms are not statements, so skip them here for synchronize to carry on
+                // after the match
+                self.skip_to_closing_brace();
+                return Err(e);
+            }
+        };
 
-                let alternative = if self.peek_token_is(Token::Else) {
-                    self.next_token();
-                    if !self.expect_peek_and_skip_token(Token::LBrace) {
-                        return Err(anyhow!("Expected Left Brace"));
-                    }
-                    Some(self.parse_block_statement().unwrap())
-                } else {
-                    None
-                };
+        Ok(Expression::Match(Box::new(MatchExpression::new(
+            token, value, arms, span,
+        ))))
+    }
 
-                Ok(Expression::If(Box::new(IfExpression::new(
-                    token,
-                    condition,
-                    consequence,
-                    alternative,
-                ))))
+    fn parse_match_arms(&mut self) -> Result<Vec<MatchArm>> {
+        let mut arms: Vec<MatchArm> = vec![];
+        while !self.peek_token_is(Token::RBrace) {
+            self.next_token();
+            arms.push(self.parse_match_arm()?);
+            if !self.peek_token_is(Token::RBrace) {
+                self.expect_peek_and_skip_token(Token::Comma)?;
             }
-            Token::Function => {
-                let token = self.current_token.clone();
+        }
+        self.next_token();
+        Ok(arms)
+    }
 
-                if !self.expect_peek_and_skip_token(Token::LParen) {
-                    return Err(anyhow!("Expected left paren"));
+    /// Moves to the `}` closing the braces the current token is in.
+    fn skip_to_closing_brace(&mut self) {
+        let mut depth = 1;
+        loop {
+            match self.current_token {
+                Token::EOF => return,
+                Token::LBrace => depth += 1,
+                Token::RBrace => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return;
+                    }
                 }
+                _ => {}
+            }
+            self.next_token();
+        }
+    }
 
-                let parameters = self.parse_function_parameters().unwrap();
-
-                if !self.expect_peek_and_skip_token(Token::LBrace) {
-                    return Err(anyhow!("Expected left brace"));
-                }
+    fn parse_match_arm(&mut self) -> Result<MatchArm> {
+        let span = self.current_span;
+        let pattern = self.parse_pattern()?;
+        check_duplicate_bindings(&pattern)?;
+        let guard = if self.peek_token_is(Token::If) {
+            self.next_token();
+            self.next_token();
+            Some(self.parse_expression(Precidence::Lowest)?)
+        } else {
+            None
+        };
+        self.expect_peek_and_skip_token(Token::FatArrow)?;
+        self.next_token();
+        // a body starting with a brace is a block, so a hash literal has to be parenthesized
+        let body = if self.current_token_is(Token::LBrace) {
+            self.parse_block_statement()?
+        } else {
+            let body_span = self.current_span;
+            let expression = self.parse_expression(Precidence::Lowest)?;
+            BlockStatement::new(
+                Token::LBrace,
+                vec![Statement::Expression(expression)],
+                body_span,
+            )
+        };
+        Ok(MatchArm::new(pattern, guard, body, span))
+    }
 
-                let body = self.parse_block_statement().unwrap();
+    /// Parses the pattern starting at the current token, leaving its last token current.
+    fn parse_pattern(&mut self) -> Result<Pattern> {
+        let span = self.current_span;
+        if let Some(literal) = self.parse_literal_pattern()? {
+            return Ok(Pattern::Literal(literal));
+        }
+        match &self.current_token {
+            Token::Ident(name) if name == "_" => Ok(Pattern::Wildcard(span)),
+            Token::Ident(_) => Ok(Pattern::Binding(Identifier::new(
+                self.current_token.clone(),
+                span,
+            ))),
+            Token::LBracket => self.parse_array_pattern(),
+            Token::LBrace => self.parse_hash_pattern(),
+            Token::Error(message) => Err(anyhow!("{}: {}", span, message)),
+            token => Err(anyhow!("{}: Unexpected token {} in pattern", span, token)),
+        }
+    }
 
-                Ok(Expression::Fn(Box::new(FnExpression::new(
-                    token, parameters, body,
-                ))))
+    /// Parses a number, string or boolean literal, numbers may be negative.
+    fn parse_literal_pattern(&mut self) -> Result<Option<Expression>> {
+        let span = self.current_span;
+        let literal = match &self.current_token {
+            Token::Int(_) => Expression::Integer(self.current_token.clone(), span),
+            Token::Float(_) => Expression::Float(self.current_token.clone(), span),
+            Token::String(s) => Expression::String(s.to_owned(), span),
+            Token::True | Token::False => Expression::Boolean(self.current_token.clone(), span),
+            Token::Minus if matches!(self.peek_token, Token::Int(_) | Token::Float(_)) => {
+                let token = self.current_token.clone();
+                self.next_token();
+                let number = self
+                    .parse_literal_pattern()?
+                    .expect("a number follows the minus");
+                Expression::Prefix(Box::new(PrefixExpression::new(token, number, span)))
             }
-            token => Err(anyhow!("Unknown token type: {:?}", token)),
+            _ => return Ok(None),
         };
+        Ok(Some(literal))
+    }
 
-        // infix
-        while !self.peek_token_is(Token::Semicolon)
-            && (precidence.clone() as i32) < (self.peek_precedence() as i32)
-        {
+    fn parse_array_pattern(&mut self) -> Result<Pattern> {
+        let span = self.current_span;
+        let mut items: Vec<Pattern> = vec![];
+        let mut rest: Option<Identifier> = None;
+        while !self.peek_token_is(Token::RBracket) {
             self.next_token();
-            let exp: Result<Expression> = match &self.current_token {
-                Token::LParen => {
-                    let args = self.parse_call_arguments().unwrap();
-                    Ok(Expression::Call(Box::new(CallExpression::new(
-                        self.current_token.clone(),
-                        expression.unwrap().clone(),
-                        args,
-                    ))))
+            if self.current_token_is(Token::Ellipsis) {
+                self.expect_peek_and_skip_token(Token::Ident(String::new()))?;
+                rest = Some(Identifier::new(
+                    self.current_token.clone(),
+                    self.current_span,
+                ));
+                break;
+            }
+            items.push(self.parse_pattern()?);
+            if !self.peek_token_is(Token::RBracket) {
+                self.expect_peek_and_skip_token(Token::Comma)?;
+            }
+        }
+        self.expect_peek_and_skip_token(Token::RBracket)?;
+        Ok(Pattern::Array(items, rest, span))
+    }
+
+    fn parse_hash_pattern(&mut self) -> Result<Pattern> {
+        let span = self.current_span;
+        let mut entries: Vec<(Expression, Pattern)> = vec![];
+        while !self.peek_token_is(Token::RBrace) {
+            self.next_token();
+            let key_span = self.current_span;
+            let entry = match &self.current_token {
+                // a bare name is the string key with that name
+                Token::Ident(name) => {
+                    let key = Expression::String(name.to_owned(), key_span);
+                    if self.peek_token_is(Token::Colon) {
+                        self.next_token();
+                        self.next_token();
+                        (key, self.parse_pattern()?)
+                    } else {
+                        let name = Identifier::new(self.current_token.clone(), key_span);
+                        (key, Pattern::Binding(name))
+                    }
                 }
-                _ => self.parse_infix(expression?.clone()),
+                _ => match self.parse_literal_pattern()? {
+                    Some(key) => {
+                        self.expect_peek_and_skip_token(Token::Colon)?;
+                        self.next_token();
+                        (key, self.parse_pattern()?)
+                    }
+                    None => {
+                        return Err(anyhow!(
+                            "{}: Unexpected token {} in pattern",
+                            key_span,
+                            self.current_token
+                        ))
+                    }
+                },
             };
-            expression = exp;
+            entries.push(entry);
+            if !self.peek_token_is(Token::RBrace) {
+                self.expect_peek_and_skip_token(Token::Comma)?;
+            }
+        }
+        self.expect_peek_and_skip_token(Token::RBrace)?;
+        Ok(Pattern::Hash(entries, span))
+    }
+
+    /// Parses the code of each `${...}` as a single expression, with its own lexer positioned
+    /// where the code starts in the source.
+    fn parse_interpolated_string(
+        &mut self,
+        segments: Vec<StringSegment>,
+        span: Span,
+    ) -> Result<Expression> {
+        let mut parts: Vec<Expression> = vec![];
+        for segment in segments.into_iter() {
+            match segment {
+                StringSegment::Text(text) => parts.push(Expression::String(text, span)),
+                StringSegment::Code(code, code_span) => {
+                    let lexer = Lexer::starting_at(code.into_bytes(), code_span);
+                    let mut parser = Parser::new(lexer);
+                    parts.push(parser.parse_expression(Precidence::Lowest)?);
+                    if !parser.peek_token_is(Token::EOF) {
+                        return Err(parser.peek_error(Token::RBrace));
+                    }
+                }
+            }
         }
-        expression
+        Ok(Expression::Interpolated(parts, span))
     }
 
     fn parse_prefix(&mut self) -> Result<Expression> {
         let token = self.current_token.clone();
+        let span = self.current_span;
         self.next_token();
-        let right = self.parse_expression(Precidence::Prefix).unwrap();
+        let right = self.parse_expression(Precidence::Prefix)?;
 
         Ok(Expression::Prefix(Box::new(PrefixExpression::new(
-            token, right,
+            token, right, span,
         ))))
     }
     fn parse_infix(&mut self, left: Expression) -> Result<Expression> {
         let token = self.current_token.clone();
-        let precidence = Precidence::from(&self.current_token);
+        let span = self.current_span;
+        let precidence = match self.current_token {
+            // parsing the right side one level lower lets it take in further powers, which
+            // makes ** right associative
+            Token::Power => Precidence::Product,
+            _ => Precidence::from(&self.current_token),
+        };
         self.next_token();
-        let right = self.parse_expression(precidence);
+        let right = self.parse_expression(precidence)?;
 
         Ok(Expression::Infix(Box::new(InfixExpression::new(
-            left,
-            token,
-            right.unwrap(),
+            left, token, right, span,
+        ))))
+    }
+    /// Parses the value of an assignment at the lowest precedence, so `a = b = c` assigns
+    /// `c` to both.
+    fn parse_assign_expression(&mut self, left: Expression) -> Result<Expression> {
+        let token = self.current_token.clone();
+        let span = self.current_span;
+        let name = match left {
+            Expression::Identifier(name, name_span) => Identifier::new(name, name_span),
+            other => {
+                return Err(anyhow!(
+                    "{}: Cannot assign to this expression",
+                    other.span()
+                ))
+            }
+        };
+        self.next_token();
+        let value = self.parse_expression(Precidence::Lowest)?;
+
+        Ok(Expression::Assign(Box::new(AssignExpression::new(
+            token, name, value, span,
         ))))
     }
+    fn parse_array_literal(&mut self) -> Result<Expression> {
+        let span = self.current_span;
+        let mut array_items: Vec<Expression> = vec![];
+
+        if self.peek_token_is(Token::RBracket) {
+            self.next_token();
+            return Ok(Expression::Array(array_items, span));
+        };
+
+        self.next_token();
+        array_items.push(self.parse_expression(Precidence::Lowest)?);
+
+        while self.peek_token_is(Token::Comma) {
+            self.next_token();
+            self.next_token();
+
+            array_items.push(self.parse_expression(Precidence::Lowest)?)
+        }
+
+        self.expect_peek_and_skip_token(Token::RBracket)?;
+        return Ok(Expression::Array(array_items, span));
+    }
+    fn parse_hash_literal(&mut self) -> Result<Expression> {
+        let span = self.current_span;
+        let mut pairs: Vec<(Expression, Expression)> = vec![];
+
+        while !self.peek_token_is(Token::RBrace) {
+            self.next_token();
+            let key = self.parse_expression(Precidence::Lowest)?;
+
+            self.expect_peek_and_skip_token(Token::Colon)?;
+
+            self.next_token();
+            let value = self.parse_expression(Precidence::Lowest)?;
+            pairs.push((key, value));
+
+            if !self.peek_token_is(Token::RBrace) {
+                self.expect_peek_and_skip_token(Token::Comma)?;
+            };
+        }
+
+        self.expect_peek_and_skip_token(Token::RBrace)?;
+        return Ok(Expression::Hash(pairs, span));
+    }
+    fn parse_index_expression(&mut self, left: Expression) -> Result<Expression> {
+        let token = self.current_token.clone();
+        let span = self.current_span;
+        self.next_token();
+        let index = self.parse_expression(Precidence::Lowest)?;
+
+        self.expect_peek_and_skip_token(Token::RBracket)?;
+
+        Ok(Expression::Index(Box::new(IndexExpression::new(
+            token, left, index, span,
+        ))))
+    }
+    /// Parses `left |> f` as the call `f(left)`, and `left |> f(y)` as `f(left, y)`. Only a
+    /// bare call gets `left` as its first argument, anything else is a function called with
+    /// it, so `left |> f(x)(y)` is `f(x)(y)(left)` and `left |> (f(x))` is `f(x)(left)`.
+    fn parse_pipeline_expression(&mut self, left: Expression) -> Result<Expression> {
+        let token = self.current_token.clone();
+        self.next_token();
+        let (right, parenthesized) = self.parse_grouped_expression(Precidence::Pipeline)?;
+
+        let call = match right {
+            Expression::Call(mut call)
+                if !parenthesized && !matches!(call.function, Expression::Call(_)) =>
+            {
+                call.arguments.get_or_insert_with(Vec::new).insert(0, left);
+                call
+            }
+            function => {
+                let span = function.span();
+                Box::new(CallExpression::new(
+                    token,
+                    function,
+                    Some(vec![left]),
+                    vec![],
+                    span,
+                ))
+            }
+        };
+        Ok(Expression::Call(call))
+    }
     fn peek_precedence(&mut self) -> Precidence {
         Precidence::from(&self.peek_token)
     }
     fn parse_block_statement(&mut self) -> Result<BlockStatement> {
         let token = self.current_token.clone();
+        let span = self.current_span;
         let mut statements: Vec<Statement> = vec![];
         self.next_token();
 
         while !self.current_token_is(Token::RBrace) && !self.current_token_is(Token::EOF) {
-            let statement = self.parse_statement().unwrap();
-            statements.push(statement);
+            if let Some(statement) = self.parse_statement() {
+                statements.push(statement);
+            }
             self.next_token();
         }
 
-        Ok(BlockStatement::new(token, statements))
+        if self.current_token_is(Token::EOF) {
+            return Err(anyhow!(
+                "{}: Expected {} but got {}",
+                self.current_span,
+                Token::RBrace,
+                Token::EOF
+            ));
+        }
+
+        Ok(BlockStatement::new(token, statements, span))
     }
 
-    fn parse_function_parameters(&mut self) -> Result<Option<Vec<Identifier>>> {
+    /// Parses the parameters and body of a function, the current token is the `fn` or the
+    /// name after it.
+    fn parse_function_literal(
+        &mut self,
+        token: Token,
+        name: Option<Identifier>,
+        span: Span,
+    ) -> Result<Expression> {
+        self.expect_peek_and_skip_token(Token::LParen)?;
+
+        let parameters = self.parse_function_parameters()?;
+
+        self.expect_peek_and_skip_token(Token::LBrace)?;
+
+        // loops outside the function cannot be broken out of from inside it
+        let loop_depth = std::mem::take(&mut self.loop_depth);
+        let body = self.parse_block_statement();
+        self.loop_depth = loop_depth;
+        let body = body?;
+
+        Ok(Expression::Fn(Box::new(FnExpression::new(
+            token, name, parameters, body, span,
+        ))))
+    }
+
+    /// Parses `(a, b = default, ...rest)`. Parameters with a default come after the ones
+    /// without, the rest parameter comes last and no name is used twice.
+    fn parse_function_parameters(&mut self) -> Result<Option<Vec<Parameter>>> {
         if self.peek_token_is(Token::RParen) {
             self.next_token();
             return Ok(None);
         };
 
-        self.next_token();
-        let mut identifiers: Vec<Identifier> = vec![Identifier::new(self.current_token.clone())];
+        let mut parameters: Vec<Parameter> = vec![];
+        loop {
+            let rest = self.peek_token_is(Token::Ellipsis);
+            if rest {
+                self.next_token();
+            }
+            self.expect_peek_and_skip_token(Token::Ident(String::new()))?;
+            let name = Identifier::new(self.current_token.clone(), self.current_span);
+            if let Some(p) = parameters.iter().find(|p| p.name.token == name.token) {
+                if let Token::Ident(s) = &p.name.token {
+                    return Err(anyhow!(
+                        "{}: Parameter {} is declared more than once",
+                        name.span,
+                        s
+                    ));
+                }
+            }
 
-        while self.peek_token_is(Token::Comma) {
-            self.next_token();
+            let default = if !rest && self.peek_token_is(Token::Assign) {
+                self.next_token();
+                self.next_token();
+                Some(self.parse_expression(Precidence::Lowest)?)
+            } else {
+                None
+            };
+            if default.is_none() && !rest && parameters.iter().any(|p| p.default.is_some()) {
+                return Err(anyhow!(
+                    "{}: Parameter without a default after one with a default",
+                    name.span
+                ));
+            }
+            parameters.push(Parameter::new(name, default, rest));
+
+            if !self.peek_token_is(Token::Comma) {
+                break;
+            }
+            if rest {
+                return Err(anyhow!(
+                    "{}: Rest parameter must be last",
+                    self.current_span
+                ));
+            }
             self.next_token();
-            identifiers.push(Identifier::new(self.current_token.clone()));
         }
-        if !self.expect_peek_and_skip_token(Token::RParen) {
-            return Err(anyhow!("Expected Right paren"));
-        };
-        return Ok(Some(identifiers));
+        self.expect_peek_and_skip_token(Token::RParen)?;
+        return Ok(Some(parameters));
     }
 
-    fn parse_call_arguments(&mut self) -> Result<Option<Vec<Expression>>> {
+    /// Parses positional arguments followed by `name: value` keyword arguments.
+    fn parse_call_arguments(&mut self) -> Result<Arguments> {
         let mut args: Vec<Expression> = vec![];
+        let mut keyword_args: Vec<(Identifier, Expression)> = vec![];
 
         if self.peek_token_is(Token::RParen) {
             self.next_token();
-            return Ok(None);
+            return Ok((None, keyword_args));
         };
 
-        self.next_token();
-        args.push(self.parse_expression(Precidence::Lowest).unwrap());
-
-        while self.peek_token_is(Token::Comma) {
-            self.next_token();
+        loop {
             self.next_token();
+            if matches!(self.current_token, Token::Ident(_)) && self.peek_token_is(Token::Colon) {
+                let name = Identifier::new(self.current_token.clone(), self.current_span);
+                self.next_token();
+                self.next_token();
+                keyword_args.push((name, self.parse_expression(Precidence::Lowest)?));
+            } else if !keyword_args.is_empty() {
+                return Err(anyhow!(
+                    "{}: Positional argument after keyword arguments",
+                    self.current_span
+                ));
+            } else {
+                args.push(self.parse_expression(Precidence::Lowest)?);
+            }
 
-            args.push(self.parse_expression(Precidence::Lowest).unwrap());
+            if !self.peek_token_is(Token::Comma) {
+                break;
+            }
+            self.next_token();
         }
 
-        if !self.expect_peek_and_skip_token(Token::RParen) {
-            return Err(anyhow!("Expected right paren"));
-        }
+        self.expect_peek_and_skip_token(Token::RParen)?;
 
-        return Ok(Some(args));
+        let args = if args.is_empty() { None } else { Some(args) };
+        Ok((args, keyword_args))
     }
 }
 
@@ -367,33 +1017,207 @@ mod tests {
 
     use crate::{
         ast::ast::{
-            BlockStatement, Expression, Identifier, InfixExpression, PrefixExpression, Return,
-            Statement,
+            BlockStatement, Expression, FnExpression, For, Identifier, IfExpression,
+            IndexExpression, InfixExpression, Let, MatchArm, MatchExpression, Parameter, Pattern,
+            PrefixExpression, Return, Statement, While,
         },
-        lexer::lexer::{Lexer, Token},
+        lexer::lexer::{Lexer, Span, Token},
     };
 
+    #[allow(clippy::len_zero)]
     fn check_errors(errors: Vec<String>) {
         if errors.len() == 0 {
             return;
         };
 
-        for err in errors.into_iter() {
-            print!("Error: {:?}", err);
+        for err in errors.into_iter() {
+            print!("Error: {:?}", err);
+        }
+        panic!("There were errors in the parser")
+    }
+
+    /// Resets every span in a node to `Span::default()`, so parsed nodes can be compared with
+    /// expected nodes built without positions.
+    trait ClearSpans {
+        fn clear_spans(&mut self);
+    }
+
+    fn without_spans<T: ClearSpans + Clone>(value: &T) -> T {
+        let mut value = value.clone();
+        value.clear_spans();
+        value
+    }
+
+    impl<T: ClearSpans> ClearSpans for Vec<T> {
+        fn clear_spans(&mut self) {
+            self.iter_mut().for_each(T::clear_spans);
+        }
+    }
+
+    impl<T: ClearSpans> ClearSpans for Option<T> {
+        fn clear_spans(&mut self) {
+            self.iter_mut().for_each(T::clear_spans);
+        }
+    }
+
+    impl<A: ClearSpans, B: ClearSpans> ClearSpans for (A, B) {
+        fn clear_spans(&mut self) {
+            self.0.clear_spans();
+            self.1.clear_spans();
+        }
+    }
+
+    impl ClearSpans for Identifier {
+        fn clear_spans(&mut self) {
+            self.span = Span::default();
+        }
+    }
+
+    impl ClearSpans for BlockStatement {
+        fn clear_spans(&mut self) {
+            self.statements.clear_spans();
+            self.span = Span::default();
+        }
+    }
+
+    impl ClearSpans for Parameter {
+        fn clear_spans(&mut self) {
+            self.name.clear_spans();
+            self.default.clear_spans();
+        }
+    }
+
+    impl ClearSpans for Statement {
+        fn clear_spans(&mut self) {
+            match self {
+                Statement::Let(l) => {
+                    l.name.clear_spans();
+                    l.value.clear_spans();
+                    l.span = Span::default();
+                }
+                Statement::LetPattern(l) => {
+                    l.pattern.clear_spans();
+                    l.value.clear_spans();
+                    l.span = Span::default();
+                }
+                Statement::Return(r) => {
+                    r.return_value.clear_spans();
+                    r.span = Span::default();
+                }
+                Statement::Expression(e) => e.clear_spans(),
+                Statement::While(w) => {
+                    w.condition.clear_spans();
+                    w.body.clear_spans();
+                    w.span = Span::default();
+                }
+                Statement::For(f) => {
+                    f.variable.clear_spans();
+                    f.iterable.clear_spans();
+                    f.body.clear_spans();
+                    f.span = Span::default();
+                }
+                Statement::Break(span) | Statement::Continue(span) => *span = Span::default(),
+            }
+        }
+    }
+
+    impl ClearSpans for Expression {
+        fn clear_spans(&mut self) {
+            match self {
+                Expression::Identifier(_, span)
+                | Expression::Integer(_, span)
+                | Expression::Float(_, span)
+                | Expression::String(_, span)
+                | Expression::Boolean(_, span) => *span = Span::default(),
+                Expression::Interpolated(parts, span) | Expression::Array(parts, span) => {
+                    parts.clear_spans();
+                    *span = Span::default();
+                }
+                Expression::Hash(pairs, span) => {
+                    pairs.clear_spans();
+                    *span = Span::default();
+                }
+                Expression::Prefix(p) => {
+                    p.right.clear_spans();
+                    p.span = Span::default();
+                }
+                Expression::Infix(i) => {
+                    i.left.clear_spans();
+                    i.right.clear_spans();
+                    i.span = Span::default();
+                }
+                Expression::If(i) => {
+                    i.condition.clear_spans();
+                    i.consequence.clear_spans();
+                    i.alternative.clear_spans();
+                    i.span = Span::default();
+                }
+                Expression::Fn(f) => {
+                    f.name.clear_spans();
+                    f.parameters.clear_spans();
+                    f.body.clear_spans();
+                    f.span = Span::default();
+                }
+                Expression::Call(c) => {
+                    c.function.clear_spans();
+                    c.arguments.clear_spans();
+                    c.keyword_arguments.clear_spans();
+                    c.span = Span::default();
+                }
+                Expression::Index(i) => {
+                    i.left.clear_spans();
+                    i.index.clear_spans();
+                    i.span = Span::default();
+                }
+                Expression::Assign(a) => {
+                    a.name.clear_spans();
+                    a.value.clear_spans();
+                    a.span = Span::default();
+                }
+                Expression::Match(m) => {
+                    m.value.clear_spans();
+                    for arm in m.arms.iter_mut() {
+                        arm.pattern.clear_spans();
+                        arm.guard.clear_spans();
+                        arm.body.clear_spans();
+                        arm.span = Span::default();
+                    }
+                    m.span = Span::default();
+                }
+            }
+        }
+    }
+
+    impl ClearSpans for Pattern {
+        fn clear_spans(&mut self) {
+            match self {
+                Pattern::Wildcard(span) => *span = Span::default(),
+                Pattern::Binding(name) => name.clear_spans(),
+                Pattern::Literal(literal) => literal.clear_spans(),
+                Pattern::Array(items, rest, span) => {
+                    items.clear_spans();
+                    rest.clear_spans();
+                    *span = Span::default();
+                }
+                Pattern::Hash(entries, span) => {
+                    entries.clear_spans();
+                    *span = Span::default();
+                }
+            }
         }
-        panic!("There were errors in the parser")
     }
 
     use super::Parser;
 
     #[test]
+    #[allow(clippy::useless_conversion, clippy::useless_vec)]
     fn test_array_parse() -> Result<()> {
         let input: Vec<u8> = r#"[1, 2 *2, 3+3]"#.into();
         let lexer = Lexer::new(input.into());
         let mut parser = Parser::new(lexer);
         check_errors(parser.errors.clone());
 
-        let program = parser.parse_program().unwrap();
+        let program = parser.parse_program();
         if program.statements.len() != 1 {
             return Err(anyhow!("not right number of statments"));
         }
@@ -401,16 +1225,18 @@ mod tests {
         println!("{:?}", program.statements[0]);
 
         let expected = vec![
-            Expression::Integer(Token::Int(1)),
+            Expression::Integer(Token::Int(1), Span::default()),
             Expression::Infix(Box::new(InfixExpression::new(
-                Expression::Integer(Token::Int(2)),
+                Expression::Integer(Token::Int(2), Span::default()),
                 Token::Asterisk,
-                Expression::Integer(Token::Int(2)),
+                Expression::Integer(Token::Int(2), Span::default()),
+                Span::default(),
             ))),
             Expression::Infix(Box::new(InfixExpression::new(
-                Expression::Integer(Token::Int(3)),
+                Expression::Integer(Token::Int(3), Span::default()),
                 Token::Plus,
-                Expression::Integer(Token::Int(3)),
+                Expression::Integer(Token::Int(3), Span::default()),
+                Span::default(),
             ))),
         ];
 
@@ -418,8 +1244,8 @@ mod tests {
             let statement = &program.statements[0];
             match statement {
                 Statement::Expression(ex) => match ex {
-                    Expression::Array(a) => {
-                        assert_eq!(a.get(i).unwrap(), expression)
+                    Expression::Array(a, _) => {
+                        assert_eq!(without_spans(a.get(i).unwrap()), *expression)
                     }
                     _ => todo!(),
                 },
@@ -430,33 +1256,161 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_index_expression_parsing() -> Result<()> {
+        struct Test {
+            input: Vec<u8>,
+            expected: Expression,
+        }
+
+        let tests = vec![
+            Test {
+                input: "myArray[1 + 1]".into(),
+                expected: Expression::Index(Box::new(IndexExpression::new(
+                    Token::LBracket,
+                    Expression::Identifier(Token::Ident(String::from("myArray")), Span::default()),
+                    Expression::Infix(Box::new(InfixExpression::new(
+                        Expression::Integer(Token::Int(1), Span::default()),
+                        Token::Plus,
+                        Expression::Integer(Token::Int(1), Span::default()),
+                        Span::default(),
+                    ))),
+                    Span::default(),
+                ))),
+            },
+            Test {
+                input: "a * [1, 2][1]".into(),
+                expected: Expression::Infix(Box::new(InfixExpression::new(
+                    Expression::Identifier(Token::Ident(String::from("a")), Span::default()),
+                    Token::Asterisk,
+                    Expression::Index(Box::new(IndexExpression::new(
+                        Token::LBracket,
+                        Expression::Array(
+                            vec![
+                                Expression::Integer(Token::Int(1), Span::default()),
+                                Expression::Integer(Token::Int(2), Span::default()),
+                            ],
+                            Span::default(),
+                        ),
+                        Expression::Integer(Token::Int(1), Span::default()),
+                        Span::default(),
+                    ))),
+                    Span::default(),
+                ))),
+            },
+        ];
+
+        for test in tests.into_iter() {
+            let lex = Lexer::new(test.input);
+            let mut parser = Parser::new(lex);
+            let program = parser.parse_program();
+            check_errors(parser.errors().clone());
+
+            if program.statements.len() != 1 {
+                return Err(anyhow!("Wrong number of statements"));
+            }
+
+            match &program.statements[0] {
+                Statement::Expression(exp) => assert_eq!(without_spans(exp), test.expected),
+                _ => todo!(),
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_hash_literal_parsing() -> Result<()> {
+        struct Test {
+            input: Vec<u8>,
+            expected: Vec<(Expression, Expression)>,
+        }
+
+        let tests = vec![
+            Test {
+                input: r#"{"one": 1, "two": 2}"#.into(),
+                expected: vec![
+                    (
+                        Expression::String(String::from("one"), Span::default()),
+                        Expression::Integer(Token::Int(1), Span::default()),
+                    ),
+                    (
+                        Expression::String(String::from("two"), Span::default()),
+                        Expression::Integer(Token::Int(2), Span::default()),
+                    ),
+                ],
+            },
+            Test {
+                input: "{}".into(),
+                expected: vec![],
+            },
+            Test {
+                input: r#"{"sum": 1 + 2, true: x}"#.into(),
+                expected: vec![
+                    (
+                        Expression::String(String::from("sum"), Span::default()),
+                        Expression::Infix(Box::new(InfixExpression::new(
+                            Expression::Integer(Token::Int(1), Span::default()),
+                            Token::Plus,
+                            Expression::Integer(Token::Int(2), Span::default()),
+                            Span::default(),
+                        ))),
+                    ),
+                    (
+                        Expression::Boolean(Token::True, Span::default()),
+                        Expression::Identifier(Token::Ident(String::from("x")), Span::default()),
+                    ),
+                ],
+            },
+        ];
+
+        for test in tests.into_iter() {
+            let lex = Lexer::new(test.input);
+            let mut parser = Parser::new(lex);
+            let program = parser.parse_program();
+            check_errors(parser.errors().clone());
+
+            if program.statements.len() != 1 {
+                return Err(anyhow!("Wrong number of statements"));
+            }
+
+            match &program.statements[0] {
+                Statement::Expression(Expression::Hash(pairs, _)) => {
+                    assert_eq!(without_spans(pairs), test.expected)
+                }
+                _ => todo!(),
+            }
+        }
+        Ok(())
+    }
+
     #[test]
     fn test_let_statment() -> Result<()> {
         let input = r#"
         let x = 5;
         let y = 10;
-        let foobar = 838383;
+        const foobar = 838383;
         "#;
 
         let lexer = Lexer::new(input.into());
         let mut parser = Parser::new(lexer);
         check_errors(parser.errors.clone());
 
-        let program = parser.parse_program().unwrap();
+        let program = parser.parse_program();
         if program.statements.len() != 3 {
             return Err(anyhow!("not right number of statments"));
         }
 
         let expected_idents = vec![
-            Token::Ident(String::from("x")),
-            Token::Ident(String::from("y")),
-            Token::Ident(String::from("foobar")),
+            (Token::Ident(String::from("x")), false),
+            (Token::Ident(String::from("y")), false),
+            (Token::Ident(String::from("foobar")), true),
         ];
-        for (i, ident) in expected_idents.into_iter().enumerate() {
+        for (i, (ident, constant)) in expected_idents.into_iter().enumerate() {
             let statment = &program.statements[i];
             match statment {
                 Statement::Let(x) => {
-                    assert_eq!(ident, x.token)
+                    assert_eq!(ident, x.token);
+                    assert_eq!(constant, x.constant);
                 }
                 _ => todo!(),
             }
@@ -476,7 +1430,7 @@ mod tests {
         let mut parser = Parser::new(lexer);
         check_errors(parser.errors().clone());
 
-        let program = parser.parse_program().unwrap();
+        let program = parser.parse_program();
 
         if program.statements.len() != 3 {
             return Err(anyhow!("wrong number of statements"));
@@ -486,7 +1440,8 @@ mod tests {
                 std::mem::discriminant(&stmt),
                 std::mem::discriminant(&Statement::Return(Return::new(
                     Token::Return,
-                    Expression::Identifier(Token::Return),
+                    Expression::Identifier(Token::Return, Span::default()),
+                    Span::default(),
                 )))
             );
         });
@@ -499,7 +1454,7 @@ mod tests {
 
         let lex = Lexer::new(input);
         let mut parser = Parser::new(lex);
-        let program = parser.parse_program().unwrap();
+        let program = parser.parse_program();
         check_errors(parser.errors().clone());
 
         if program.statements.len() != 1 {
@@ -508,7 +1463,7 @@ mod tests {
 
         match &program.statements[0] {
             Statement::Expression(exp) => match exp {
-                Expression::Identifier(t) => match t {
+                Expression::Identifier(t, _) => match t {
                     Token::Ident(s) => {
                         assert_eq!(s, &"foobar".to_string())
                     }
@@ -522,12 +1477,13 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::unnecessary_cast)]
     fn test_integer_expression() -> Result<()> {
         let input: Vec<u8> = "5;".into();
 
         let lex = Lexer::new(input);
         let mut parser = Parser::new(lex);
-        let program = parser.parse_program().unwrap();
+        let program = parser.parse_program();
         check_errors(parser.errors().clone());
 
         if program.statements.len() != 1 {
@@ -536,7 +1492,7 @@ mod tests {
 
         match &program.statements[0] {
             Statement::Expression(exp) => match exp {
-                Expression::Integer(t) => match t {
+                Expression::Integer(t, _) => match t {
                     Token::Int(s) => {
                         assert_eq!(s.to_owned(), 5 as isize)
                     }
@@ -550,12 +1506,13 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::collapsible_match)]
     fn test_string_expression() -> Result<()> {
         let input: Vec<u8> = r#""Hello World""#.into();
 
         let lex = Lexer::new(input);
         let mut parser = Parser::new(lex);
-        let program = parser.parse_program().unwrap();
+        let program = parser.parse_program();
         check_errors(parser.errors().clone());
 
         if program.statements.len() != 1 {
@@ -564,7 +1521,7 @@ mod tests {
 
         match &program.statements[0] {
             Statement::Expression(exp) => match exp {
-                Expression::String(s) => assert_eq!(s.to_owned(), String::from("Hello World")),
+                Expression::String(s, _) => assert_eq!(s.to_owned(), String::from("Hello World")),
                 _ => println!("Other"),
             },
             _ => println!("Other"),
@@ -584,12 +1541,12 @@ mod tests {
 
         let lex = Lexer::new(input);
         let mut parser = Parser::new(lex);
-        let program = parser.parse_program().unwrap();
+        let program = parser.parse_program();
         check_errors(parser.errors().clone());
 
         match &program.statements[0] {
             Statement::Expression(exp) => match exp {
-                Expression::Boolean(t) => {
+                Expression::Boolean(t, _) => {
                     assert_eq!(t.to_owned(), Token::True);
                 }
                 _ => todo!(),
@@ -600,6 +1557,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::unnecessary_cast)]
     fn test_parsing_prefix_expressions() -> Result<()> {
         struct PrefixTest {
             input: String,
@@ -622,7 +1580,7 @@ mod tests {
         for test in tests.into_iter() {
             let lex = Lexer::new(test.input.into());
             let mut parser = Parser::new(lex);
-            let program = parser.parse_program().unwrap();
+            let program = parser.parse_program();
             check_errors(parser.errors().clone());
 
             if program.statements.len() != 1 {
@@ -635,7 +1593,7 @@ mod tests {
                         let prefix_expression = t;
                         assert_eq!(prefix_expression.token, test.operator);
                         match &prefix_expression.right {
-                            Expression::Integer(t) => assert_eq!(t, &Token::Int(test.int_value)),
+                            Expression::Integer(t, _) => assert_eq!(t, &Token::Int(test.int_value)),
                             _ => todo!(),
                         }
                     }
@@ -660,58 +1618,64 @@ mod tests {
         let tests = vec![
             Infix {
                 input: "5 + 5;".into(),
-                left_token: Expression::Integer(Token::Int(5)),
+                left_token: Expression::Integer(Token::Int(5), Span::default()),
                 operator: Token::Plus,
-                right_token: Expression::Integer(Token::Int(5)),
+                right_token: Expression::Integer(Token::Int(5), Span::default()),
             },
             Infix {
                 input: "5 - 5;".into(),
-                left_token: Expression::Integer(Token::Int(5)),
+                left_token: Expression::Integer(Token::Int(5), Span::default()),
                 operator: Token::Minus,
-                right_token: Expression::Integer(Token::Int(5)),
+                right_token: Expression::Integer(Token::Int(5), Span::default()),
             },
             Infix {
                 input: "5 * 5;".into(),
-                left_token: Expression::Integer(Token::Int(5)),
+                left_token: Expression::Integer(Token::Int(5), Span::default()),
                 operator: Token::Asterisk,
-                right_token: Expression::Integer(Token::Int(5)),
+                right_token: Expression::Integer(Token::Int(5), Span::default()),
             },
             Infix {
                 input: "5 / 5;".into(),
-                left_token: Expression::Integer(Token::Int(5)),
+                left_token: Expression::Integer(Token::Int(5), Span::default()),
                 operator: Token::Slash,
-                right_token: Expression::Integer(Token::Int(5)),
+                right_token: Expression::Integer(Token::Int(5), Span::default()),
             },
             Infix {
                 input: "5 > 5;".into(),
-                left_token: Expression::Integer(Token::Int(5)),
+                left_token: Expression::Integer(Token::Int(5), Span::default()),
                 operator: Token::GreaterThan,
-                right_token: Expression::Integer(Token::Int(5)),
+                right_token: Expression::Integer(Token::Int(5), Span::default()),
             },
             Infix {
                 input: "5 < 5;".into(),
-                left_token: Expression::Integer(Token::Int(5)),
+                left_token: Expression::Integer(Token::Int(5), Span::default()),
                 operator: Token::LessThan,
-                right_token: Expression::Integer(Token::Int(5)),
+                right_token: Expression::Integer(Token::Int(5), Span::default()),
             },
             Infix {
                 input: "5 == 5;".into(),
-                left_token: Expression::Integer(Token::Int(5)),
+                left_token: Expression::Integer(Token::Int(5), Span::default()),
                 operator: Token::Equal,
-                right_token: Expression::Integer(Token::Int(5)),
+                right_token: Expression::Integer(Token::Int(5), Span::default()),
+            },
+            Infix {
+                input: "1.5 * 2".into(),
+                left_token: Expression::Float(Token::Float(1.5), Span::default()),
+                operator: Token::Asterisk,
+                right_token: Expression::Integer(Token::Int(2), Span::default()),
             },
             Infix {
                 input: "5 != 5".into(),
-                left_token: Expression::Integer(Token::Int(5)),
+                left_token: Expression::Integer(Token::Int(5), Span::default()),
                 operator: Token::NotEqual,
-                right_token: Expression::Integer(Token::Int(5)),
+                right_token: Expression::Integer(Token::Int(5), Span::default()),
             },
         ];
 
         for test in tests.into_iter() {
             let lex = Lexer::new(test.input);
             let mut parser = Parser::new(lex);
-            let program = parser.parse_program().unwrap();
+            let program = parser.parse_program();
             check_errors(parser.errors().clone());
 
             if program.statements.len() != 1 {
@@ -722,9 +1686,9 @@ mod tests {
                 Statement::Expression(exp) => match exp {
                     // TODO: finish writing test
                     Expression::Infix(i) => {
-                        assert_eq!(i.left, test.left_token);
+                        assert_eq!(without_spans(&i.left), test.left_token);
                         assert_eq!(i.token, test.operator);
-                        assert_eq!(i.right, test.right_token);
+                        assert_eq!(without_spans(&i.right), test.right_token);
                     }
                     _ => todo!(),
                 },
@@ -749,66 +1713,75 @@ mod tests {
                 input: "-a * b".into(),
                 left: Expression::Prefix(Box::new(PrefixExpression::new(
                     Token::Minus,
-                    Expression::Identifier(Token::Ident("a".to_string())),
+                    Expression::Identifier(Token::Ident("a".to_string()), Span::default()),
+                    Span::default(),
                 ))),
                 token: Token::Asterisk,
-                right: Expression::Identifier(Token::Ident(String::from("b"))),
+                right: Expression::Identifier(Token::Ident(String::from("b")), Span::default()),
             },
             Test {
                 input: "3 + 4 * 5 == 3 * 1 + 4 * 5".into(),
                 left: Expression::Infix(Box::new(InfixExpression::new(
-                    Expression::Integer(Token::Int(3)),
+                    Expression::Integer(Token::Int(3), Span::default()),
                     Token::Plus,
                     Expression::Infix(Box::new(InfixExpression::new(
-                        Expression::Integer(Token::Int(4)),
+                        Expression::Integer(Token::Int(4), Span::default()),
                         Token::Asterisk,
-                        Expression::Integer(Token::Int(5)),
+                        Expression::Integer(Token::Int(5), Span::default()),
+                        Span::default(),
                     ))),
+                    Span::default(),
                 ))),
                 token: Token::Equal,
                 right: Expression::Infix(Box::new(InfixExpression::new(
                     Expression::Infix(Box::new(InfixExpression::new(
-                        Expression::Integer(Token::Int(3)),
+                        Expression::Integer(Token::Int(3), Span::default()),
                         Token::Asterisk,
-                        Expression::Integer(Token::Int(1)),
+                        Expression::Integer(Token::Int(1), Span::default()),
+                        Span::default(),
                     ))),
                     Token::Plus,
                     Expression::Infix(Box::new(InfixExpression::new(
-                        Expression::Integer(Token::Int(4)),
+                        Expression::Integer(Token::Int(4), Span::default()),
                         Token::Asterisk,
-                        Expression::Integer(Token::Int(5)),
+                        Expression::Integer(Token::Int(5), Span::default()),
+                        Span::default(),
                     ))),
+                    Span::default(),
                 ))),
             },
             Test {
                 input: "3 < 5 == true".into(),
                 left: Expression::Infix(Box::new(InfixExpression::new(
-                    Expression::Integer(Token::Int(3)),
+                    Expression::Integer(Token::Int(3), Span::default()),
                     Token::LessThan,
-                    Expression::Integer(Token::Int(5)),
+                    Expression::Integer(Token::Int(5), Span::default()),
+                    Span::default(),
                 ))),
                 token: Token::Equal,
-                right: Expression::Boolean(Token::True),
+                right: Expression::Boolean(Token::True, Span::default()),
             },
             Test {
                 input: "1 + (2 + 3) + 4".into(),
                 left: Expression::Infix(Box::new(InfixExpression {
-                    left: Expression::Integer(Token::Int(1)),
+                    left: Expression::Integer(Token::Int(1), Span::default()),
                     token: Token::Plus,
                     right: Expression::Infix(Box::new(InfixExpression::new(
-                        Expression::Integer(Token::Int(2)),
+                        Expression::Integer(Token::Int(2), Span::default()),
                         Token::Plus,
-                        Expression::Integer(Token::Int(3)),
+                        Expression::Integer(Token::Int(3), Span::default()),
+                        Span::default(),
                     ))),
+                    span: Span::default(),
                 })),
                 token: Token::Plus,
-                right: Expression::Integer(Token::Int(4)),
+                right: Expression::Integer(Token::Int(4), Span::default()),
             },
         ];
         for test in tests.into_iter() {
             let lex = Lexer::new(test.input);
             let mut parser = Parser::new(lex);
-            let program = parser.parse_program().unwrap();
+            let program = parser.parse_program();
 
             if program.statements.len() != 1 {
                 return Err(anyhow!("Wrong number of statements"));
@@ -817,9 +1790,9 @@ mod tests {
             match &program.statements[0] {
                 Statement::Expression(exp) => match exp {
                     Expression::Infix(i) => {
-                        assert_eq!(i.left, test.left);
+                        assert_eq!(without_spans(&i.left), test.left);
                         assert_eq!(i.token, test.token);
-                        assert_eq!(i.right, test.right);
+                        assert_eq!(without_spans(&i.right), test.right);
                     }
                     _ => todo!(),
                 },
@@ -829,6 +1802,75 @@ mod tests {
         Ok(())
     }
 
+    /// Renders operators with explicit parentheses to check how an expression was grouped.
+    fn parenthesize(expression: &Expression) -> String {
+        match expression {
+            Expression::Prefix(p) => format!("({}{})", p.token, parenthesize(&p.right)),
+            Expression::Infix(i) => format!(
+                "({} {} {})",
+                parenthesize(&i.left),
+                i.token,
+                parenthesize(&i.right)
+            ),
+            Expression::Assign(a) => format!(
+                "({} {} {})",
+                parenthesize(&Expression::Identifier(a.name.token.clone(), a.name.span)),
+                a.token,
+                parenthesize(&a.value)
+            ),
+            Expression::Call(c) => {
+                let arguments: Vec<String> =
+                    c.arguments.iter().flatten().map(parenthesize).collect();
+                format!("{}({})", parenthesize(&c.function), arguments.join(", "))
+            }
+            Expression::Identifier(Token::Ident(name), _) => name.to_owned(),
+            Expression::Integer(Token::Int(i), _) => i.to_string(),
+            other => format!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_operator_precedence_table() {
+        let tests = vec![
+            ("a ** b ** c", "(a Power (b Power c))"),
+            ("-a ** b", "(Minus(a Power b))"),
+            ("a ** -b", "(a Power (Minusb))"),
+            ("a * b ** c % d", "((a Asterisk (b Power c)) Percent d)"),
+            ("a + b << c - d", "((a Plus b) ShiftLeft (c Minus d))"),
+            ("a & b ^ c | d", "(((a Ampersand b) Caret c) Pipe d)"),
+            ("a | b <= c", "((a Pipe b) LessEqual c)"),
+            (
+                "a >= b == c < d",
+                "((a GreaterEqual b) Equal (c LessThan d))",
+            ),
+            ("a || b && c == d", "(a Or (b And (c Equal d)))"),
+            ("a && b || !c", "((a And b) Or (Bangc))"),
+            ("a = b = c || d", "(a Assign (b Assign (c Or d)))"),
+            ("a += b * c", "(a PlusAssign (b Asterisk c))"),
+            ("a %= -b", "(a PercentAssign (Minusb))"),
+            ("a |> f |> g(b)", "g(f(a), b)"),
+            ("a + b |> f", "f((a Plus b))"),
+            ("a || b |> f(c)(d)", "f(c)(d)((a Or b))"),
+            ("a |> (f(c))", "f(c)(a)"),
+            ("a |> (f)(c)", "f(a, c)"),
+            ("a |> f((c))", "f(a, c)"),
+            ("a |> (f(c))(d)", "f(c)(d)(a)"),
+            ("x = a |> f()", "(x Assign f(a))"),
+        ];
+
+        for (input, expected) in tests.into_iter() {
+            let lex = Lexer::new(input.into());
+            let mut parser = Parser::new(lex);
+            let program = parser.parse_program();
+            check_errors(parser.errors());
+
+            match &program.statements[0] {
+                Statement::Expression(exp) => assert_eq!(parenthesize(exp), expected),
+                other => panic!("expected an expression, got {:?}", other),
+            }
+        }
+    }
+
     #[test]
     fn test_if_expression() -> Result<()> {
         struct Test {
@@ -844,15 +1886,18 @@ mod tests {
                 input: "if (x < y) {x}".into(),
                 token: Token::If,
                 condition: Expression::Infix(Box::new(InfixExpression::new(
-                    Expression::Identifier(Token::Ident(String::from("x"))),
+                    Expression::Identifier(Token::Ident(String::from("x")), Span::default()),
                     Token::LessThan,
-                    Expression::Identifier(Token::Ident(String::from("y"))),
+                    Expression::Identifier(Token::Ident(String::from("y")), Span::default()),
+                    Span::default(),
                 ))),
                 consequnce: BlockStatement::new(
                     Token::LBrace,
-                    vec![Statement::Expression(Expression::Identifier(Token::Ident(
-                        String::from("x"),
-                    )))],
+                    vec![Statement::Expression(Expression::Identifier(
+                        Token::Ident(String::from("x")),
+                        Span::default(),
+                    ))],
+                    Span::default(),
                 ),
                 alternative: None,
             },
@@ -860,21 +1905,62 @@ mod tests {
                 input: "if (x < y) {x} else {y}".into(),
                 token: Token::If,
                 condition: Expression::Infix(Box::new(InfixExpression::new(
-                    Expression::Identifier(Token::Ident(String::from("x"))),
+                    Expression::Identifier(Token::Ident(String::from("x")), Span::default()),
                     Token::LessThan,
-                    Expression::Identifier(Token::Ident(String::from("y"))),
+                    Expression::Identifier(Token::Ident(String::from("y")), Span::default()),
+                    Span::default(),
                 ))),
                 consequnce: BlockStatement::new(
                     Token::LBrace,
-                    vec![Statement::Expression(Expression::Identifier(Token::Ident(
-                        String::from("x"),
-                    )))],
+                    vec![Statement::Expression(Expression::Identifier(
+                        Token::Ident(String::from("x")),
+                        Span::default(),
+                    ))],
+                    Span::default(),
                 ),
                 alternative: Some(BlockStatement::new(
                     Token::LBrace,
-                    vec![Statement::Expression(Expression::Identifier(Token::Ident(
-                        String::from("y"),
+                    vec![Statement::Expression(Expression::Identifier(
+                        Token::Ident(String::from("y")),
+                        Span::default(),
+                    ))],
+                    Span::default(),
+                )),
+            },
+            Test {
+                input: "if (x) {x} else if (y) {y}".into(),
+                token: Token::If,
+                condition: Expression::Identifier(Token::Ident(String::from("x")), Span::default()),
+                consequnce: BlockStatement::new(
+                    Token::LBrace,
+                    vec![Statement::Expression(Expression::Identifier(
+                        Token::Ident(String::from("x")),
+                        Span::default(),
+                    ))],
+                    Span::default(),
+                ),
+                alternative: Some(BlockStatement::new(
+                    Token::Else,
+                    vec![Statement::Expression(Expression::If(Box::new(
+                        IfExpression::new(
+                            Token::If,
+                            Expression::Identifier(
+                                Token::Ident(String::from("y")),
+                                Span::default(),
+                            ),
+                            BlockStatement::new(
+                                Token::LBrace,
+                                vec![Statement::Expression(Expression::Identifier(
+                                    Token::Ident(String::from("y")),
+                                    Span::default(),
+                                ))],
+                                Span::default(),
+                            ),
+                            None,
+                            Span::default(),
+                        ),
                     )))],
+                    Span::default(),
                 )),
             },
         ];
@@ -882,7 +1968,7 @@ mod tests {
         for test in tests.into_iter() {
             let lex = Lexer::new(test.input);
             let mut parser = Parser::new(lex);
-            let program = parser.parse_program().unwrap();
+            let program = parser.parse_program();
             check_errors(parser.errors().clone());
 
             if program.statements.len() != 1 {
@@ -892,9 +1978,9 @@ mod tests {
                 Statement::Expression(exp) => match exp {
                     Expression::If(i) => {
                         assert_eq!(i.token, test.token);
-                        assert_eq!(i.condition, test.condition);
-                        assert_eq!(i.consequence, test.consequnce);
-                        assert_eq!(i.alternative, test.alternative);
+                        assert_eq!(without_spans(&i.condition), test.condition);
+                        assert_eq!(without_spans(&i.consequence), test.consequnce);
+                        assert_eq!(without_spans(&i.alternative), test.alternative);
                     }
                     _ => todo!(),
                 },
@@ -909,32 +1995,61 @@ mod tests {
     fn test_function_expression() -> Result<()> {
         struct Test {
             input: Vec<u8>,
-            paramaters: Option<Vec<Identifier>>,
+            paramaters: Option<Vec<Parameter>>,
             body: BlockStatement,
         }
 
-        let tests = vec![Test {
-            input: "fn(x,y) {x+y}".into(),
-            paramaters: Some(vec![
-                Identifier::new(Token::Ident(String::from("x"))),
-                Identifier::new(Token::Ident(String::from("y"))),
-            ]),
-            body: BlockStatement::new(
-                Token::LBrace,
-                vec![Statement::Expression(Expression::Infix(Box::new(
-                    InfixExpression::new(
-                        Expression::Identifier(Token::Ident(String::from("x"))),
-                        Token::Plus,
-                        Expression::Identifier(Token::Ident(String::from("y"))),
+        let ident = |name: &str| Identifier::new(Token::Ident(String::from(name)), Span::default());
+        let tests = vec![
+            Test {
+                input: "fn(x,y) {x+y}".into(),
+                paramaters: Some(vec![
+                    Parameter::new(ident("x"), None, false),
+                    Parameter::new(ident("y"), None, false),
+                ]),
+                body: BlockStatement::new(
+                    Token::LBrace,
+                    vec![Statement::Expression(Expression::Infix(Box::new(
+                        InfixExpression::new(
+                            Expression::Identifier(
+                                Token::Ident(String::from("x")),
+                                Span::default(),
+                            ),
+                            Token::Plus,
+                            Expression::Identifier(
+                                Token::Ident(String::from("y")),
+                                Span::default(),
+                            ),
+                            Span::default(),
+                        ),
+                    )))],
+                    Span::default(),
+                ),
+            },
+            Test {
+                input: "fn(x, y = 10, ...z) {}".into(),
+                paramaters: Some(vec![
+                    Parameter::new(ident("x"), None, false),
+                    Parameter::new(
+                        ident("y"),
+                        Some(Expression::Integer(Token::Int(10), Span::default())),
+                        false,
                     ),
-                )))],
-            ),
-        }];
+                    Parameter::new(ident("z"), None, true),
+                ]),
+                body: BlockStatement::new(Token::LBrace, vec![], Span::default()),
+            },
+            Test {
+                input: "fn(...rest) {}".into(),
+                paramaters: Some(vec![Parameter::new(ident("rest"), None, true)]),
+                body: BlockStatement::new(Token::LBrace, vec![], Span::default()),
+            },
+        ];
 
         for test in tests.into_iter() {
             let lex = Lexer::new(test.input);
             let mut parser = Parser::new(lex);
-            let program = parser.parse_program().unwrap();
+            let program = parser.parse_program();
 
             if program.statements.len() != 1 {
                 return Err(anyhow!("Wrong number of statements"));
@@ -943,8 +2058,8 @@ mod tests {
             match &program.statements[0] {
                 Statement::Expression(exp) => match exp {
                     Expression::Fn(f) => {
-                        assert_eq!(test.paramaters, f.parameters);
-                        assert_eq!(test.body, f.body);
+                        assert_eq!(without_spans(&f.parameters), test.paramaters);
+                        assert_eq!(without_spans(&f.body), test.body);
                     }
                     _ => todo!(),
                 },
@@ -955,36 +2070,119 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_function_statement() {
+        let input = "fn double(x) { x * 2 } double(1)";
+        let lex = Lexer::new(input.into());
+        let mut parser = Parser::new(lex);
+        let program = parser.parse_program();
+        check_errors(parser.errors());
+
+        let name = Identifier::new(Token::Ident(String::from("double")), Span::default());
+        let x = Expression::Identifier(Token::Ident(String::from("x")), Span::default());
+        assert_eq!(
+            without_spans(&program.statements[0]),
+            Statement::Let(Let::new(
+                name.token.clone(),
+                name.clone(),
+                Expression::Fn(Box::new(FnExpression::new(
+                    Token::Function,
+                    Some(name),
+                    Some(vec![Parameter::new(
+                        Identifier::new(Token::Ident(String::from("x")), Span::default()),
+                        None,
+                        false,
+                    )]),
+                    BlockStatement::new(
+                        Token::LBrace,
+                        vec![Statement::Expression(Expression::Infix(Box::new(
+                            InfixExpression::new(
+                                x,
+                                Token::Asterisk,
+                                Expression::Integer(Token::Int(2), Span::default()),
+                                Span::default(),
+                            ),
+                        )))],
+                        Span::default(),
+                    ),
+                    Span::default(),
+                ))),
+                false,
+                Span::default(),
+            ))
+        );
+        assert_eq!(program.statements.len(), 2);
+    }
+
     #[test]
     fn test_call_function_expression_parsing() -> Result<()> {
         struct Test {
             input: Vec<u8>,
             function: Expression,
             arguments: Option<Vec<Expression>>,
+            keyword_arguments: Vec<(Identifier, Expression)>,
         }
 
-        let tests = vec![Test {
-            input: "add(1, 2 * 3, 4 +5)".into(),
-            function: Expression::Identifier(Token::Ident(String::from("add"))),
-            arguments: Some(vec![
-                Expression::Integer(Token::Int(1)),
-                Expression::Infix(Box::new(InfixExpression::new(
-                    Expression::Integer(Token::Int(2)),
-                    Token::Asterisk,
-                    Expression::Integer(Token::Int(3)),
-                ))),
-                Expression::Infix(Box::new(InfixExpression::new(
-                    Expression::Integer(Token::Int(4)),
-                    Token::Plus,
-                    Expression::Integer(Token::Int(5)),
-                ))),
-            ]),
-        }];
+        let tests = vec![
+            Test {
+                input: "add(1, 2 * 3, 4 +5)".into(),
+                function: Expression::Identifier(
+                    Token::Ident(String::from("add")),
+                    Span::default(),
+                ),
+                arguments: Some(vec![
+                    Expression::Integer(Token::Int(1), Span::default()),
+                    Expression::Infix(Box::new(InfixExpression::new(
+                        Expression::Integer(Token::Int(2), Span::default()),
+                        Token::Asterisk,
+                        Expression::Integer(Token::Int(3), Span::default()),
+                        Span::default(),
+                    ))),
+                    Expression::Infix(Box::new(InfixExpression::new(
+                        Expression::Integer(Token::Int(4), Span::default()),
+                        Token::Plus,
+                        Expression::Integer(Token::Int(5), Span::default()),
+                        Span::default(),
+                    ))),
+                ]),
+                keyword_arguments: vec![],
+            },
+            Test {
+                input: "greet(\"hi\", name: \"bob\", loud: true)".into(),
+                function: Expression::Identifier(
+                    Token::Ident(String::from("greet")),
+                    Span::default(),
+                ),
+                arguments: Some(vec![Expression::String(
+                    String::from("hi"),
+                    Span::default(),
+                )]),
+                keyword_arguments: vec![
+                    (
+                        Identifier::new(Token::Ident(String::from("name")), Span::default()),
+                        Expression::String(String::from("bob"), Span::default()),
+                    ),
+                    (
+                        Identifier::new(Token::Ident(String::from("loud")), Span::default()),
+                        Expression::Boolean(Token::True, Span::default()),
+                    ),
+                ],
+            },
+            Test {
+                input: "f(y: 2)".into(),
+                function: Expression::Identifier(Token::Ident(String::from("f")), Span::default()),
+                arguments: None,
+                keyword_arguments: vec![(
+                    Identifier::new(Token::Ident(String::from("y")), Span::default()),
+                    Expression::Integer(Token::Int(2), Span::default()),
+                )],
+            },
+        ];
 
         for test in tests.into_iter() {
             let lex = Lexer::new(test.input);
             let mut parser = Parser::new(lex);
-            let program = parser.parse_program().unwrap();
+            let program = parser.parse_program();
 
             if program.statements.len() != 1 {
                 return Err(anyhow!("wrong number of statements"));
@@ -993,8 +2191,9 @@ mod tests {
             match &program.statements[0] {
                 Statement::Expression(exp) => match exp {
                     Expression::Call(c) => {
-                        assert_eq!(c.function, test.function);
-                        assert_eq!(c.arguments, test.arguments);
+                        assert_eq!(without_spans(&c.function), test.function);
+                        assert_eq!(without_spans(&c.arguments), test.arguments);
+                        assert_eq!(without_spans(&c.keyword_arguments), test.keyword_arguments);
                     }
                     _ => todo!(),
                 },
@@ -1003,4 +2202,326 @@ mod tests {
         }
         Ok(())
     }
+
+    #[test]
+    fn test_node_spans() {
+        let input: Vec<u8> = "let x = 1;\n  x + add(2, [3])[0];".into();
+        let lex = Lexer::new(input);
+        let mut parser = Parser::new(lex);
+        let program = parser.parse_program();
+        check_errors(parser.errors().clone());
+
+        let position = |span: Span| (span.line, span.column);
+        assert_eq!(position(program.statements[0].span()), (1, 1));
+        match &program.statements[1] {
+            Statement::Expression(Expression::Infix(i)) => {
+                assert_eq!(position(i.span), (2, 5));
+                assert_eq!(position(i.left.span()), (2, 3));
+                match &i.right {
+                    Expression::Index(index) => {
+                        assert_eq!(position(index.span), (2, 18));
+                        assert_eq!(position(index.left.span()), (2, 7));
+                        assert_eq!(position(index.index.span()), (2, 19));
+                    }
+                    _ => panic!("expected an index expression, got {:?}", i.right),
+                }
+            }
+            other => panic!("expected an infix expression, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_interpolated_string_parsing() {
+        let lex = Lexer::new(r#""a ${x + 1} b""#.into());
+        let mut parser = Parser::new(lex);
+        let program = parser.parse_program();
+        check_errors(parser.errors());
+
+        let expected = Expression::Interpolated(
+            vec![
+                Expression::String(String::from("a "), Span::default()),
+                Expression::Infix(Box::new(InfixExpression::new(
+                    Expression::Identifier(Token::Ident(String::from("x")), Span::default()),
+                    Token::Plus,
+                    Expression::Integer(Token::Int(1), Span::default()),
+                    Span::default(),
+                ))),
+                Expression::String(String::from(" b"), Span::default()),
+            ],
+            Span::default(),
+        );
+        assert_eq!(
+            without_spans(&program.statements),
+            vec![Statement::Expression(expected)]
+        );
+    }
+
+    #[test]
+    fn test_loop_parsing() {
+        let input = "while (i < 3) { i; break; }; for (x in [1, 2]) { continue }";
+        let lex = Lexer::new(input.into());
+        let mut parser = Parser::new(lex);
+        let program = parser.parse_program();
+        check_errors(parser.errors());
+
+        assert_eq!(
+            without_spans(&program.statements),
+            vec![
+                Statement::While(While::new(
+                    Token::While,
+                    Expression::Infix(Box::new(InfixExpression::new(
+                        Expression::Identifier(Token::Ident(String::from("i")), Span::default()),
+                        Token::LessThan,
+                        Expression::Integer(Token::Int(3), Span::default()),
+                        Span::default(),
+                    ))),
+                    BlockStatement::new(
+                        Token::LBrace,
+                        vec![
+                            Statement::Expression(Expression::Identifier(
+                                Token::Ident(String::from("i")),
+                                Span::default(),
+                            )),
+                            Statement::Break(Span::default()),
+                        ],
+                        Span::default(),
+                    ),
+                    Span::default(),
+                )),
+                Statement::For(For::new(
+                    Token::For,
+                    Identifier::new(Token::Ident(String::from("x")), Span::default()),
+                    Expression::Array(
+                        vec![
+                            Expression::Integer(Token::Int(1), Span::default()),
+                            Expression::Integer(Token::Int(2), Span::default()),
+                        ],
+                        Span::default(),
+                    ),
+                    BlockStatement::new(
+                        Token::LBrace,
+                        vec![Statement::Continue(Span::default())],
+                        Span::default(),
+                    ),
+                    Span::default(),
+                )),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_match_parsing() {
+        let input = r#"match (x) { -1 => a, [b, ...c] if (b) => b, {"k": _, d} => { return d }, }"#;
+        let lex = Lexer::new(input.into());
+        let mut parser = Parser::new(lex);
+        let program = parser.parse_program();
+        check_errors(parser.errors());
+
+        let ident = |name: &str| Identifier::new(Token::Ident(String::from(name)), Span::default());
+        let ident_expression =
+            |name: &str| Expression::Identifier(Token::Ident(String::from(name)), Span::default());
+        let block = |statement: Statement| {
+            BlockStatement::new(Token::LBrace, vec![statement], Span::default())
+        };
+        let expected = MatchExpression::new(
+            Token::Match,
+            ident_expression("x"),
+            vec![
+                MatchArm::new(
+                    Pattern::Literal(Expression::Prefix(Box::new(PrefixExpression::new(
+                        Token::Minus,
+                        Expression::Integer(Token::Int(1), Span::default()),
+                        Span::default(),
+                    )))),
+                    None,
+                    block(Statement::Expression(ident_expression("a"))),
+                    Span::default(),
+                ),
+                MatchArm::new(
+                    Pattern::Array(
+                        vec![Pattern::Binding(ident("b"))],
+                        Some(ident("c")),
+                        Span::default(),
+                    ),
+                    Some(ident_expression("b")),
+                    block(Statement::Expression(ident_expression("b"))),
+                    Span::default(),
+                ),
+                MatchArm::new(
+                    Pattern::Hash(
+                        vec![
+                            (
+                                Expression::String(String::from("k"), Span::default()),
+                                Pattern::Wildcard(Span::default()),
+                            ),
+                            (
+                                Expression::String(String::from("d"), Span::default()),
+                                Pattern::Binding(ident("d")),
+                            ),
+                        ],
+                        Span::default(),
+                    ),
+                    None,
+                    block(Statement::Return(Return::new(
+                        Token::Return,
+                        ident_expression("d"),
+                        Span::default(),
+                    ))),
+                    Span::default(),
+                ),
+            ],
+            Span::default(),
+        );
+        assert_eq!(
+            without_spans(&program.statements),
+            vec![Statement::Expression(Expression::Match(Box::new(expected)))]
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        struct Test {
+            input: &'static str,
+            statements: usize,
+            errors: Vec<&'static str>,
+        }
+
+        let tests = vec![
+            Test {
+                input: "let x 5; let y = 10; y",
+                statements: 2,
+                errors: vec!["line 1, column 7: Expected Assign but got Int: 5"],
+            },
+            Test {
+                input: "let = 1;\nlet a = );\nlet b = 2;",
+                statements: 1,
+                errors: vec![
+                    "line 1, column 5: Expected Ident but got Assign",
+                    "line 2, column 9: Unexpected token Right Paran",
+                ],
+            },
+            Test {
+                input: "let f = fn(x) { let y = ; x }; f(1 2); f(1)",
+                statements: 2,
+                errors: vec![
+                    "line 1, column 25: Unexpected token Semicolin",
+                    "line 1, column 36: Expected Right Paran but got Int: 2",
+                ],
+            },
+            Test {
+                input: "if (x { 1 } let z = 1;",
+                statements: 1,
+                errors: vec!["line 1, column 7: Expected Right Paran but got Left Brace"],
+            },
+            Test {
+                input: "fn(1) { 1 }; fn(x) { x",
+                statements: 0,
+                errors: vec![
+                    "line 1, column 4: Expected Ident but got Int: 1",
+                    "line 1, column 23: Expected Right Brace but got EOF",
+                ],
+            },
+            Test {
+                input: "\"ok\"; \"${1 +}\"; \"${a b}\"",
+                statements: 1,
+                errors: vec![
+                    "line 1, column 13: Unexpected token EOF",
+                    "line 1, column 22: Expected Right Brace but got Ident: b",
+                ],
+            },
+            Test {
+                input: "match (x) { 1 => 2 3 }; match (x) { + => 1 }; match (x) { [...] => 1 }",
+                statements: 0,
+                errors: vec![
+                    "line 1, column 20: Expected Commma but got Int: 3",
+                    "line 1, column 37: Unexpected token Plus in pattern",
+                    "line 1, column 63: Expected Ident but got Right Bracket",
+                ],
+            },
+            Test {
+                input: "let {a b} = c; let [d e] = f; let {g} h; let [i] = [1];",
+                statements: 1,
+                errors: vec![
+                    "line 1, column 8: Expected Commma but got Ident: b",
+                    "line 1, column 23: Expected Commma but got Ident: e",
+                    "line 1, column 39: Expected Assign but got Ident: h",
+                ],
+            },
+            Test {
+                input: "let [a, a] = [1, 2]; let {a, b: [c, ...a]} = d; match (x) { [e, {f: e}] => e }; let [_, _, ..._] = g;",
+                statements: 1,
+                errors: vec![
+                    "line 1, column 9: a is bound more than once in the pattern",
+                    "line 1, column 40: a is bound more than once in the pattern",
+                    "line 1, column 69: e is bound more than once in the pattern",
+                ],
+            },
+            Test {
+                input: "1 = 2; a + b -= 1; x = 1",
+                statements: 1,
+                errors: vec![
+                    "line 1, column 1: Cannot assign to this expression",
+                    "line 1, column 10: Cannot assign to this expression",
+                ],
+            },
+            Test {
+                input: "break; while (true) { let f = fn() { continue; }; break; }",
+                statements: 1,
+                errors: vec![
+                    "line 1, column 1: Break outside of a loop",
+                    "line 1, column 38: Continue outside of a loop",
+                ],
+            },
+            Test {
+                input: "fn(x = 1, y) {}; fn(...a, b) {}; f(a: 1, 2); f(1)",
+                statements: 1,
+                errors: vec![
+                    "line 1, column 11: Parameter without a default after one with a default",
+                    "line 1, column 24: Rest parameter must be last",
+                    "line 1, column 42: Positional argument after keyword arguments",
+                ],
+            },
+            Test {
+                input: "fn(x, x) {}; fn f(a, b = 1, ...a) {}; fn(c) { fn(c) { c } }",
+                statements: 1,
+                errors: vec![
+                    "line 1, column 7: Parameter x is declared more than once",
+                    "line 1, column 32: Parameter a is declared more than once",
+                ],
+            },
+            Test {
+                input: "let s = \"a\\qb\"; let t = \"open",
+                statements: 0,
+                errors: vec![
+                    "line 1, column 9: Unknown escape sequence: \\q",
+                    "line 1, column 25: Unterminated string",
+                ],
+            },
+        ];
+
+        for test in tests.into_iter() {
+            let lex = Lexer::new(test.input.into());
+            let mut parser = Parser::new(lex);
+            let program = parser.parse_program();
+
+            assert_eq!(parser.errors(), test.errors, "input: {}", test.input);
+            assert_eq!(
+                program.statements.len(),
+                test.statements,
+                "input: {}",
+                test.input
+            );
+        }
+    }
+
+    #[test]
+    fn test_comments_are_skipped() {
+        let input = "// setup\nlet a = /* one */ 1;\n/* the\n answer */ a + 2 // done";
+        for lex in [Lexer::new(input.into()), Lexer::with_comments(input.into())] {
+            let mut parser = Parser::new(lex);
+            let program = parser.parse_program();
+            check_errors(parser.errors());
+            assert_eq!(program.statements.len(), 2);
+        }
+    }
 }
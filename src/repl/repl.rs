@@ -1,13 +1,69 @@
-use std::{fmt::Debug, io::Write};
+use std::{io::Write, str::FromStr};
+
+use anyhow::{anyhow, Result};
 
 use crate::{
+    ast::ast::Program,
+    compiler::compiler::Compiler,
     lexer::lexer::Lexer,
     object::object::{Environment, Object},
     parser::parser::Parser,
+    vm::vm::Vm,
 };
 
-pub fn start() {
-    let mut env = Environment::new();
+/// The execution strategy used to run programs.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Engine {
+    /// The recursive tree walker in `Object::eval`.
+    Eval,
+    /// The bytecode compiler and stack vm.
+    Vm,
+}
+
+impl FromStr for Engine {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "eval" => Ok(Engine::Eval),
+            "vm" => Ok(Engine::Vm),
+            _ => Err(anyhow!("Unknown engine: {}. Expected eval or vm", s)),
+        }
+    }
+}
+
+/// Runs programs with one engine, keeping bindings between runs.
+enum Session {
+    Eval(Environment),
+    Vm(Box<Compiler>, Box<Vm>),
+}
+
+impl Session {
+    fn new(engine: Engine) -> Self {
+        match engine {
+            Engine::Eval => Session::Eval(Environment::new()),
+            Engine::Vm => Session::Vm(Box::default(), Box::default()),
+        }
+    }
+
+    fn run(&mut self, program: Program) -> Object {
+        match self {
+            Session::Eval(env) => Object::eval(program.statements, env),
+            Session::Vm(compiler, vm) => {
+                let result = compiler
+                    .compile(&program)
+                    .and_then(|bytecode| vm.run(bytecode));
+                match result {
+                    Ok(object) => object,
+                    Err(e) => Object::Error(e.to_string()),
+                }
+            }
+        }
+    }
+}
+
+pub fn start(engine: Engine) {
+    let mut session = Session::new(engine);
     loop {
         print!(">> ");
         std::io::stdout().flush().unwrap();
@@ -19,14 +75,36 @@ pub fn start() {
         let lexer = Lexer::new(input_string.into_bytes());
         let mut parser = Parser::new(lexer);
 
-        let program = parser.parse_program().unwrap();
-        if parser.errors().len() != 0 {
+        let program = parser.parse_program();
+        if !parser.errors().is_empty() {
             println!("There was an error in the program");
+            for err in parser.errors() {
+                println!("\t{}", err);
+            }
             continue;
         }
 
-        let eval = Object::eval(program.statements, &mut env);
+        let eval = session.run(program);
 
         println!("{}", eval);
     }
 }
+
+/// Runs the program in the file at `path` and prints its result.
+pub fn run_file(path: &str, engine: Engine) -> Result<()> {
+    let input = std::fs::read(path)?;
+    let lexer = Lexer::new(input);
+    let mut parser = Parser::new(lexer);
+
+    let program = parser.parse_program();
+    if !parser.errors().is_empty() {
+        return Err(anyhow!(
+            "There was an error in the program\n\t{}",
+            parser.errors().join("\n\t")
+        ));
+    }
+
+    let mut session = Session::new(engine);
+    println!("{}", session.run(program));
+    Ok(())
+}
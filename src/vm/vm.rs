@@ -0,0 +1,1073 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use anyhow::{anyhow, Result};
+
+use crate::{
+    compiler::{
+        code::{read_u16, Opcode},
+        compiler::Bytecode,
+    },
+    lexer::lexer::{Span, Token},
+    object::object::{
+        at, expected_arguments, Capture, Closure, CompiledFunction, HashKey, Mismatch, Object,
+    },
+};
+
+/// Calls nested deeper than this are reported as a stack overflow instead of exhausting
+/// memory.
+const MAX_FRAMES: usize = 10_000;
+
+/// The local variables of a frame, shared cells so closures can capture them.
+type Locals = Vec<Rc<RefCell<Object>>>;
+
+struct Frame {
+    closure: Closure,
+    ip: usize,
+    /// Offset of the instruction being executed, used to find its source position.
+    instruction_start: usize,
+    locals: Locals,
+    /// Which parameters were left without an argument, empty for functions without
+    /// defaults.
+    missing_arguments: Vec<bool>,
+    /// Height of the operand stack when the frame was entered.
+    base: usize,
+}
+
+impl Frame {
+    fn new(closure: Closure, locals: Locals, base: usize) -> Self {
+        Frame {
+            closure,
+            ip: 0,
+            instruction_start: 0,
+            locals,
+            missing_arguments: vec![],
+            base,
+        }
+    }
+}
+
+/// A stack machine executing `Bytecode` produced by the `Compiler`. Globals are kept between
+/// calls to `run` so a REPL can build on earlier input.
+#[derive(Default)]
+pub struct Vm {
+    constants: Vec<Object>,
+    global_names: Vec<String>,
+    globals: Vec<Option<Object>>,
+    stack: Vec<Object>,
+    frames: Vec<Frame>,
+    last_popped: Object,
+}
+
+impl Vm {
+    pub fn new() -> Self {
+        Vm::default()
+    }
+
+    /// Runs the program and returns the value of its last expression statement. A top level
+    /// `return` stops the program and yields `Object::Return`, as it does in the tree walker.
+    pub fn run(&mut self, bytecode: Bytecode) -> Result<Object> {
+        self.constants = bytecode.constants;
+        self.global_names = bytecode.global_names;
+        self.stack.clear();
+        self.last_popped = Object::Null;
+
+        let main = CompiledFunction {
+            instructions: bytecode.instructions,
+            num_locals: 0,
+            parameters: vec![],
+            num_defaults: 0,
+            variadic: false,
+            name: None,
+            captures: vec![],
+            positions: bytecode.positions,
+        };
+        self.frames = vec![Frame::new(Closure::new(Rc::new(main), vec![]), vec![], 0)];
+
+        self.execute().map_err(|e| match self.current_span() {
+            Some(span) => at(span, e),
+            None => e,
+        })
+    }
+
+    fn execute(&mut self) -> Result<Object> {
+        loop {
+            let frame = self.current_frame_mut();
+            let instructions = &frame.closure.function.instructions;
+            if frame.ip >= instructions.len() {
+                break;
+            }
+            let opcode = Opcode::try_from(instructions[frame.ip])?;
+            frame.instruction_start = frame.ip;
+            frame.ip += 1;
+
+            match opcode {
+                Opcode::Constant => {
+                    let index = self.read_u16_operand();
+                    let constant = self.constants[index].clone();
+                    self.push(constant);
+                }
+                Opcode::Pop => {
+                    self.last_popped = self.pop()?;
+                }
+                Opcode::Add
+                | Opcode::Sub
+                | Opcode::Mul
+                | Opcode::Div
+                | Opcode::Equal
+                | Opcode::NotEqual
+                | Opcode::GreaterThan
+                | Opcode::LessThan
+                | Opcode::GreaterEqual
+                | Opcode::LessEqual
+                | Opcode::Mod
+                | Opcode::Pow
+                | Opcode::BitAnd
+                | Opcode::BitOr
+                | Opcode::BitXor
+                | Opcode::ShiftLeft
+                | Opcode::ShiftRight => {
+                    let right = self.pop()?;
+                    let left = self.pop()?;
+                    let result = Object::eval_infix_expression(&infix_token(opcode), left, right)?;
+                    self.push(result);
+                }
+                Opcode::Minus | Opcode::Bang => {
+                    let token = match opcode {
+                        Opcode::Minus => Token::Minus,
+                        _ => Token::Bang,
+                    };
+                    let right = self.pop()?;
+                    self.push(Object::eval_prefix_expression(&token, right)?);
+                }
+                Opcode::True => self.push(Object::Boolean(true)),
+                Opcode::False => self.push(Object::Boolean(false)),
+                Opcode::Null => self.push(Object::Null),
+                Opcode::Jump => {
+                    let position = self.read_u16_operand();
+                    self.current_frame_mut().ip = position;
+                }
+                Opcode::JumpNotTruthy => {
+                    let position = self.read_u16_operand();
+                    if !self.pop()?.is_truthy() {
+                        self.current_frame_mut().ip = position;
+                    }
+                }
+                Opcode::Iterate => {
+                    let iterable = self.pop()?;
+                    self.push(Object::Array(iterable.iteration_items()?));
+                    self.push(Object::Integer(0));
+                }
+                Opcode::IterNext => {
+                    let position = self.read_u16_operand();
+                    let height = self.stack.len();
+                    let next = match self.stack.get(height.wrapping_sub(2)..) {
+                        Some([Object::Array(items), Object::Integer(index)]) => {
+                            items.get(*index as usize).cloned()
+                        }
+                        _ => return Err(anyhow!("Stack underflow")),
+                    };
+                    match next {
+                        Some(item) => {
+                            if let Some(Object::Integer(index)) = self.stack.last_mut() {
+                                *index += 1;
+                            }
+                            self.push(item);
+                        }
+                        None => {
+                            self.stack.truncate(height - 2);
+                            self.current_frame_mut().ip = position;
+                        }
+                    }
+                }
+                Opcode::GetGlobal => {
+                    let index = self.read_u16_operand();
+                    match self.globals.get(index).cloned().flatten() {
+                        Some(global) => self.push(global),
+                        None => {
+                            let name = self.global_names.get(index).cloned().unwrap_or_default();
+                            return Err(anyhow!("Identifier not found: {}", name));
+                        }
+                    }
+                }
+                Opcode::SetGlobal => {
+                    let index = self.read_u16_operand();
+                    if self.globals.len() <= index {
+                        self.globals.resize(index + 1, None);
+                    }
+                    self.globals[index] = Some(self.pop()?);
+                }
+                Opcode::AssignGlobal => {
+                    let index = self.read_u16_operand();
+                    let value = self.pop()?;
+                    match self.globals.get_mut(index) {
+                        Some(Some(global)) => *global = value,
+                        _ => {
+                            let name = self.global_names.get(index).cloned().unwrap_or_default();
+                            return Err(anyhow!("Cannot assign to undefined identifier: {}", name));
+                        }
+                    }
+                }
+                Opcode::GetLocal => {
+                    let index = self.read_u8_operand();
+                    let local = self.current_frame().locals[index].borrow().clone();
+                    self.push(local);
+                }
+                Opcode::SetLocal => {
+                    let index = self.read_u8_operand();
+                    let value = self.pop()?;
+                    *self.current_frame().locals[index].borrow_mut() = value;
+                }
+                Opcode::GetFree => {
+                    let index = self.read_u8_operand();
+                    let free = self.current_frame().closure.free[index].borrow().clone();
+                    self.push(free);
+                }
+                Opcode::SetFree => {
+                    let index = self.read_u8_operand();
+                    let value = self.pop()?;
+                    *self.current_frame().closure.free[index].borrow_mut() = value;
+                }
+                Opcode::Array => {
+                    let count = self.read_u16_operand();
+                    let elements = self.pop_many(count)?;
+                    self.push(Object::Array(elements));
+                }
+                Opcode::Hash => {
+                    let count = self.read_u16_operand();
+                    let items = self.pop_many(count * 2)?;
+                    let mut pairs: HashMap<HashKey, Object> = HashMap::new();
+                    for pair in items.chunks(2) {
+                        pairs.insert(HashKey::try_from(&pair[0])?, pair[1].clone());
+                    }
+                    self.push(Object::Hash(pairs));
+                }
+                Opcode::Interpolate => {
+                    let count = self.read_u16_operand();
+                    let values = self.pop_many(count)?;
+                    self.push(Object::interpolate(&values));
+                }
+                Opcode::Index => {
+                    let index = self.pop()?;
+                    let left = self.pop()?;
+                    self.push(Object::eval_index_expression(left, index)?);
+                }
+                Opcode::Call => {
+                    let num_args = self.read_u8_operand();
+                    self.call(num_args, vec![])?;
+                }
+                Opcode::CallKeywords => {
+                    let num_args = self.read_u8_operand();
+                    let keywords = match self.pop()? {
+                        Object::Array(names) => names
+                            .into_iter()
+                            .map(|name| match name {
+                                Object::String(s) => Ok(s),
+                                other => Err(anyhow!("Not a keyword: {}", other.inspect())),
+                            })
+                            .collect::<Result<Vec<String>>>()?,
+                        other => return Err(anyhow!("Not keyword names: {}", other.inspect())),
+                    };
+                    self.call(num_args, keywords)?;
+                }
+                Opcode::ArgumentMissing => {
+                    let index = self.read_u8_operand();
+                    let frame = self.current_frame();
+                    let missing = frame.missing_arguments.get(index).copied();
+                    self.push(Object::Boolean(missing.unwrap_or(false)));
+                }
+                Opcode::ReturnValue => {
+                    let value = self.pop()?;
+                    let frame = self.frames.pop().expect("vm always has a frame");
+                    if self.frames.is_empty() {
+                        return Ok(Object::Return(Box::new(value)));
+                    }
+                    self.stack.truncate(frame.base);
+                    self.push(value);
+                }
+                Opcode::Return => {
+                    let frame = self.frames.pop().expect("vm always has a frame");
+                    if self.frames.is_empty() {
+                        return Ok(Object::Return(Box::new(Object::Null)));
+                    }
+                    self.stack.truncate(frame.base);
+                    self.push(Object::Null);
+                }
+                Opcode::Closure => {
+                    let index = self.read_u16_operand();
+                    let function = match &self.constants[index] {
+                        Object::CompiledFunction(f) => Rc::clone(f),
+                        other => return Err(anyhow!("Not a function: {}", other.inspect())),
+                    };
+                    let frame = self.current_frame();
+                    let free = function
+                        .captures
+                        .iter()
+                        .map(|capture| match capture {
+                            Capture::Local(i) => Rc::clone(&frame.locals[*i]),
+                            Capture::Free(i) => Rc::clone(&frame.closure.free[*i]),
+                        })
+                        .collect();
+                    self.push(Object::Closure(Closure::new(function, free)));
+                }
+                Opcode::MatchLiteral => {
+                    let literal = self.pop()?;
+                    let value = self.pop()?;
+                    let matches = Object::matches_literal(&literal, &value);
+                    self.push(Object::Boolean(matches));
+                }
+                Opcode::MatchArray => {
+                    let len = self.read_u16_operand();
+                    let rest = self.read_u8_operand() == 1;
+                    let matches = match self.pop()? {
+                        Object::Array(items) => items.len() == len || (rest && items.len() > len),
+                        _ => false,
+                    };
+                    self.push(Object::Boolean(matches));
+                }
+                Opcode::MatchHash => {
+                    let matches = matches!(self.pop()?, Object::Hash(_));
+                    self.push(Object::Boolean(matches));
+                }
+                Opcode::MatchKey => {
+                    let key = HashKey::try_from(&self.pop()?)?;
+                    let matches = match self.pop()? {
+                        Object::Hash(pairs) => pairs.contains_key(&key),
+                        _ => false,
+                    };
+                    self.push(Object::Boolean(matches));
+                }
+                Opcode::SliceFrom => {
+                    let start = self.read_u16_operand();
+                    match self.pop()? {
+                        Object::Array(items) => self.push(Object::Array(
+                            items.get(start..).unwrap_or_default().to_vec(),
+                        )),
+                        other => return Err(anyhow!("Not an array: {}", other.inspect())),
+                    }
+                }
+                Opcode::NoMatch => {
+                    let value = self.pop()?;
+                    return Err(anyhow!("No match arm for {}", value.inspect()));
+                }
+                Opcode::MismatchArray => {
+                    let len = self.read_u16_operand();
+                    let rest = self.read_u8_operand() == 1;
+                    let got = self.pop()?;
+                    return Err(self.destructure_error(Mismatch::Array { len, rest, got }));
+                }
+                Opcode::MismatchHash => {
+                    let got = self.pop()?;
+                    return Err(self.destructure_error(Mismatch::Hash(got)));
+                }
+                Opcode::MismatchKey => {
+                    let key = self.pop()?;
+                    return Err(self.destructure_error(Mismatch::MissingKey(key)));
+                }
+                Opcode::MismatchLiteral => {
+                    let got = self.pop()?;
+                    let expected = self.pop()?;
+                    return Err(self.destructure_error(Mismatch::Literal { expected, got }));
+                }
+            }
+        }
+
+        Ok(self.last_popped.clone())
+    }
+
+    /// Calls the function below the `num_args` arguments on the stack. The last of the
+    /// arguments are keyword arguments, one for each of `keywords`.
+    fn call(&mut self, num_args: usize, keywords: Vec<String>) -> Result<()> {
+        let mut args = self.pop_many(num_args)?;
+        let keyword_values = args.split_off(num_args - keywords.len());
+        let keyword_args: Vec<(String, Object)> =
+            keywords.into_iter().zip(keyword_values).collect();
+        let callee = self.pop()?;
+        match callee {
+            Object::Closure(closure) => {
+                let (locals, missing_arguments) =
+                    bind_arguments(&closure.function, args, keyword_args)?;
+                if self.frames.len() >= MAX_FRAMES {
+                    return Err(anyhow!(
+                        "Stack overflow: more than {} nested calls",
+                        MAX_FRAMES
+                    ));
+                }
+                let mut frame = Frame::new(closure, locals, self.stack.len());
+                frame.missing_arguments = missing_arguments;
+                self.frames.push(frame);
+                Ok(())
+            }
+            Object::BuiltinFunction(_) if !keyword_args.is_empty() => {
+                Err(anyhow!("Builtin functions do not take keyword arguments"))
+            }
+            Object::BuiltinFunction(bf) => {
+                let result = bf(Some(args))?;
+                self.push(result);
+                Ok(())
+            }
+            _ => Err(anyhow!("Not a function: {}", callee.inspect())),
+        }
+    }
+
+    /// The source position of the instruction the current frame is executing, if the
+    /// compiler recorded one.
+    fn current_span(&self) -> Option<Span> {
+        let frame = self.frames.last()?;
+        let positions = &frame.closure.function.positions;
+        positions
+            .binary_search_by_key(&frame.instruction_start, |(offset, _)| *offset)
+            .ok()
+            .map(|i| positions[i].1)
+    }
+
+    fn current_frame(&self) -> &Frame {
+        self.frames.last().expect("vm always has a frame")
+    }
+
+    fn current_frame_mut(&mut self) -> &mut Frame {
+        self.frames.last_mut().expect("vm always has a frame")
+    }
+
+    /// The error for a destructuring let whose value, on top of the stack, did not match.
+    fn destructure_error(&mut self, mismatch: Mismatch) -> anyhow::Error {
+        match self.pop() {
+            Ok(value) => anyhow!("Cannot destructure {}: {}", value.inspect(), mismatch),
+            Err(e) => e,
+        }
+    }
+
+    fn read_u16_operand(&mut self) -> usize {
+        let frame = self.current_frame_mut();
+        let operand = read_u16(&frame.closure.function.instructions[frame.ip..]);
+        frame.ip += 2;
+        operand
+    }
+
+    fn read_u8_operand(&mut self) -> usize {
+        let frame = self.current_frame_mut();
+        let operand = frame.closure.function.instructions[frame.ip] as usize;
+        frame.ip += 1;
+        operand
+    }
+
+    fn push(&mut self, object: Object) {
+        self.stack.push(object);
+    }
+
+    fn pop(&mut self) -> Result<Object> {
+        self.stack.pop().ok_or_else(|| anyhow!("Stack underflow"))
+    }
+
+    fn pop_many(&mut self, count: usize) -> Result<Vec<Object>> {
+        if count > self.stack.len() {
+            return Err(anyhow!("Stack underflow"));
+        }
+        Ok(self.stack.split_off(self.stack.len() - count))
+    }
+}
+
+/// Arranges the arguments of a call into the locals of `function`, as the tree walker's
+/// `bind_arguments` does: positional arguments fill the parameters in order and any left
+/// over go to the rest parameter, then keyword arguments fill parameters by name. Also
+/// returns which parameters are still missing, for their defaults to fill in.
+fn bind_arguments(
+    function: &CompiledFunction,
+    args: Vec<Object>,
+    keyword_args: Vec<(String, Object)>,
+) -> Result<(Locals, Vec<bool>)> {
+    let name = function.name.as_deref().unwrap_or("anonymous function");
+    let num_parameters = function.parameters.len();
+    let required = num_parameters - function.num_defaults;
+    let num_args = args.len();
+    let wrong_number = || {
+        anyhow!(
+            "Wrong number of arguments for {}: expected {}, got {}",
+            name,
+            expected_arguments(required, num_parameters, function.variadic),
+            num_args
+        )
+    };
+    if num_args > num_parameters && !function.variadic {
+        return Err(wrong_number());
+    }
+
+    let mut args = args.into_iter();
+    let mut values: Vec<Option<Object>> = (0..num_parameters).map(|_| args.next()).collect();
+    let extra: Vec<Object> = args.collect();
+
+    let has_keywords = !keyword_args.is_empty();
+    for (keyword, value) in keyword_args.into_iter() {
+        let Some(i) = function.parameters.iter().position(|p| *p == keyword) else {
+            return Err(anyhow!(
+                "Unknown keyword argument for {}: {}",
+                name,
+                keyword
+            ));
+        };
+        if values[i].is_some() {
+            return Err(anyhow!("Argument given twice for {}: {}", name, keyword));
+        }
+        values[i] = Some(value);
+    }
+
+    let mut locals: Locals = Vec::with_capacity(function.num_locals);
+    let mut missing: Vec<bool> = vec![];
+    for (i, value) in values.into_iter().enumerate() {
+        if value.is_none() && i < required {
+            return Err(match has_keywords {
+                true => anyhow!("Missing argument for {}: {}", name, function.parameters[i]),
+                false => wrong_number(),
+            });
+        }
+        if function.num_defaults > 0 {
+            missing.push(value.is_none());
+        }
+        locals.push(Rc::new(RefCell::new(value.unwrap_or(Object::Null))));
+    }
+    if function.variadic {
+        locals.push(Rc::new(RefCell::new(Object::Array(extra))));
+    }
+    locals.resize_with(function.num_locals, Default::default);
+    Ok((locals, missing))
+}
+
+/// The operator token evaluated by a binary opcode, so the vm shares the tree walker's
+/// operator semantics and error messages.
+fn infix_token(opcode: Opcode) -> Token {
+    match opcode {
+        Opcode::Add => Token::Plus,
+        Opcode::Sub => Token::Minus,
+        Opcode::Mul => Token::Asterisk,
+        Opcode::Div => Token::Slash,
+        Opcode::Equal => Token::Equal,
+        Opcode::NotEqual => Token::NotEqual,
+        Opcode::GreaterThan => Token::GreaterThan,
+        Opcode::LessThan => Token::LessThan,
+        Opcode::GreaterEqual => Token::GreaterEqual,
+        Opcode::LessEqual => Token::LessEqual,
+        Opcode::Mod => Token::Percent,
+        Opcode::Pow => Token::Power,
+        Opcode::BitAnd => Token::Ampersand,
+        Opcode::BitOr => Token::Pipe,
+        Opcode::BitXor => Token::Caret,
+        Opcode::ShiftLeft => Token::ShiftLeft,
+        Opcode::ShiftRight => Token::ShiftRight,
+        _ => Token::Illegal,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{
+        compiler::compiler::Compiler,
+        lexer::lexer::Lexer,
+        object::object::{Environment, HashKey, Object},
+        parser::parser::Parser,
+    };
+
+    use super::Vm;
+
+    struct Test {
+        input: &'static str,
+        expected: Object,
+    }
+
+    fn run(input: &str) -> Object {
+        let lex = Lexer::new(input.into());
+        let mut parser = Parser::new(lex);
+        let program = parser.parse_program();
+        let mut compiler = Compiler::new();
+        let mut vm = Vm::new();
+        // compile errors are reported the same way as runtime errors, like the repl does
+        match compiler
+            .compile(&program)
+            .and_then(|bytecode| vm.run(bytecode))
+        {
+            Ok(object) => object,
+            Err(e) => Object::Error(e.to_string()),
+        }
+    }
+
+    fn run_tests(tests: Vec<Test>) {
+        for test in tests.into_iter() {
+            assert_eq!(run(test.input), test.expected, "input: {}", test.input);
+        }
+    }
+
+    #[test]
+    fn test_integer_arithmetic() {
+        run_tests(vec![
+            Test {
+                input: "1",
+                expected: Object::Integer(1),
+            },
+            Test {
+                input: "1 + 2 * 3 - 4 / 2",
+                expected: Object::Integer(5),
+            },
+            Test {
+                input: "-5 + 10",
+                expected: Object::Integer(5),
+            },
+            Test {
+                input: "(5 + 10 * 2 + 15 / 3) * 2 + -10",
+                expected: Object::Integer(50),
+            },
+        ]);
+    }
+
+    #[test]
+    fn test_boolean_expressions() {
+        run_tests(vec![
+            Test {
+                input: "1 < 2",
+                expected: Object::Boolean(true),
+            },
+            Test {
+                input: "1 > 2 == false",
+                expected: Object::Boolean(true),
+            },
+            Test {
+                input: "!true != !!true",
+                expected: Object::Boolean(true),
+            },
+            Test {
+                input: "!(if (false) { 5; })",
+                expected: Object::Boolean(true),
+            },
+        ]);
+    }
+
+    #[test]
+    fn test_conditionals() {
+        run_tests(vec![
+            Test {
+                input: "if (1 < 2) { 10 } else { 20 }",
+                expected: Object::Integer(10),
+            },
+            Test {
+                input: "if (1 > 2) { 10 } else { 20 }",
+                expected: Object::Integer(20),
+            },
+            Test {
+                input: "if (false) { 10 }",
+                expected: Object::Null,
+            },
+            Test {
+                input: "if (10 > 1) { if (10 > 1) { return 10; } return 1; }",
+                expected: Object::Return(Box::new(Object::Integer(10))),
+            },
+        ]);
+    }
+
+    #[test]
+    fn test_let_statements() {
+        run_tests(vec![
+            Test {
+                input: "let one = 1; let two = one + one; one + two",
+                expected: Object::Integer(3),
+            },
+            Test {
+                input: "let a = 5; let b = a; let c = a + b + 5;",
+                expected: Object::Integer(15),
+            },
+            Test {
+                input: "let x = 100; for (x in [1, 2]) {}; x",
+                expected: Object::Integer(100),
+            },
+            Test {
+                input: "let f = fn() { let x = 5; for (x in [1, 2]) { let x = x * 10; } x }; f()",
+                expected: Object::Integer(5),
+            },
+        ]);
+    }
+
+    #[test]
+    fn test_collections() {
+        let mut hash: HashMap<HashKey, Object> = HashMap::new();
+        hash.insert(HashKey::Integer(2), Object::Integer(4));
+        hash.insert(HashKey::String(String::from("a")), Object::Integer(6));
+        run_tests(vec![
+            Test {
+                input: "[1, 2 * 2, 3 + 3]",
+                expected: Object::Array(vec![
+                    Object::Integer(1),
+                    Object::Integer(4),
+                    Object::Integer(6),
+                ]),
+            },
+            Test {
+                input: r#"{1 + 1: 2 * 2, "a": 3 + 3}"#,
+                expected: Object::Hash(hash),
+            },
+            Test {
+                input: "[1, 2, 3][1 + 1]",
+                expected: Object::Integer(3),
+            },
+            Test {
+                input: r#"{"foo": 5}["bar"]"#,
+                expected: Object::Null,
+            },
+        ]);
+    }
+
+    #[test]
+    fn test_functions() {
+        run_tests(vec![
+            Test {
+                input: "let identity = fn(x) { x }; identity(5);",
+                expected: Object::Integer(5),
+            },
+            Test {
+                input: "let add = fn(x, y) { x + y; }; add(5 + 5, add(5, 5));",
+                expected: Object::Integer(20),
+            },
+            Test {
+                input: "let early = fn() { return 99; 100; }; early();",
+                expected: Object::Integer(99),
+            },
+            Test {
+                input: "let noReturn = fn() { }; noReturn();",
+                expected: Object::Null,
+            },
+            Test {
+                input: "let f = fn() { let a = 1; }; f();",
+                expected: Object::Integer(1),
+            },
+            Test {
+                input: "let f = fn(a) { let b = 2; let c = a + b; c * 2 }; f(1) + f(2);",
+                expected: Object::Integer(14),
+            },
+            Test {
+                input: r#"len("four")"#,
+                expected: Object::Integer(4),
+            },
+        ]);
+    }
+
+    #[test]
+    fn test_closures_and_recursion() {
+        run_tests(vec![
+            Test {
+                input: "let newAdder = fn(x) { fn(y) { x + y } }; let addTwo = newAdder(2); addTwo(2);",
+                expected: Object::Integer(4),
+            },
+            Test {
+                input: "let a = 1; let f = fn(b) { fn(c) { fn(d) { a + b + c + d } } }; f(2)(3)(4);",
+                expected: Object::Integer(10),
+            },
+            Test {
+                input: "let fib = fn(n) { if (n < 2) { return n; } fib(n - 1) + fib(n - 2) }; fib(15);",
+                expected: Object::Integer(610),
+            },
+            Test {
+                input: "let wrapper = fn() { let countDown = fn(x) { if (x == 0) { return 0; } countDown(x - 1) }; countDown(3) }; wrapper();",
+                expected: Object::Integer(0),
+            },
+            Test {
+                input: "let isEven = fn(n) { if (n == 0) { true } else { isOdd(n - 1) } };
+                let isOdd = fn(n) { if (n == 0) { false } else { isEven(n - 1) } };
+                isEven(10);",
+                expected: Object::Boolean(true),
+            },
+            Test {
+                input: "let getX = fn() { x }; let x = 5; getX();",
+                expected: Object::Integer(5),
+            },
+        ]);
+    }
+
+    #[test]
+    fn test_errors() {
+        run_tests(vec![
+            Test {
+                input: "5 + true; 5;",
+                expected: Object::Error(String::from(
+                    "line 1, column 3: Type mismatch: 5 Plus true",
+                )),
+            },
+            Test {
+                input: "foobar",
+                expected: Object::Error(String::from(
+                    "line 1, column 1: Identifier not found: foobar",
+                )),
+            },
+            Test {
+                input: "let add = fn(x, y) { x + y }; add(1);",
+                expected: Object::Error(String::from(
+                    "line 1, column 31: Wrong number of arguments for add: expected 2, got 1",
+                )),
+            },
+            Test {
+                input: "len();",
+                expected: Object::Error(String::from(
+                    "line 1, column 1: Wrong number of arguments for len: expected 1, got 0",
+                )),
+            },
+            Test {
+                input: "[1, 2][2]",
+                expected: Object::Error(String::from(
+                    "line 1, column 7: Index out of bounds: index 2 but length is 2",
+                )),
+            },
+            Test {
+                input: "let a = 1; a();",
+                expected: Object::Error(String::from("line 1, column 12: Not a function: 1")),
+            },
+            Test {
+                input: "let f = fn() { f() }; f();",
+                expected: Object::Error(String::from(
+                    "line 1, column 16: Stack overflow: more than 10000 nested calls",
+                )),
+            },
+        ]);
+    }
+
+    #[test]
+    fn test_operand_limits() {
+        // distinct identifiers, since identifiers cannot contain digits
+        let names = |count: usize, item: &str| -> String {
+            let items: Vec<String> = (0..count)
+                .map(|i| {
+                    let name: String = format!("{:o}", i)
+                        .bytes()
+                        .map(|digit| (digit - b'0' + b'a') as char)
+                        .collect();
+                    item.replace("{name}", &name).replace("{}", &i.to_string())
+                })
+                .collect();
+            items.join(" ")
+        };
+        let tests = vec![
+            (
+                format!("let f = fn(...xs) {{ xs[254] }}; f({})", vec!["7"; 255].join(", ")),
+                Object::Integer(7),
+            ),
+            (
+                format!("let f = fn(...xs) {{ xs[254] }}; f({})", vec!["7"; 256].join(", ")),
+                Object::Error(String::from(
+                    "line 1, column 32: Too many arguments in a call, the limit is 255",
+                )),
+            ),
+            (
+                format!("fn() {{ {} a }}()", names(256, "let {name} = 1;")),
+                Object::Integer(1),
+            ),
+            (
+                format!("fn() {{ {} a }}()", names(257, "let {name} = 1;")),
+                Object::Error(String::from(
+                    "line 1, column 3264: Too many local bindings in one function, the limit is 256",
+                )),
+            ),
+            (names(65536, "{};"), Object::Integer(65535)),
+            (
+                names(65537, "{};"),
+                Object::Error(String::from(
+                    "Too many constants, the limit is 65536",
+                )),
+            ),
+            (
+                format!("let x = 0; if (true) {{ {} }} x", vec!["x += 1;"; 4000].join(" ")),
+                Object::Integer(4000),
+            ),
+            (
+                format!("let x = 0; if (true) {{ {} }} x", vec!["x += 1;"; 5000].join(" ")),
+                Object::Error(String::from(
+                    "Too many bytes of instructions in one function, the limit is 65536",
+                )),
+            ),
+        ];
+
+        for (input, expected) in tests.into_iter() {
+            assert_eq!(run(&input), expected, "input: {:.40}", input);
+        }
+    }
+
+    #[test]
+    fn test_match() {
+        run_tests(vec![
+            Test {
+                input: r#"match (2) { 1 => "one", 2 => "two", _ => "many" }"#,
+                expected: Object::String(String::from("two")),
+            },
+            Test {
+                input:
+                    "match ([1, [2, 3], 4]) { [a] => a, [a, [b, c], ...rest] => [a + b + c, rest] }",
+                expected: Object::Array(vec![
+                    Object::Integer(6),
+                    Object::Array(vec![Object::Integer(4)]),
+                ]),
+            },
+            Test {
+                input: r#"let f = fn(x) { match (x) { {"k": v} if (v > 1) => v, {k} => -k, _ => 0 } }; [f({"k": 2}), f({"k": 1}), f(3)]"#,
+                expected: Object::Array(vec![
+                    Object::Integer(2),
+                    Object::Integer(-1),
+                    Object::Integer(0),
+                ]),
+            },
+            Test {
+                input: "let x = 10; match (1) { x => x }; x",
+                expected: Object::Integer(10),
+            },
+            Test {
+                input: "match (5) { 1 => 1 }",
+                expected: Object::Error(String::from("line 1, column 1: No match arm for 5")),
+            },
+        ]);
+    }
+
+    #[test]
+    fn test_globals_persist_between_runs() {
+        let mut compiler = Compiler::new();
+        let mut vm = Vm::new();
+        let mut result = Object::Null;
+        for input in ["let a = 2;", "let double = fn(x) { x * 2 };", "double(a)"] {
+            let lex = Lexer::new(input.into());
+            let mut parser = Parser::new(lex);
+            let program = parser.parse_program();
+            let bytecode = compiler.compile(&program).unwrap();
+            result = vm.run(bytecode).unwrap();
+        }
+        assert_eq!(result, Object::Integer(4));
+    }
+
+    #[test]
+    fn test_matches_tree_walker() {
+        let inputs = [
+            "let a = [1, 2, 3]; a[0] + a[2]",
+            "if (1 > 2) { 1 }",
+            "return 2 * 5; 9;",
+            "let f = fn(x) { if (x > 2) { return x; } f(x + 1) }; f(0);",
+            r#"{"a": [1, 2]}["a"][1]"#,
+            r#"let n = 2; "${n} * ${n} = ${n * n}, ${[n]} ${"s"}""#,
+            "1 + true",
+            "[7 % 3, 2 ** 3 ** 2, 3 <= 3, 4 >= 5, 6 & 3 | 8, 6 ^ 3, 1 << 4 >> 1, 2.0 ** 2]",
+            "1 << -1",
+            "let i = 0; let s = 0; while (i < 5) { i += 1; if (i == 2) { continue; } s += i; } s",
+            "let s = 0; for (x in [1, 2, 3]) { s += 1 + if (x == 2) { continue; } else { 0 }; } s",
+            "let s = 0; let i = 0; while (i < 5) { i += 1; s += [1, i, if (i == 3) { break; } else { 2 }][1]; } [s, i]",
+            "let n = 0; for (x in [1, 2]) { n += {x: if (x == 1) { continue; } else { x }}[x]; } n",
+            "let f = fn(a, b) { a + b }; let s = 0; for (x in [1, 2, 3]) { s += f(x, if (x == 2) { break; } else { x }); } s",
+            "let out = []; for (x in [1, 2, 3]) { for (y in \"ab\") { if (x == 2) { break; } let out = out + 1; } }",
+            "let f = fn(xs) { let n = 0; for (x in xs) { if (x > 2) { return n; } n += x; } n }; [f([1, 2, 3, 4]), f([]), f({2: 1, 1: 2})]",
+            "let i = 0; while (true) { i += 1; if (i > 3) { break; } }; i",
+            "let i = 0; while (i < 2) { let y = i; i += 1; } y",
+            "let x = 100; for (x in [1, 2]) { let y = x; }; [x, y]",
+            "let s = 0; for (x in [1, 2]) { let t = x; for (y in [10]) { let t = t + y; s += t; } s += t; } s",
+            "let f = fn() { let s = 0; for (x in [1, 2]) { let y = x * 10; s += y; } [s, x] }; f()",
+            "for (x in 5) { x }",
+            "let x = 1; x += 2; x *= 5; x -= 1; x /= 2; x %= 4; [x, x = 9, x]",
+            "let n = 0; let counter = fn() { let step = 2; fn() { n += step; step += 1; n } }; let c = counter(); [c(), c(), n]",
+            "let f = fn(a) { let b = a; b = b * 2; a += b; [a, b] }; f(3)",
+            "let i = 0; let sum = 0; while (i < 4) { i += 1; sum += i; } sum",
+            "let f = fn() { later = 1 }; let later = 0; f(); later",
+            "let f = fn() { missing = 1 }; f()",
+            "y += 1",
+            "let x = 1; x /= 0",
+            "const x = 1; let f = fn() { let x = 2; x }; f() + x",
+            "const x = 1; let f = fn() { x = 2 }; f()",
+            "const x = 1; x -= 1",
+            "const x = 1; let x = 2",
+            "let f = fn() { const y = 1; let g = fn() { y = 2 }; g() }; f()",
+            "const x = 1; for (x in [2]) { const y = x; }; x",
+            "let x = 1; const x = 2; x",
+            "let sign = fn(x) { if (x < 0) { -1 } else if (x == 0) { 0 } else { 1 } }; [sign(-5), sign(0), sign(5)]",
+            "let f = fn(x) { if (x == 1) { return 10; } else if (x == 2) { return 20; } 30 }; [f(1), f(2), f(3)]",
+            "if (false) { 1 } else if (false) { 2 }",
+            "[true && 0, false && missing, 1 > 2 || \"\", false || if (false) { 1 }]",
+            "let f = fn(n) { n > 0 && f(n - 1) }; f(5) || f(1 / 0)",
+            "let f = fn(x) { 10 / x }; f(0)",
+            "9223372036854775807 * 2",
+            "let ratio = 3 / 4.0; [ratio * 100, int(ratio * 100), -ratio, 2 > ratio]",
+            "let f = fn() { let a = [1, if (true) { return 5; }]; 7 }; f()",
+            r#"let f = fn(x) { let h = {"a": if (x) { return 1; } else { 2 }}; h["a"] + 10 }; [f(true), f(false)]"#,
+            "let f = fn(x) { 1 + if (x) { return 2; } else { 3 } }; [f(true), f(false)]",
+            r#"let f = fn() { str(if (true) { return "r"; }) + "s" }; f()"#,
+            "let f = fn() { -if (true) { return 4; } }; f()",
+            "let f = fn(x, y = 10, z = x + y) { [x, y, z] }; [f(1), f(1, 2), f(1, 2, 3)]",
+            "let f = fn(first, ...others) { [first, others, len(others)] }; [f(1), f(1, 2, 3)]",
+            "let f = fn(x = 1, ...rest) { let n = 2; [x, rest, n] }; [f(), f(5, 6)]",
+            "let f = fn(x, y = 2) { x + y }; f()",
+            "let f = fn(x, y = 2) { x + y }; f(1, 2, 3)",
+            "let f = fn(x, ...r) { x }; f()",
+            "let f = fn(x = 1 / 0) { x }; f()",
+            "let k = 3; let f = fn(x = k) { fn() { x } }; f()()",
+            "let f = fn(x, y = 1, z = 2) { [x, y, z] }; [f(z: 5, x: 0), f(1, z: 3), f(y: 4, x: 1)]",
+            "let f = fn(x, y = x * 2, z = y + 1) { [x, y, z] }; [f(1, z: 0), f(x: 2)]",
+            "let f = fn(x, y = 1) { y }; f(1, y: null)",
+            "let f = fn(x, y) { x - y }; f(1, y: 3)",
+            "fn fact(n) { if (n < 2) { 1 } else { n * fact(n - 1) } } let g = fact; g(1, 2)",
+            "let f = fn(x, ...r) { [x, r] }; f(x: 1)",
+            "let f = fn(x, y) { x }; f(1, z: 2)",
+            "let f = fn(x, y) { x }; f(1, x: 2)",
+            "let f = fn(x, y) { x }; f(y: 2)",
+            "let f = fn(x, y = 1) { x }; f(1, 2, y: 3)",
+            "let f = fn(x, y = 1) { x }; f(1, 2, 3, y: 3)",
+            "let f = fn(x, ...r) { x }; f(1, r: 2)",
+            "len(s: \"abc\")",
+            "let f = fn(a, b) { fn(c = a) { [b, c] } }; [f(1, b: 2)(), f(b: 3, a: 4)(c: 5)]",
+            "fn f(n, acc = 0) { if (n == 0) { acc } else { f(n - 1, acc: acc + n) } } f(10)",
+            "fn fib(n) { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } } fib(10)",
+            "fn outer(x) { fn inner(y) { x + y } inner(2) } outer(1)",
+            "fn f(a) { a } f(1, 2)",
+            "const f = 1; fn f() { 2 }",
+            "fn add(x, y) { x + y } [1 |> add(2) |> add(3) |> str, \"ab\" |> len]",
+            "let twice = fn(f, x) { f(f(x)) }; fn(x) { x + 1 } |> twice(3)",
+            "1 |> len",
+            "let f = fn(x) { fn(y) { fn(z) { x * 100 + y * 10 + z } } }; 3 |> f(1)(2)",
+            "let add = fn(x) { fn(y) { x - y } }; [1 |> (add(3)), 1 |> (add)(3)]",
+            r#"match (2) { 1 => "one", 2 => "two", _ => "many" }"#,
+            r#"match (-1.5) { -1.5 => "neg", _ => "other" }"#,
+            r#"match ("b") { "a" => 1, "b" => 2 }"#,
+            "match (true) { false => 0, true => 1 }",
+            "[match (7) { n if (n > 5) => n * 2, n => n }, match (3) { n if (n > 5) => n * 2, n => n }]",
+            "match ([1, 2, 3]) { [] => 0, [x] => x, [x, y] => x + y, [x, ...rest] => rest }",
+            "match ([1]) { [x, y, ..._] => 0, [_, ..._] => 1 }",
+            "match ([1, [2, 3]]) { [a, [b, c]] => a + b + c }",
+            r#"match ({"name": "ann", "age": 30}) { {"age": 31} => "", {"age": 30, name} => name }"#,
+            r#"match ({"name": "bob"}) { {age} => age, {name: [n]} => n, {name: n} => n }"#,
+            "let count = 0; match (5) { n => count += n }; count",
+            r#"let classify = fn(x) { match (x) { 0 => "zero", n if (n < 0) => "negative", _ => "positive" } }; [classify(0), classify(-3), classify(4)]"#,
+            "match (5) { n if (n + true) => 1 }",
+            r#"match (1.0) { 1 => "int", _ => "other" }"#,
+            r#"match (1) { "1" => "string", true => "bool", 1.0 => "float" }"#,
+            r#"let f = fn(x) { match (x) { 0 => { return "z" }, n => { let m = n * 2; m } }; "after" }; [f(0), f(2)]"#,
+            "match (3) { n => { let m = n * 2; m + 1 } }",
+            "let f = fn(xs) { let n = 0; for (x in xs) { match (x) { 0 => { continue }, 9 => { break }, x => { n += x } } } n }; f([1, 0, 2, 9, 5])",
+            "match ({}) { {k: [a, ...b]} => 1, {} => 2 }",
+            "let f = fn() { match ([1]) { [x] => fn() { x } } }; f()()",
+            "match ([]) { [...xs] => xs }",
+            "match ({[1]: 2}) { _ => 1 }",
+            "match ({1: 2}) { {[1]: x} => x }",
+            "let [a, b, ...rest] = [1, 2, 3, 4]; [a, b, rest]",
+            "let [a, ...rest] = [1]; rest",
+            r#"let {"pos": [x, y], name: n} = {"pos": [3, 4], "name": "p"}; [x * y, n]"#,
+            "let [_, second] = [1, 2]; second",
+            "let f = fn(pair) { let [a, b] = pair; a - b }; f([5, 3])",
+            "let f = fn(pair) { let [a, b] = pair; fn() { a * b } }; f([5, 3])()",
+            "const [a] = [1]; a = 2",
+            "const a = 1; let [a] = [2]",
+            "let [a, b] = [1, 2, 3]",
+            r#"let {name} = {"age": 1}"#,
+            "let [a] = 5",
+            "let [a, ...rest] = []",
+            r#"let [x, {k}] = [1, {"j": 2}]"#,
+            "let [x, {k}] = [1, [2]]",
+            "let [1, y] = [2, 3]",
+            "let [-1.5, y] = [-1.5, 3]; y",
+            "let {[1]: a} = {1: 2}; a",
+            "for (p in [[1, 2], [3, 4]]) { let [a, b] = p; a + b }",
+        ];
+        for input in inputs.iter() {
+            let lex = Lexer::new(input.to_string().into());
+            let mut parser = Parser::new(lex);
+            let program = parser.parse_program();
+            let env = Environment::new();
+            let evaluated = Object::eval(program.statements, &env);
+            assert_eq!(run(input), evaluated, "input: {}", input);
+        }
+    }
+}